@@ -1,11 +1,15 @@
 use serde::de::DeserializeOwned as SeDeserializeOwned;
 use serde::Serialize as SeSerialize;
 use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::prelude::*;
-use std::net::{IpAddr, SocketAddr};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
 
-use crate::primitives::consts::{P2P_DEFAULT_SOCKET, RPC_DEFAULT_SOCKET};
+use crate::p2p::TransportType;
+use crate::primitives::consts::{
+    P2P_DEFAULT_FRAGMENT_SIZE, P2P_DEFAULT_LAN_MULTICAST, P2P_DEFAULT_SOCKET, RPC_DEFAULT_SOCKET,
+};
 use crate::primitives::types::{GroupID, PeerAddr as NodeAddr};
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -42,6 +46,8 @@ pub struct ConfigureRow {
     upper_address: Socket,
     lower_address: Socket,
     bootstrap_peers: Vec<PeerAddr>,
+    #[serde(default)]
+    p2p_config: P2PConfig,
 }
 
 impl ConfigureRow {
@@ -62,10 +68,72 @@ impl ConfigureRow {
             upper_address,
             lower_address,
             bootstrap_peers,
+            self.p2p_config.clone(),
         )
     }
 }
 
+/// p2p network options, in config.toml is `[p2p_config]`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct P2PConfig {
+    /// max bytes of one udp datagram before path mtu probed.
+    pub fragment_size: usize,
+    /// transport used when connect to new peer, `udp` or `tcp`.
+    pub transport: TransportType,
+    /// listen tcp on p2p socket, so peers can connect by tcp.
+    pub tcp: bool,
+    /// max inbound messages per second from one peer.
+    pub peer_message_rate: u32,
+    /// max inbound bytes per second from one peer.
+    pub peer_byte_rate: u32,
+    /// max inbound messages per second in one group.
+    pub group_message_rate: u32,
+    /// max inbound bytes per second in one group.
+    pub group_byte_rate: u32,
+    /// peer throttled times in one minute before disconnect it.
+    pub throttle_limit: u32,
+    /// forward messages for peers which hole punching fail.
+    pub relay: bool,
+    /// max peers can reserve relay in self.
+    pub relay_reservations: u32,
+    /// max relayed bytes per second of one reservation.
+    pub relay_byte_rate: u32,
+    /// leading zero bits of identity puzzle peers must solve to join, 0 is disabled, max 16.
+    pub puzzle_difficulty: u8,
+    /// identity puzzle difficulty of special groups, key is group id.
+    pub group_puzzle_difficulty: HashMap<String, u8>,
+    /// announce self and find peers in local network by udp multicast.
+    pub lan_discovery: bool,
+    /// multicast group and port of local network discovery.
+    pub lan_multicast: SocketAddrV4,
+    /// interface joined multicast group, `0.0.0.0` is chosen by system.
+    pub lan_interface: Ipv4Addr,
+}
+
+impl Default for P2PConfig {
+    fn default() -> Self {
+        P2PConfig {
+            fragment_size: P2P_DEFAULT_FRAGMENT_SIZE,
+            transport: TransportType::UDP,
+            tcp: false,
+            peer_message_rate: 1000,
+            peer_byte_rate: 4 * 1024 * 1024,
+            group_message_rate: 10000,
+            group_byte_rate: 32 * 1024 * 1024,
+            throttle_limit: 100,
+            relay: true,
+            relay_reservations: 32,
+            relay_byte_rate: 512 * 1024,
+            puzzle_difficulty: 0,
+            group_puzzle_difficulty: HashMap::new(),
+            lan_discovery: false,
+            lan_multicast: P2P_DEFAULT_LAN_MULTICAST.parse().unwrap(),
+            lan_interface: Ipv4Addr::UNSPECIFIED,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Configure {
     pub current_group: GroupID,
@@ -75,6 +143,7 @@ pub struct Configure {
     pub upper_address: SocketAddr,
     pub lower_address: SocketAddr,
     pub bootstrap_peers: Vec<(NodeAddr, SocketAddr)>,
+    pub p2p_config: P2PConfig,
 }
 
 impl Configure {
@@ -86,6 +155,7 @@ impl Configure {
         upper_address: SocketAddr,
         lower_address: SocketAddr,
         bootstrap_peers: Vec<(NodeAddr, SocketAddr)>,
+        p2p_config: P2PConfig,
     ) -> Self {
         Configure {
             current_group,
@@ -95,6 +165,7 @@ impl Configure {
             upper_address,
             lower_address,
             bootstrap_peers,
+            p2p_config,
         }
     }
 
@@ -106,6 +177,7 @@ impl Configure {
         let current_group = GroupID::from_str("0x00000000000000000000000000000000").unwrap();
         let upper_group = GroupID::from_str("0x00000000000000000000000000000000").unwrap();
         let bootstrap_peers = vec![];
+        let p2p_config = Default::default();
 
         Configure {
             current_group,
@@ -115,6 +187,7 @@ impl Configure {
             upper_address,
             lower_address,
             bootstrap_peers,
+            p2p_config,
         }
     }
 
@@ -0,0 +1,102 @@
+use chacha20poly1305::aead::generic_array::GenericArray;
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::XChaCha20Poly1305;
+use rand::rngs::OsRng;
+use serde_derive::{Deserialize, Serialize};
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use x25519_dalek::{PublicKey as XPublicKey, StaticSecret};
+
+use super::hash::H256;
+
+pub const EXCHANGE_KEY_LENGTH: usize = 32;
+pub const SYMMETRIC_KEY_LENGTH: usize = 32;
+pub const NONCE_LENGTH: usize = 24;
+
+/// x25519 public key, send to other side for key agreement.
+#[derive(Default, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ExchangeKey {
+    value: [u8; EXCHANGE_KEY_LENGTH],
+}
+
+/// x25519 secret key, only use once for one session key agreement,
+/// so the session keys have forward secrecy.
+#[derive(Clone)]
+pub struct EphemeralKey {
+    secret: StaticSecret,
+    public: ExchangeKey,
+}
+
+/// XChaCha20-Poly1305 key, use random nonce for every message.
+#[derive(Clone)]
+pub struct SymmetricKey {
+    value: [u8; SYMMETRIC_KEY_LENGTH],
+}
+
+impl ExchangeKey {
+    pub fn to_bytes(&self) -> [u8; EXCHANGE_KEY_LENGTH] {
+        self.value
+    }
+}
+
+impl EphemeralKey {
+    pub fn generate() -> EphemeralKey {
+        let mut csprng: OsRng = OsRng::new().unwrap();
+        let secret = StaticSecret::new(&mut csprng);
+        let public = ExchangeKey {
+            value: *XPublicKey::from(&secret).as_bytes(),
+        };
+
+        EphemeralKey { secret, public }
+    }
+
+    pub fn public_key(&self) -> &ExchangeKey {
+        &self.public
+    }
+
+    /// diffie hellman with other side's public key, return the hashed shared secret.
+    pub fn exchange(&self, other: &ExchangeKey) -> H256 {
+        let shared = self.secret.diffie_hellman(&XPublicKey::from(other.value));
+        H256::new(shared.as_bytes())
+    }
+}
+
+impl SymmetricKey {
+    pub fn from_hash(hash: &H256) -> SymmetricKey {
+        SymmetricKey {
+            value: hash.to_bytes(),
+        }
+    }
+
+    /// encrypt bytes, return nonce + ciphertext.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, ()> {
+        let cipher = XChaCha20Poly1305::new(GenericArray::from_slice(&self.value));
+        let nonce: [u8; NONCE_LENGTH] = rand::random();
+        let mut bytes = nonce.to_vec();
+        bytes.append(
+            &mut cipher
+                .encrypt(GenericArray::from_slice(&nonce), plaintext)
+                .map_err(|_| ())?,
+        );
+        Ok(bytes)
+    }
+
+    /// decrypt nonce + ciphertext bytes, fail if it is modified.
+    pub fn decrypt(&self, bytes: &[u8]) -> Result<Vec<u8>, ()> {
+        if bytes.len() < NONCE_LENGTH {
+            return Err(());
+        }
+        let cipher = XChaCha20Poly1305::new(GenericArray::from_slice(&self.value));
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LENGTH);
+        cipher
+            .decrypt(GenericArray::from_slice(nonce), ciphertext)
+            .map_err(|_| ())
+    }
+}
+
+impl Debug for ExchangeKey {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let mut hex = String::new();
+        hex.extend(self.value.iter().map(|byte| format!("{:02x?}", byte)));
+        write!(f, "0x{}", hex)
+    }
+}
@@ -1,2 +1,3 @@
+pub mod cipher;
 pub mod hash;
 pub mod keypair;
@@ -1,5 +1,3 @@
-#![feature(vec_drain_as_slice)]
-
 use std::net::SocketAddr;
 
 mod config;
@@ -16,9 +14,10 @@ pub mod traits;
 use actor::prelude::{Actor, Addr, System, SystemRunner};
 use crypto::keypair::PrivateKey;
 use p2p::p2p_start;
+use primitives::types::{GroupID, PeerAddr};
 use rpc::rpc_start;
 
-pub use config::Configure;
+pub use config::{Configure, P2PConfig};
 pub use network_bridge::NetworkBridgeActor;
 
 pub fn system_init() -> SystemRunner {
@@ -29,12 +28,23 @@ pub fn system_run(runner: SystemRunner) {
     let _ = runner.run();
 }
 
+/// start p2p and rpc network, the groups (configured current and upper group)
+/// are bootstrapped by the bootstrap peers at once.
 pub fn network_start(
     p2p_socket: SocketAddr,
     rpc_socket: SocketAddr,
     psk: Option<PrivateKey>,
+    p2p_config: P2PConfig,
+    bootstrap_peers: Vec<(PeerAddr, SocketAddr)>,
+    bootstrap_groups: Vec<GroupID>,
 ) -> Addr<NetworkBridgeActor> {
-    let p2p_addr = p2p_start::<NetworkBridgeActor>(p2p_socket, psk);
+    let p2p_addr = p2p_start::<NetworkBridgeActor>(
+        p2p_socket,
+        psk,
+        p2p_config,
+        bootstrap_peers,
+        bootstrap_groups,
+    );
     let rpc_addr = rpc_start::<NetworkBridgeActor>(rpc_socket);
 
     NetworkBridgeActor::create(|ctx| {
@@ -17,9 +17,16 @@ struct MultipleRecipient {
     lower_groups: Vec<GroupID>,
 
     recipient_event: Recipient<EventMessage>,
+    recipient_event_fail: Recipient<EventFailMessage>,
+    recipient_broadcast: Recipient<BroadcastMessage>,
     recipient_peer_join: Recipient<PeerJoinMessage>,
     recipient_peer_join_result: Recipient<PeerJoinResultMessage>,
     recipient_peer_leave: Recipient<PeerLeaveMessage>,
+    recipient_peer_version: Recipient<PeerVersionMessage>,
+    recipient_dht_value: Recipient<DHTValueMessage>,
+    recipient_provider: Recipient<ProviderMessage>,
+    recipient_peer_misbehave: Recipient<PeerMisbehaveMessage>,
+    recipient_bootstrapped: Recipient<BootstrappedMessage>,
 
     recipient_local: Recipient<LocalMessage>,
     recipient_upper: Recipient<UpperMessage>,
@@ -100,9 +107,16 @@ impl<B: BridgeActor> Handler<RegisterBridgeMessage<B>> for NetworkBridgeActor {
             lower_groups: Vec::new(),
 
             recipient_event: addr.clone().recipient::<EventMessage>(),
+            recipient_event_fail: addr.clone().recipient::<EventFailMessage>(),
+            recipient_broadcast: addr.clone().recipient::<BroadcastMessage>(),
             recipient_peer_join: addr.clone().recipient::<PeerJoinMessage>(),
             recipient_peer_join_result: addr.clone().recipient::<PeerJoinResultMessage>(),
             recipient_peer_leave: addr.clone().recipient::<PeerLeaveMessage>(),
+            recipient_peer_version: addr.clone().recipient::<PeerVersionMessage>(),
+            recipient_dht_value: addr.clone().recipient::<DHTValueMessage>(),
+            recipient_provider: addr.clone().recipient::<ProviderMessage>(),
+            recipient_peer_misbehave: addr.clone().recipient::<PeerMisbehaveMessage>(),
+            recipient_bootstrapped: addr.clone().recipient::<BootstrappedMessage>(),
 
             recipient_local: addr.clone().recipient::<LocalMessage>(),
             recipient_upper: addr.clone().recipient::<UpperMessage>(),
@@ -205,6 +219,40 @@ impl Handler<EventMessage> for NetworkBridgeActor {
     }
 }
 
+/// receive reliable event message from bridge actor, and send to p2p
+impl Handler<ReliableEventMessage> for NetworkBridgeActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReliableEventMessage, _ctx: &mut Self::Context) {
+        self.send_p2p(ReceiveReliableEventMessage(msg.0, msg.1, msg.2));
+    }
+}
+
+/// event fail message is only sent to bridge actor.
+impl Handler<EventFailMessage> for NetworkBridgeActor {
+    type Result = ();
+
+    fn handle(&mut self, _msg: EventFailMessage, _ctx: &mut Self::Context) {}
+}
+
+/// receive event cancel message from bridge actor, and send to p2p
+impl Handler<EventCancelMessage> for NetworkBridgeActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: EventCancelMessage, _ctx: &mut Self::Context) {
+        self.send_p2p(ReceiveEventCancelMessage(msg.0, msg.1));
+    }
+}
+
+/// receive broadcast message from bridge actor, and send to p2p
+impl Handler<BroadcastMessage> for NetworkBridgeActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: BroadcastMessage, _ctx: &mut Self::Context) {
+        self.send_p2p(ReceiveBroadcastMessage(msg.0, msg.1, msg.2));
+    }
+}
+
 /// receive peer join message from bridge actor, and send to p2p
 impl Handler<PeerJoinMessage> for NetworkBridgeActor {
     type Result = ();
@@ -232,6 +280,60 @@ impl Handler<PeerLeaveMessage> for NetworkBridgeActor {
     }
 }
 
+/// receive peer version query from bridge actor, and send to p2p
+impl Handler<PeerVersionMessage> for NetworkBridgeActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: PeerVersionMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.send_p2p(ReceivePeerVersionMessage(msg.0, msg.1, msg.2, msg.3));
+    }
+}
+
+/// receive dht put or get from bridge actor, and send to p2p
+impl Handler<DHTValueMessage> for NetworkBridgeActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: DHTValueMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.send_p2p(ReceiveDHTValueMessage(msg.0, msg.1, msg.2, msg.3));
+    }
+}
+
+/// receive content provide or find from bridge actor, and send to p2p
+impl Handler<ProviderMessage> for NetworkBridgeActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: ProviderMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.send_p2p(ReceiveProviderMessage(msg.0, msg.1, msg.2, msg.3));
+    }
+}
+
+/// receive peer misbehaviour from bridge actor, and send to p2p
+impl Handler<PeerMisbehaveMessage> for NetworkBridgeActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: PeerMisbehaveMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.send_p2p(ReceivePeerMisbehaveMessage(msg.0, msg.1, msg.2, msg.3));
+    }
+}
+
+/// receive group bootstrap from bridge actor, and send to p2p
+impl Handler<BootstrapMessage> for NetworkBridgeActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: BootstrapMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.send_p2p(ReceiveBootstrapMessage(msg.0, msg.1));
+    }
+}
+
+/// receive group bootstrapped query from bridge actor, and send to p2p
+impl Handler<BootstrappedMessage> for NetworkBridgeActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: BootstrappedMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.send_p2p(ReceiveBootstrappedMessage(msg.0));
+    }
+}
+
 /// impl RPCBridgeActor for NetworkBridgeActor {}
 impl P2PBridgeActor for NetworkBridgeActor {}
 
@@ -250,6 +352,55 @@ impl Handler<ReceiveEventMessage> for NetworkBridgeActor {
     }
 }
 
+/// receive reliable event message from p2p actor, and send to bridge
+impl Handler<ReceiveReliableEventMessage> for NetworkBridgeActor {
+    type Result = ();
+
+    fn handle(
+        &mut self,
+        msg: ReceiveReliableEventMessage,
+        _ctx: &mut Self::Context,
+    ) -> Self::Result {
+        self.bridges.get(&msg.0).and_then(|group| {
+            Some(
+                group
+                    .recipient_event
+                    .do_send(EventMessage(msg.0, msg.1, msg.2)),
+            )
+        });
+    }
+}
+
+/// receive reliable event fail message from p2p actor, and send to bridge
+impl Handler<ReceiveEventFailMessage> for NetworkBridgeActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReceiveEventFailMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.bridges.get(&msg.0).and_then(|group| {
+            Some(
+                group
+                    .recipient_event_fail
+                    .do_send(EventFailMessage(msg.0, msg.1, msg.2)),
+            )
+        });
+    }
+}
+
+/// receive broadcast message from p2p actor, and send to bridge
+impl Handler<ReceiveBroadcastMessage> for NetworkBridgeActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReceiveBroadcastMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.bridges.get(&msg.0).and_then(|group| {
+            Some(
+                group
+                    .recipient_broadcast
+                    .do_send(BroadcastMessage(msg.0, msg.1, msg.2)),
+            )
+        });
+    }
+}
+
 /// receive peer join message from p2p actor, and send to bridge
 impl Handler<ReceivePeerJoinMessage> for NetworkBridgeActor {
     type Result = ();
@@ -299,6 +450,89 @@ impl Handler<ReceivePeerLeaveMessage> for NetworkBridgeActor {
     }
 }
 
+/// receive peer version from p2p actor, and send to bridge
+impl Handler<ReceivePeerVersionMessage> for NetworkBridgeActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReceivePeerVersionMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.bridges.get(&msg.0).and_then(|group| {
+            Some(
+                group
+                    .recipient_peer_version
+                    .do_send(PeerVersionMessage(msg.0, msg.1, msg.2, msg.3)),
+            )
+        });
+    }
+}
+
+/// receive dht value from p2p actor, and send to bridge
+impl Handler<ReceiveDHTValueMessage> for NetworkBridgeActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReceiveDHTValueMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.bridges.get(&msg.0).and_then(|group| {
+            Some(
+                group
+                    .recipient_dht_value
+                    .do_send(DHTValueMessage(msg.0, msg.1, msg.2, msg.3)),
+            )
+        });
+    }
+}
+
+/// receive content providers from p2p actor, and send to bridge
+impl Handler<ReceiveProviderMessage> for NetworkBridgeActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReceiveProviderMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.bridges.get(&msg.0).and_then(|group| {
+            Some(
+                group
+                    .recipient_provider
+                    .do_send(ProviderMessage(msg.0, msg.1, msg.2, msg.3)),
+            )
+        });
+    }
+}
+
+/// receive peer banned from p2p actor, and send to bridge
+impl Handler<ReceivePeerMisbehaveMessage> for NetworkBridgeActor {
+    type Result = ();
+
+    fn handle(
+        &mut self,
+        msg: ReceivePeerMisbehaveMessage,
+        _ctx: &mut Self::Context,
+    ) -> Self::Result {
+        self.bridges.get(&msg.0).and_then(|group| {
+            Some(
+                group
+                    .recipient_peer_misbehave
+                    .do_send(PeerMisbehaveMessage(msg.0, msg.1, msg.2, msg.3)),
+            )
+        });
+    }
+}
+
+/// receive group bootstrapped from p2p actor, and send to bridge
+impl Handler<ReceiveBootstrappedMessage> for NetworkBridgeActor {
+    type Result = ();
+
+    fn handle(
+        &mut self,
+        msg: ReceiveBootstrappedMessage,
+        _ctx: &mut Self::Context,
+    ) -> Self::Result {
+        self.bridges.get(&msg.0).and_then(|group| {
+            Some(
+                group
+                    .recipient_bootstrapped
+                    .do_send(BootstrappedMessage(msg.0)),
+            )
+        });
+    }
+}
+
 /// impl RPCBridgeActor for NetworkBridgeActor
 impl RPCBridgeActor for NetworkBridgeActor {}
 
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use crate::crypto::keypair::PublicKey;
+use crate::primitives::types::{GroupID, PeerInfoByte};
+
+/// group table has peers less than this, need bootstrap again.
+const MIN_PEERS: usize = 3;
+
+/// first retry time of bootstrap, every retry will double it.
+const INITIAL_BACKOFF: u64 = 2;
+
+/// max retry time of bootstrap.
+const MAX_BACKOFF: u64 = 300;
+
+#[derive(Clone)]
+struct BootstrapState {
+    join: PeerInfoByte,
+    backoff: u64,
+    next: Instant,
+    done: bool,
+}
+
+/// bootstrap groups by the configured seeds, retry with backoff until the table
+/// has enough peers, and bootstrap again when the peers is too few.
+#[derive(Clone, Default)]
+pub(crate) struct Bootstrap {
+    seeds: Vec<(PublicKey, SocketAddr)>,
+    groups: HashMap<GroupID, BootstrapState>,
+}
+
+impl Bootstrap {
+    pub fn new(seeds: Vec<(PublicKey, SocketAddr)>) -> Self {
+        Bootstrap {
+            seeds,
+            groups: HashMap::new(),
+        }
+    }
+
+    pub fn seeds(&self) -> &Vec<(PublicKey, SocketAddr)> {
+        &self.seeds
+    }
+
+    /// join info of the group bootstrapping.
+    pub fn join(&self, group: &GroupID) -> Option<PeerInfoByte> {
+        self.groups.get(group).map(|state| state.join.clone())
+    }
+
+    /// the group table has enough peers.
+    pub fn is_done(&self, group: &GroupID) -> bool {
+        self.groups
+            .get(group)
+            .map(|state| state.done)
+            .unwrap_or(false)
+    }
+
+    /// start bootstrap group with the join info, the join info is used when retry.
+    pub fn start(&mut self, group: GroupID, join: PeerInfoByte) {
+        self.groups.insert(
+            group,
+            BootstrapState {
+                join,
+                backoff: INITIAL_BACKOFF,
+                next: Instant::now(),
+                done: false,
+            },
+        );
+    }
+
+    /// check every group by the peers number of table,
+    /// return the groups need join seeds now, and the groups bootstrapped.
+    pub fn next<F: Fn(&GroupID) -> usize>(
+        &mut self,
+        peers: F,
+    ) -> (Vec<(GroupID, PeerInfoByte)>, Vec<GroupID>) {
+        let now = Instant::now();
+        let mut joins = vec![];
+        let mut dones = vec![];
+
+        for (group, state) in self.groups.iter_mut() {
+            if peers(group) >= MIN_PEERS {
+                if !state.done {
+                    state.done = true;
+                    state.backoff = INITIAL_BACKOFF;
+                    dones.push(group.clone());
+                }
+                continue;
+            }
+
+            if state.done {
+                println!("DEBUG: group peers too few, bootstrap again");
+                state.done = false;
+                state.next = now;
+            }
+
+            if now >= state.next {
+                state.next = now + Duration::new(state.backoff, 0);
+                state.backoff = (state.backoff * 2).min(MAX_BACKOFF);
+                joins.push((group.clone(), state.join.clone()));
+            }
+        }
+
+        (joins, dones)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::keypair::PrivateKey;
+
+    #[test]
+    fn test_bootstrap_backoff() {
+        let seed = PrivateKey::generate().generate_public_key();
+        let socket: SocketAddr = "127.0.0.1:7364".parse().unwrap();
+        let group: GroupID = Default::default();
+        let mut bootstrap = Bootstrap::new(vec![(seed, socket)]);
+        assert_eq!(bootstrap.next(|_| 0), (vec![], vec![]));
+
+        bootstrap.start(group.clone(), vec![1]);
+        assert_eq!(
+            bootstrap.next(|_| 0),
+            (vec![(group.clone(), vec![1])], vec![])
+        );
+        assert_eq!(bootstrap.next(|_| 1), (vec![], vec![]));
+
+        assert_eq!(bootstrap.next(|_| MIN_PEERS), (vec![], vec![group.clone()]));
+        assert_eq!(bootstrap.next(|_| MIN_PEERS), (vec![], vec![]));
+
+        // too few peers, bootstrap again at once
+        assert_eq!(
+            bootstrap.next(|_| 1),
+            (vec![(group.clone(), vec![1])], vec![])
+        );
+    }
+}
@@ -1,5 +1,8 @@
 use byteorder::{BigEndian, ByteOrder};
+use bytes::{BufMut, BytesMut};
 use serde_derive::{Deserialize, Serialize};
+use std::io::{Error, ErrorKind};
+use tokio::codec::{Decoder, Encoder};
 
 use crate::crypto::keypair::{PublicKey, Signature, PUBLIC_KEY_LENGTH, SIGNATURE_LENGTH};
 use crate::primitives::types::GroupID;
@@ -11,6 +14,9 @@ pub const HEAD_LENGTH: usize =
 const BEFORE_TO_LENGTH: usize = 4 + 2 + 32 + PUBLIC_KEY_LENGTH;
 const BEFORE_SIGN_LENGTH: usize = 4 + 2 + 32 + PUBLIC_KEY_LENGTH + PUBLIC_KEY_LENGTH;
 
+/// max body length of one p2p message in stream.
+const MAX_BODY_LENGTH: usize = 16 * 1024 * 1024;
+
 #[derive(Default, Clone, Debug)]
 pub struct P2PHead {
     pub len: u32,        //[u8; 4]
@@ -60,6 +66,19 @@ impl P2PHead {
         self.sign = sign;
     }
 
+    /// the bytes need signed: version, group, from, to and body bytes.
+    pub fn sign_data(&self, body: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&self.encode()[4..BEFORE_SIGN_LENGTH]);
+        bytes.extend_from_slice(body);
+        bytes
+    }
+
+    /// check the signature is signed by from's private key.
+    pub fn verify(&self, body: &[u8]) -> bool {
+        self.from.verify_bytes(&self.sign_data(body), &self.sign)
+    }
+
     pub fn encode(&self) -> [u8; HEAD_LENGTH] {
         let mut bytes = [0u8; HEAD_LENGTH];
         BigEndian::write_u32(&mut bytes, self.len);
@@ -110,3 +129,157 @@ impl P2PHead {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(bound = "")]
 pub struct P2PBody(pub P2PContent);
+
+/// length-prefixed codec for stream transport, use P2PHead's len as length.
+#[derive(Default)]
+pub struct P2PCodec;
+
+impl Decoder for P2PCodec {
+    type Item = (P2PHead, P2PContent);
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if src.len() < HEAD_LENGTH {
+            return Ok(None);
+        }
+
+        let head = P2PHead::decode(&src[..HEAD_LENGTH]);
+        let size = head.len as usize;
+        if size > MAX_BODY_LENGTH {
+            return Err(Error::new(ErrorKind::InvalidData, "p2p body too large"));
+        }
+
+        if src.len() < HEAD_LENGTH + size {
+            src.reserve(HEAD_LENGTH + size - src.len());
+            return Ok(None);
+        }
+
+        let bytes = src.split_to(HEAD_LENGTH + size);
+        let content = bincode::deserialize(&bytes[HEAD_LENGTH..]).unwrap_or(P2PContent::None);
+        Ok(Some((head, content)))
+    }
+}
+
+impl Encoder for P2PCodec {
+    type Item = (P2PHead, P2PBody);
+    type Error = Error;
+
+    fn encode(&mut self, msg: Self::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let (mut head, body) = msg;
+        let body_bytes: Vec<u8> = bincode::serialize(&body).unwrap_or(vec![]);
+        head.update_len(body_bytes.len() as u32);
+
+        dst.reserve(HEAD_LENGTH + body_bytes.len());
+        dst.put_slice(&head.encode());
+        dst.put_slice(&body_bytes);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::keypair::PrivateKey;
+
+    #[test]
+    fn test_head_sign_verify() {
+        let psk = PrivateKey::generate();
+        let to = PrivateKey::generate().generate_public_key();
+        let gid = GroupID::new(b"group");
+        let body = vec![1u8, 2, 3];
+
+        let mut head = P2PHead::new(1, gid, psk.generate_public_key(), to);
+        head.update_signature(psk.sign_bytes(&head.sign_data(&body)));
+        let head = P2PHead::decode(&head.encode());
+        assert!(head.verify(&body));
+
+        let mut changed = head.clone();
+        changed.gid = GroupID::new(b"other group");
+        assert!(!changed.verify(&body));
+
+        let mut changed = head.clone();
+        changed.to = PrivateKey::generate().generate_public_key();
+        assert!(!changed.verify(&body));
+
+        let mut changed = head.clone();
+        changed.ver = 2;
+        assert!(!changed.verify(&body));
+
+        assert!(!head.verify(&vec![1u8, 2, 4]));
+    }
+
+    #[test]
+    fn test_codec_framing() {
+        let from = PrivateKey::generate().generate_public_key();
+        let to = PrivateKey::generate().generate_public_key();
+        let head = P2PHead::new(1, GroupID::new(b"group"), from, to);
+
+        let mut codec = P2PCodec;
+        let mut buf = BytesMut::new();
+        codec
+            .encode((head.clone(), P2PBody(P2PContent::None)), &mut buf)
+            .unwrap();
+        let frame = buf.clone();
+        codec
+            .encode((head.clone(), P2PBody(P2PContent::None)), &mut buf)
+            .unwrap();
+
+        // partial frame wait for more bytes
+        let mut partial = BytesMut::from(&frame[..frame.len() - 1]);
+        assert!(codec.decode(&mut partial).unwrap().is_none());
+        let mut partial = BytesMut::from(&frame[..HEAD_LENGTH - 1]);
+        assert!(codec.decode(&mut partial).unwrap().is_none());
+
+        // two frames in one buffer
+        let (h1, _) = codec.decode(&mut buf).unwrap().unwrap();
+        let (h2, _) = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(h1.from, head.from);
+        assert_eq!(h2.to, head.to);
+        assert!(buf.is_empty());
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+
+        // too large body length is error
+        let mut large = head.clone();
+        large.update_len(MAX_BODY_LENGTH as u32 + 1);
+        let mut buf = BytesMut::from(&large.encode()[..]);
+        assert!(codec.decode(&mut buf).is_err());
+    }
+
+    #[test]
+    fn test_codec_nested_route() {
+        let from = PrivateKey::generate().generate_public_key();
+        let to = PrivateKey::generate().generate_public_key();
+        let mut head = P2PHead::new(1, GroupID::new(b"group"), from, to);
+
+        // route prefix is variant, hops and empty head, repeat it as deep nesting.
+        let prefix = bincode::serialize(&P2PContent::Route(1, vec![], vec![])).unwrap();
+        let mut body = vec![];
+        for _ in 0..100_000 {
+            body.extend_from_slice(&prefix[..prefix.len() - 8]);
+        }
+        body.extend_from_slice(&bincode::serialize(&P2PContent::None).unwrap());
+        head.update_len(body.len() as u32);
+
+        let mut codec = P2PCodec;
+        let mut buf = BytesMut::from(&head.encode()[..]);
+        buf.extend_from_slice(&body);
+        let (_, content) = codec.decode(&mut buf).unwrap().unwrap();
+        match content {
+            P2PContent::Route(_, _, inner) => assert!(inner.len() < body.len()),
+            P2PContent::None => {}
+            _ => panic!("nested route decode to other content"),
+        }
+
+        // origin message is opaque bytes in route
+        let inner = bincode::serialize(&P2PBody(P2PContent::HeartBeat)).unwrap();
+        let route = P2PContent::Route(3, head.encode().to_vec(), inner.clone());
+        let mut buf = BytesMut::new();
+        codec
+            .encode((head.clone(), P2PBody(route)), &mut buf)
+            .unwrap();
+        match codec.decode(&mut buf).unwrap().unwrap().1 {
+            P2PContent::Route(3, _, bytes) => assert_eq!(bytes, inner),
+            _ => panic!("route decode fail"),
+        }
+    }
+}
@@ -1,9 +1,15 @@
 use serde_derive::{Deserialize, Serialize};
 use std::net::SocketAddr;
 
+use crate::crypto::cipher::ExchangeKey;
+use crate::crypto::hash::H256;
 use crate::crypto::keypair::PublicKey;
 use crate::primitives::types::{EventByte, PeerInfoByte};
 
+use super::dht::{DHTRecord, MemberUpdate};
+use super::gossip::GossipItem;
+use super::pex::PexRecord;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(bound = "")]
 pub enum P2PContent {
@@ -23,4 +29,172 @@ pub enum P2PContent {
 
     /// need send to network bridge - Event
     Event(EventByte),
+
+    /// secure session handshake, params is ephemeral exchange key, nonce and timestamp
+    Handshake(ExchangeKey, u64, u64),
+
+    /// handshake answer, params is ephemeral exchange key, nonce and initiator's nonce
+    HandshakeOk(ExchangeKey, u64, u64),
+
+    /// encrypted content by session key, params is session key id and ciphertext
+    Sealed([u8; 8], Vec<u8>),
+
+    /// reliable event, params is channel id, sequence number and event
+    Reliable(u64, u64, EventByte),
+    ReliableAck(u64, u64),
+
+    /// path mtu probe, params is datagram size and padding
+    MtuProbe(u32, Vec<u8>),
+    MtuProbeOk(u32),
+
+    /// version negotiation, params is min version, max version, features and nonce,
+    /// reject params is the nonce of negotiation and reason
+    Version(u16, u16, u32, u64),
+    VersionOk(u16, u16, u32),
+    VersionReject(u64, String),
+
+    /// group broadcast, eager push full message, lazy push and pull message ids
+    Gossip(GossipItem),
+    GossipIHave(Vec<H256>),
+    GossipIWant(Vec<H256>),
+
+    /// routed message to not connected peer, params is hops left,
+    /// encoded head and encoded body of origin message, body decode after hops check.
+    Route(u8, Vec<u8>, Vec<u8>),
+
+    /// reserve relay in peer, when hole punching fail
+    RelayReserve,
+
+    /// relay accept reservation, params is keep time (seconds)
+    RelayReserveOk(u32),
+
+    /// relay reject reservation, params is reason
+    RelayReserveReject(String),
+
+    /// message forward by relay, params is encoded head and encoded body of origin message
+    Relay(Vec<u8>, Vec<u8>),
+
+    /// ask peer echo the source address
+    Observe,
+
+    /// source address peer observed
+    Observed(SocketAddr),
+
+    /// ask peer send probe to the peer in its table, for check NAT type
+    ObserveHelp(PublicKey),
+
+    /// probe from peer never connected
+    ObserveProbe,
+
+    /// find the k closest peers to the key, params is request id and key
+    FindNode(u64, H256),
+
+    /// the closest peers self known, params is request id and peers
+    FindNodeOk(u64, Vec<(PublicKey, SocketAddr)>),
+
+    /// store the record in the closest peer, params is key and record
+    Store(H256, DHTRecord),
+
+    /// find the record of key, params is request id and key
+    FindValue(u64, H256),
+
+    /// the record if stored, or the closest peers, params is request id, record and peers
+    FindValueOk(u64, Option<DHTRecord>, Vec<(PublicKey, SocketAddr)>),
+
+    /// announce self hold the content, params is content hash
+    AddProvider(H256),
+
+    /// find the content providers, params is request id and content hash
+    GetProviders(u64, H256),
+
+    /// the providers self known and the closest peers, params is request id, providers and peers,
+    /// if responder provide it, the address is the responder advertised address.
+    GetProvidersOk(
+        u64,
+        Vec<(PublicKey, SocketAddr)>,
+        Vec<(PublicKey, SocketAddr)>,
+    ),
+
+    /// failure detector probe, params is seq and piggybacked membership updates
+    Ping(u64, Vec<MemberUpdate>),
+
+    /// probe answer, params is seq and piggybacked membership updates
+    Ack(u64, Vec<MemberUpdate>),
+
+    /// ask to ping target and forward the ack,
+    /// params is seq, target, target socket and piggybacked membership updates
+    PingReq(u64, PublicKey, SocketAddr, Vec<MemberUpdate>),
+
+    /// peer exchange, params is need answer and the recent peers of self.
+    Pex(bool, Vec<PexRecord>),
+}
+
+impl P2PContent {
+    /// content used for version negotiation
+    pub fn is_version(&self) -> bool {
+        match self {
+            P2PContent::Version(..) | P2PContent::VersionOk(..) | P2PContent::VersionReject(..) => {
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// content can forward by other peers, the content change peers table can not.
+    pub fn can_route(&self) -> bool {
+        match self {
+            P2PContent::HeartBeat
+            | P2PContent::HeartBeatOk
+            | P2PContent::DHT(_)
+            | P2PContent::Hole(_, _)
+            | P2PContent::HolePunching
+            | P2PContent::HolePunchingOk
+            | P2PContent::Leave
+            | P2PContent::Join(_)
+            | P2PContent::MtuProbe(_, _)
+            | P2PContent::MtuProbeOk(_)
+            | P2PContent::Route(_, _, _)
+            | P2PContent::RelayReserve
+            | P2PContent::RelayReserveOk(_)
+            | P2PContent::RelayReserveReject(_)
+            | P2PContent::Relay(_, _)
+            | P2PContent::Observe
+            | P2PContent::Observed(_)
+            | P2PContent::ObserveHelp(_)
+            | P2PContent::ObserveProbe
+            | P2PContent::FindNode(_, _)
+            | P2PContent::FindNodeOk(_, _)
+            | P2PContent::Store(_, _)
+            | P2PContent::FindValue(_, _)
+            | P2PContent::FindValueOk(_, _, _)
+            | P2PContent::AddProvider(_)
+            | P2PContent::GetProviders(_, _)
+            | P2PContent::GetProvidersOk(_, _, _)
+            | P2PContent::Ping(_, _)
+            | P2PContent::Ack(_, _)
+            | P2PContent::PingReq(_, _, _, _)
+            | P2PContent::Pex(_, _) => false,
+            _ => true,
+        }
+    }
+
+    /// content must be encrypted by secure session, only the content used
+    /// before session established or not carry data can be sent plain.
+    pub fn need_seal(&self) -> bool {
+        match self {
+            P2PContent::None
+            | P2PContent::Handshake(..)
+            | P2PContent::HandshakeOk(..)
+            | P2PContent::Sealed(_, _)
+            | P2PContent::HolePunching
+            | P2PContent::HolePunchingOk
+            | P2PContent::ObserveProbe
+            | P2PContent::MtuProbe(_, _)
+            | P2PContent::MtuProbeOk(_)
+            | P2PContent::Version(..)
+            | P2PContent::VersionOk(..)
+            | P2PContent::VersionReject(..) => false,
+            _ => true,
+        }
+    }
 }
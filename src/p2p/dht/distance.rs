@@ -1,34 +1,40 @@
 use serde_derive::{Deserialize, Serialize};
 use std::cmp::Ordering;
 
-/// DHT Distance use 160 bit (20bytes)
+use crate::crypto::hash::H256;
+use crate::crypto::keypair::PublicKey;
+
+/// DHT id of peer, the hash of public key, so peer can not choose its id.
+pub(crate) fn node_id(pk: &PublicKey) -> H256 {
+    H256::new(&pk.to_bytes())
+}
+
+/// DHT Distance use 256 bit (32bytes), big endian
 #[derive(Debug, Clone, Serialize, Deserialize, Eq)]
-pub(crate) struct Distance([u8; 20]);
+pub(crate) struct Distance([u8; 32]);
 
 impl Distance {
+    /// bits of distance.
+    pub const BITS: usize = 256;
+
+    /// xor of two ids, short id is filled with zero at the end.
     pub fn distance(from: &[u8], base: &[u8]) -> Self {
-        let from_len = from.len();
-        let base_len = base.len();
-
-        let (len, mut hold) = if from_len >= 20 && base_len >= 20 {
-            (0, [0; 20])
-        } else if from_len > base_len {
-            let mut hold = [0; 20];
-            hold[0..base_len].copy_from_slice(&from[base_len..]);
-            (base_len, hold)
-        } else {
-            let mut hold = [0; 20];
-            hold[0..base_len].copy_from_slice(&from[base_len..]);
-            (from_len, hold)
-        };
-
-        hold[len..20].copy_from_slice(
-            &((0..(20usize - len))
-                .map(|i| from.get(i).unwrap() ^ base.get(i).unwrap())
-                .collect::<Vec<u8>>()[..]),
-        );
+        let mut hold = [0u8; 32];
+        for i in 0..32 {
+            hold[i] = from.get(i).unwrap_or(&0) ^ base.get(i).unwrap_or(&0);
+        }
         Distance(hold)
     }
+
+    /// leading zero bits of distance, all zero is BITS.
+    pub fn leading_zeros(&self) -> usize {
+        for (i, byte) in self.0.iter().enumerate() {
+            if *byte != 0 {
+                return i * 8 + byte.leading_zeros() as usize;
+            }
+        }
+        Self::BITS
+    }
 }
 
 impl Default for Distance {
@@ -55,3 +61,66 @@ impl PartialEq for Distance {
         self.0 == other.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn id() -> impl Strategy<Value = [u8; 32]> {
+        proptest::array::uniform32(any::<u8>())
+    }
+
+    proptest! {
+        #[test]
+        fn prop_distance_xor(a in id(), b in id(), c in id()) {
+            let (ab, ba) = (Distance::distance(&a, &b), Distance::distance(&b, &a));
+            prop_assert_eq!(&ab, &ba);
+            prop_assert_eq!(ab == Distance::default(), a == b);
+            prop_assert_eq!(Distance::distance(&a, &a).leading_zeros(), Distance::BITS);
+
+            // d(a, c) = d(a, b) ^ d(b, c)
+            let ac = Distance::distance(&a, &c);
+            let bc = Distance::distance(&b, &c);
+            prop_assert_eq!(ac, Distance::distance(&ab.0, &bc.0));
+        }
+
+        #[test]
+        fn prop_distance_leading_zeros(a in id(), b in id()) {
+            let d = Distance::distance(&a, &b);
+            let zeros = d.leading_zeros();
+            prop_assume!(zeros < Distance::BITS);
+
+            // highest set bit is at index BITS - 1 - zeros
+            let (byte, bit) = (zeros / 8, 7 - zeros % 8);
+            prop_assert!(d.0[..byte].iter().all(|b| *b == 0));
+            prop_assert_eq!(d.0[byte] >> bit, 1);
+        }
+
+        #[test]
+        fn prop_distance_order(a in id(), b in id(), c in id()) {
+            let (ab, ac) = (Distance::distance(&a, &b), Distance::distance(&a, &c));
+            if ab.leading_zeros() > ac.leading_zeros() {
+                prop_assert!(ab < ac);
+            }
+
+            // same order as 256 bit big endian integer
+            let int = |d: &Distance| {
+                let (mut hi, mut lo) = ([0u8; 16], [0u8; 16]);
+                hi.copy_from_slice(&d.0[..16]);
+                lo.copy_from_slice(&d.0[16..]);
+                (u128::from_be_bytes(hi), u128::from_be_bytes(lo))
+            };
+            prop_assert_eq!(ab.cmp(&ac), int(&ab).cmp(&int(&ac)));
+            prop_assert_eq!(ab == ac, b == c);
+        }
+    }
+
+    #[test]
+    fn test_distance_short_id() {
+        let d = Distance::distance(&[0xff], &[0x0f, 0x01]);
+        assert_eq!(d.0[0], 0xf0);
+        assert_eq!(d.0[1], 0x01);
+        assert_eq!(d.leading_zeros(), 0);
+    }
+}
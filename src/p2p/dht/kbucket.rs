@@ -0,0 +1,250 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use crate::crypto::hash::H256;
+use crate::crypto::keypair::PublicKey;
+use crate::primitives::consts::K_BUCKET;
+
+use super::distance::{node_id, Distance};
+
+/// bucket not refreshed in this time, need lookup a random key in it.
+const REFRESH_TIME: u64 = 600;
+
+/// result of insert peer to k-buckets.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Insert {
+    /// new peer in bucket.
+    Inserted,
+    /// peer had in bucket, update socket and move to tail.
+    Updated,
+    /// bucket is full, peer wait in replacement cache,
+    /// the least recently seen peer need ping, if not alive, replace it.
+    Full(PublicKey, SocketAddr),
+    /// self or invalid peer.
+    Ignored,
+}
+
+/// k peers of one distance prefix, head is least recently seen.
+#[derive(Serialize, Deserialize, Clone, Default)]
+struct KBucket {
+    peers: VecDeque<(PublicKey, SocketAddr)>,
+    replacements: VecDeque<(PublicKey, SocketAddr)>,
+}
+
+impl KBucket {
+    fn position(&self, pk: &PublicKey) -> Option<usize> {
+        self.peers.iter().position(|(p, _)| p == pk)
+    }
+
+    fn insert(&mut self, pk: &PublicKey, socket: SocketAddr) -> Insert {
+        if let Some(index) = self.position(pk) {
+            self.peers.remove(index);
+            self.peers.push_back((pk.clone(), socket));
+            return Insert::Updated;
+        }
+
+        if self.peers.len() < K_BUCKET {
+            self.peers.push_back((pk.clone(), socket));
+            return Insert::Inserted;
+        }
+
+        self.replacements.retain(|(p, _)| p != pk);
+        if self.replacements.len() >= K_BUCKET {
+            self.replacements.pop_front();
+        }
+        self.replacements.push_back((pk.clone(), socket));
+
+        let (lru, lru_socket) = self.peers.front().cloned().unwrap();
+        Insert::Full(lru, lru_socket)
+    }
+
+    /// remove peer, the most recently seen replacement will fill it.
+    fn remove(&mut self, pk: &PublicKey) -> bool {
+        self.replacements.retain(|(p, _)| p != pk);
+        if let Some(index) = self.position(pk) {
+            self.peers.remove(index);
+            if let Some(replacement) = self.replacements.pop_back() {
+                self.peers.push_back(replacement);
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    fn seen(&mut self, pk: &PublicKey) {
+        if let Some(index) = self.position(pk) {
+            if let Some(peer) = self.peers.remove(index) {
+                self.peers.push_back(peer);
+            }
+        }
+    }
+}
+
+/// kademlia routing table, peers in k-buckets by xor distance prefix to self.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct KBuckets {
+    base: PublicKey,
+    buckets: Vec<KBucket>,
+    #[serde(skip)]
+    refreshed: HashMap<usize, Instant>,
+}
+
+impl KBuckets {
+    pub fn new(base: &PublicKey) -> Self {
+        KBuckets {
+            base: base.clone(),
+            buckets: vec![Default::default(); Distance::BITS],
+            refreshed: HashMap::new(),
+        }
+    }
+
+    pub fn base(&self) -> &PublicKey {
+        &self.base
+    }
+
+    /// bucket index, peers in bucket i have distance in [2^i, 2^(i+1)).
+    fn index(&self, pk: &PublicKey) -> Option<usize> {
+        let zeros = Distance::distance(&node_id(&self.base).to_bytes(), &node_id(pk).to_bytes())
+            .leading_zeros();
+        if zeros >= Distance::BITS {
+            None
+        } else {
+            Some(Distance::BITS - 1 - zeros)
+        }
+    }
+
+    pub fn insert(&mut self, pk: &PublicKey, socket: SocketAddr) -> Insert {
+        match self.index(pk) {
+            Some(index) => self.buckets[index].insert(pk, socket),
+            None => Insert::Ignored,
+        }
+    }
+
+    pub fn remove(&mut self, pk: &PublicKey) -> bool {
+        match self.index(pk) {
+            Some(index) => self.buckets[index].remove(pk),
+            None => false,
+        }
+    }
+
+    /// peer is alive, move to the bucket tail.
+    pub fn seen(&mut self, pk: &PublicKey) {
+        if let Some(index) = self.index(pk) {
+            self.buckets[index].seen(pk);
+        }
+    }
+
+    pub fn get(&self, pk: &PublicKey) -> Option<SocketAddr> {
+        self.index(pk).and_then(|index| {
+            self.buckets[index]
+                .peers
+                .iter()
+                .find(|(p, _)| p == pk)
+                .map(|(_, socket)| *socket)
+        })
+    }
+
+    pub fn contains(&self, pk: &PublicKey) -> bool {
+        self.get(pk).is_some()
+    }
+
+    pub fn all(&self) -> Vec<(PublicKey, SocketAddr)> {
+        self.buckets
+            .iter()
+            .flat_map(|bucket| bucket.peers.iter().cloned())
+            .collect()
+    }
+
+    /// n peers closest to target by xor distance.
+    pub fn closest(&self, target: &[u8], n: usize) -> Vec<(PublicKey, SocketAddr)> {
+        let mut peers = self.all();
+        peers.sort_by_key(|(pk, _)| Distance::distance(&node_id(pk).to_bytes(), target));
+        peers.truncate(n);
+        peers
+    }
+
+    /// random keys of the not empty buckets which not refreshed recently.
+    pub fn refreshes(&mut self) -> Vec<H256> {
+        let now = Instant::now();
+        let base = node_id(&self.base).to_bytes();
+        let mut keys = vec![];
+        for (index, bucket) in self.buckets.iter().enumerate() {
+            if bucket.peers.is_empty() {
+                continue;
+            }
+            if let Some(ins) = self.refreshed.get(&index) {
+                if now.duration_since(*ins) < Duration::new(REFRESH_TIME, 0) {
+                    continue;
+                }
+            }
+            self.refreshed.insert(index, now);
+
+            // distance in [2^index, 2^(index+1)), highest bit is index
+            let mut distance: [u8; 32] = rand::random();
+            let zeros = Distance::BITS - 1 - index;
+            for i in 0..32 {
+                let bits = i * 8;
+                if bits + 8 <= zeros {
+                    distance[i] = 0;
+                } else if bits < zeros + 1 {
+                    let high = zeros - bits;
+                    distance[i] = (distance[i] & (0xff >> high)) | (0x80 >> high);
+                }
+            }
+
+            let mut key = [0u8; 32];
+            for i in 0..32 {
+                key[i] = base[i] ^ distance[i];
+            }
+            keys.push(H256::from_bytes(&key).unwrap());
+        }
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::keypair::PrivateKey;
+
+    #[test]
+    fn test_kbuckets_replace_and_closest() {
+        let base = PrivateKey::generate().generate_public_key();
+        let socket: SocketAddr = "127.0.0.1:7364".parse().unwrap();
+        let mut buckets = KBuckets::new(&base);
+        assert_eq!(buckets.insert(&base, socket), Insert::Ignored);
+
+        // most random keys in the farthest bucket
+        let mut full = None;
+        let mut inserted = vec![];
+        while full.is_none() {
+            let pk = PrivateKey::generate().generate_public_key();
+            match buckets.insert(&pk, socket) {
+                Insert::Inserted => inserted.push(pk),
+                Insert::Full(lru, _) => full = Some((pk, lru)),
+                _ => {}
+            }
+        }
+
+        let (replacement, lru) = full.unwrap();
+        assert!(!buckets.contains(&replacement));
+        assert!(buckets.remove(&lru));
+        assert!(buckets.contains(&replacement));
+
+        let target = inserted.last().unwrap();
+        let target_id = node_id(target).to_bytes();
+        assert_eq!(&buckets.closest(&target_id, 1)[0].0, target);
+        assert_eq!(buckets.closest(&target_id, 3).len(), 3);
+
+        // refresh key in the same bucket
+        for key in buckets.refreshes() {
+            let zeros =
+                Distance::distance(&node_id(&base).to_bytes(), &key.to_bytes()).leading_zeros();
+            assert!(!buckets.buckets[Distance::BITS - 1 - zeros].peers.is_empty());
+        }
+        assert!(buckets.refreshes().is_empty());
+    }
+}
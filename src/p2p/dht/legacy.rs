@@ -0,0 +1,48 @@
+use serde_derive::{Deserialize, Serialize};
+use std::net::SocketAddr;
+
+use crate::crypto::keypair::PublicKey;
+
+use super::table::DHTTable;
+
+type TreeNode = Option<Box<Node>>;
+
+/// stored format of the old unbalanced binary tree, only for migrate.
+#[derive(Serialize, Deserialize)]
+struct Node {
+    left: TreeNode,
+    right: TreeNode,
+    pk: PublicKey,
+    distance: [u8; 20],
+    value: SocketAddr,
+}
+
+impl Node {
+    fn collect_element(&self, vec: &mut Vec<(PublicKey, SocketAddr)>) {
+        for child in [&self.left, &self.right].iter() {
+            if let Some(ref node) = child {
+                vec.push((node.pk.clone(), node.value));
+                node.collect_element(vec);
+            }
+        }
+    }
+}
+
+/// stored format of the old DHT table, only the tree was stored.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct LegacyDHTTable {
+    cells: Node,
+}
+
+impl LegacyDHTTable {
+    /// move all peers of the tree to k-buckets table.
+    pub fn migrate(self) -> DHTTable {
+        let mut table = DHTTable::new(&self.cells.pk);
+        let mut peers = vec![];
+        self.cells.collect_element(&mut peers);
+        for (pk, socket) in peers {
+            table.add_peer(&pk, socket);
+        }
+        table
+    }
+}
@@ -0,0 +1,278 @@
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use crate::crypto::hash::H256;
+use crate::crypto::keypair::PublicKey;
+use crate::p2p::puzzle;
+use crate::primitives::consts::K_BUCKET;
+use crate::primitives::types::GroupID;
+
+use super::distance::{node_id, Distance};
+use super::store::DHTRecord;
+
+/// parallel requests of one lookup.
+pub const ALPHA: usize = 3;
+
+/// wait peer response in this time, if not, ask next peer.
+const REQUEST_TIMEOUT: u64 = 2;
+
+/// max time of one lookup.
+const LOOKUP_TIME: u64 = 30;
+
+/// what to do when lookup finished.
+#[derive(Clone)]
+pub(crate) enum LookupKind {
+    /// connect the closest peers.
+    Node,
+    /// store the record to the closest peers.
+    Store(DHTRecord),
+    /// find the record, stop when found, params is the origin key.
+    Value(Vec<u8>),
+    /// announce self provide the content to the closest peers.
+    Provide,
+    /// find the content providers, and the address they advertised.
+    Providers(Vec<(PublicKey, SocketAddr)>),
+}
+
+#[derive(Clone, PartialEq)]
+enum State {
+    Waiting,
+    Asking(Instant),
+    Responded,
+    Failed,
+}
+
+#[derive(Clone)]
+struct Candidate {
+    pk: PublicKey,
+    socket: SocketAddr,
+    distance: Distance,
+    state: State,
+    via: Option<PublicKey>,
+}
+
+/// iterative lookup of the k closest peers to target,
+/// ask alpha closest peers in parallel, until the k closest all responded.
+#[derive(Clone)]
+pub(crate) struct Lookup {
+    group: GroupID,
+    target: H256,
+    base: PublicKey,
+    difficulty: u8,
+    candidates: Vec<Candidate>,
+    start: Instant,
+    kind: LookupKind,
+}
+
+impl Lookup {
+    pub fn new(
+        group: GroupID,
+        target: H256,
+        base: &PublicKey,
+        difficulty: u8,
+        seeds: Vec<(PublicKey, SocketAddr)>,
+        via: Option<PublicKey>,
+        kind: LookupKind,
+    ) -> Self {
+        let mut lookup = Lookup {
+            group,
+            target,
+            base: base.clone(),
+            difficulty,
+            candidates: vec![],
+            start: Instant::now(),
+            kind,
+        };
+        lookup.add(seeds, via);
+        lookup
+    }
+
+    pub fn group(&self) -> &GroupID {
+        &self.group
+    }
+
+    pub fn target(&self) -> &H256 {
+        &self.target
+    }
+
+    pub fn kind(&self) -> &LookupKind {
+        &self.kind
+    }
+
+    pub fn kind_mut(&mut self) -> &mut LookupKind {
+        &mut self.kind
+    }
+
+    /// add the peers learned from via peer, sort by distance to target,
+    /// the peer not solved identity puzzle is ignored.
+    fn add(&mut self, peers: Vec<(PublicKey, SocketAddr)>, via: Option<PublicKey>) {
+        let target = self.target.to_bytes();
+        for (pk, socket) in peers {
+            if pk == self.base
+                || !puzzle::verify(&pk, self.difficulty)
+                || self.candidates.iter().any(|c| c.pk == pk)
+            {
+                continue;
+            }
+
+            self.candidates.push(Candidate {
+                distance: Distance::distance(&node_id(&pk).to_bytes(), &target),
+                pk,
+                socket,
+                state: State::Waiting,
+                via: via.clone(),
+            });
+        }
+        self.candidates.sort_by(|a, b| a.distance.cmp(&b.distance));
+    }
+
+    /// the peers need ask now, not more than alpha asking.
+    pub fn next(&mut self) -> Vec<(PublicKey, SocketAddr)> {
+        let now = Instant::now();
+        for candidate in self.candidates.iter_mut() {
+            if let State::Asking(ins) = candidate.state {
+                if now.duration_since(ins) > Duration::new(REQUEST_TIMEOUT, 0) {
+                    candidate.state = State::Failed;
+                }
+            }
+        }
+
+        let mut asking = self
+            .candidates
+            .iter()
+            .filter(|c| match c.state {
+                State::Asking(_) => true,
+                _ => false,
+            })
+            .count();
+
+        let mut nexts = vec![];
+        for candidate in self
+            .candidates
+            .iter_mut()
+            .filter(|c| c.state != State::Failed)
+            .take(K_BUCKET)
+        {
+            if asking >= ALPHA {
+                break;
+            }
+            if candidate.state == State::Waiting {
+                candidate.state = State::Asking(now);
+                asking += 1;
+                nexts.push((candidate.pk.clone(), candidate.socket));
+            }
+        }
+        nexts
+    }
+
+    /// peer response the closer peers, return false if not asked it.
+    pub fn response(&mut self, pk: &PublicKey, peers: Vec<(PublicKey, SocketAddr)>) -> bool {
+        let asked = match self.candidates.iter_mut().find(|c| &c.pk == pk) {
+            Some(candidate) => match candidate.state {
+                State::Asking(_) => {
+                    candidate.state = State::Responded;
+                    true
+                }
+                _ => false,
+            },
+            None => false,
+        };
+
+        if asked {
+            self.add(peers, Some(pk.clone()));
+        }
+        asked
+    }
+
+    /// the k closest alive peers all responded, or timeout.
+    pub fn is_done(&self) -> bool {
+        Instant::now().duration_since(self.start) > Duration::new(LOOKUP_TIME, 0)
+            || self
+                .candidates
+                .iter()
+                .filter(|c| c.state != State::Failed)
+                .take(K_BUCKET)
+                .all(|c| c.state == State::Responded)
+    }
+
+    /// the k closest responded peers, and the peer who told it.
+    pub fn result(&self) -> Vec<(PublicKey, SocketAddr, Option<PublicKey>)> {
+        self.candidates
+            .iter()
+            .filter(|c| c.state == State::Responded)
+            .take(K_BUCKET)
+            .map(|c| (c.pk.clone(), c.socket, c.via.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::keypair::PrivateKey;
+
+    #[test]
+    fn test_lookup_converge() {
+        let base = PrivateKey::generate().generate_public_key();
+        let socket: SocketAddr = "127.0.0.1:7364".parse().unwrap();
+        let peers: Vec<(PublicKey, SocketAddr)> = (0..20)
+            .map(|_| (PrivateKey::generate().generate_public_key(), socket))
+            .collect();
+        let target = H256::new(b"target");
+
+        let mut lookup = Lookup::new(
+            Default::default(),
+            target.clone(),
+            &base,
+            0,
+            peers[..2].to_vec(),
+            None,
+            LookupKind::Node,
+        );
+        let asks = lookup.next();
+        assert_eq!(asks.len(), 2);
+        assert!(!lookup.response(&peers[5].0, vec![]));
+        assert!(lookup.response(&asks[0].0, peers[2..].to_vec()));
+        assert!(lookup.response(&asks[1].0, vec![]));
+
+        // every peer responded, converge to the k closest
+        while !lookup.is_done() {
+            let asks = lookup.next();
+            assert!(asks.len() <= ALPHA);
+            for (pk, _) in asks {
+                lookup.response(&pk, vec![]);
+            }
+        }
+        let result = lookup.result();
+        assert_eq!(result.len(), K_BUCKET);
+
+        let mut all = peers.clone();
+        let target = target.to_bytes();
+        all.sort_by_key(|(pk, _)| Distance::distance(&node_id(pk).to_bytes(), &target));
+        assert!(result.iter().zip(all.iter()).all(|(a, b)| a.0 == b.0));
+    }
+
+    #[test]
+    fn test_lookup_puzzle() {
+        let base = PrivateKey::generate().generate_public_key();
+        let socket: SocketAddr = "127.0.0.1:7364".parse().unwrap();
+        let solved = puzzle::solve(8).generate_public_key();
+        let mut unsolved = PrivateKey::generate().generate_public_key();
+        while puzzle::verify(&unsolved, 8) {
+            unsolved = PrivateKey::generate().generate_public_key();
+        }
+
+        let mut lookup = Lookup::new(
+            Default::default(),
+            H256::new(b"target"),
+            &base,
+            8,
+            vec![(solved.clone(), socket), (unsolved.clone(), socket)],
+            None,
+            LookupKind::Node,
+        );
+        let asks = lookup.next();
+        assert_eq!(asks.len(), 1);
+        assert_eq!(asks[0].0, solved);
+    }
+}
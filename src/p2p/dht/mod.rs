@@ -1,5 +1,16 @@
-mod binary_tree;
 mod distance;
+mod kbucket;
+mod legacy;
+mod lookup;
+mod provider;
+mod store;
+mod swim;
 mod table;
 
-pub(crate) use self::table::DHTTable;
+pub(crate) use self::distance::{node_id, Distance};
+pub(crate) use self::legacy::LegacyDHTTable;
+pub(crate) use self::lookup::{Lookup, LookupKind};
+pub(crate) use self::provider::Providers;
+pub(crate) use self::store::{now_secs, DHTRecord, RecordStore, MAX_VALUE_SIZE};
+pub(crate) use self::swim::{Acked, MemberUpdate, Probe};
+pub(crate) use self::table::{DHTTable, PeerMeta};
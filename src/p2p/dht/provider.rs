@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use crate::crypto::hash::H256;
+use crate::crypto::keypair::PublicKey;
+use crate::primitives::consts::K_BUCKET;
+use crate::primitives::types::GroupID;
+
+/// announce self provided content to the closest peers again in this time.
+const REPROVIDE_TIME: u64 = 3600;
+
+/// provider record kept in this time, if not announced again, remove it.
+const PROVIDE_TIME: u64 = 3 * REPROVIDE_TIME;
+
+/// max content keys stored in self.
+const MAX_KEYS: usize = 10000;
+
+/// who hold the content, the content is identified by hash,
+/// keep the providers announced by others, and the content self provided.
+#[derive(Clone, Default)]
+pub(crate) struct Providers {
+    providers: HashMap<(GroupID, H256), HashMap<PublicKey, (SocketAddr, Instant)>>,
+    locals: HashMap<(GroupID, H256), Instant>,
+}
+
+impl Providers {
+    /// self hold the content, return false if had provided.
+    pub fn provide(&mut self, group: GroupID, key: H256) -> bool {
+        self.locals.insert((group, key), Instant::now()).is_none()
+    }
+
+    pub fn is_local(&self, group: &GroupID, key: &H256) -> bool {
+        self.locals.contains_key(&(group.clone(), key.clone()))
+    }
+
+    /// peer announce it hold the content, only keep k providers of one content.
+    pub fn add(&mut self, group: GroupID, key: H256, pk: PublicKey, socket: SocketAddr) -> bool {
+        let index = (group, key);
+        if !self.providers.contains_key(&index) && self.providers.len() >= MAX_KEYS {
+            return false;
+        }
+
+        let providers = self.providers.entry(index).or_insert(HashMap::new());
+        if !providers.contains_key(&pk) && providers.len() >= K_BUCKET {
+            let oldest = providers
+                .iter()
+                .min_by_key(|(_, (_, ins))| *ins)
+                .map(|(pk, _)| pk.clone());
+            if let Some(oldest) = oldest {
+                providers.remove(&oldest);
+            }
+        }
+        providers.insert(pk, (socket, Instant::now()));
+        true
+    }
+
+    pub fn get(&self, group: &GroupID, key: &H256) -> Vec<(PublicKey, SocketAddr)> {
+        self.providers
+            .get(&(group.clone(), key.clone()))
+            .map(|providers| {
+                providers
+                    .iter()
+                    .map(|(pk, (socket, _))| (pk.clone(), *socket))
+                    .collect()
+            })
+            .unwrap_or(vec![])
+    }
+
+    /// self provided content need announce again, and mark the time.
+    pub fn reprovides(&mut self) -> Vec<(GroupID, H256)> {
+        let now = Instant::now();
+        let mut keys = vec![];
+        for ((group, key), ins) in self.locals.iter_mut() {
+            if now.duration_since(*ins) >= Duration::new(REPROVIDE_TIME, 0) {
+                *ins = now;
+                keys.push((group.clone(), key.clone()));
+            }
+        }
+        keys
+    }
+
+    /// remove the providers not announced again.
+    pub fn evict(&mut self) {
+        let now = Instant::now();
+        for providers in self.providers.values_mut() {
+            providers
+                .retain(|_, (_, ins)| now.duration_since(*ins) < Duration::new(PROVIDE_TIME, 0));
+        }
+        self.providers.retain(|_, providers| !providers.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::keypair::PrivateKey;
+
+    #[test]
+    fn test_providers() {
+        let group: GroupID = Default::default();
+        let key = H256::new(b"block");
+        let socket: SocketAddr = "127.0.0.1:7364".parse().unwrap();
+        let mut providers = Providers::default();
+
+        let pks: Vec<PublicKey> = (0..K_BUCKET + 1)
+            .map(|_| PrivateKey::generate().generate_public_key())
+            .collect();
+        for pk in pks.iter() {
+            assert!(providers.add(group.clone(), key.clone(), pk.clone(), socket));
+        }
+        let holders = providers.get(&group, &key);
+        assert_eq!(holders.len(), K_BUCKET);
+        assert!(holders.iter().any(|(pk, _)| pk == &pks[K_BUCKET]));
+        assert!(providers.get(&group, &H256::new(b"other")).is_empty());
+
+        assert!(providers.provide(group.clone(), key.clone()));
+        assert!(!providers.provide(group.clone(), key.clone()));
+        assert!(providers.is_local(&group, &key));
+        assert!(providers.reprovides().is_empty());
+    }
+}
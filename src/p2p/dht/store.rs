@@ -0,0 +1,230 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::crypto::hash::H256;
+use crate::crypto::keypair::{PrivateKey, PublicKey, Signature};
+use crate::primitives::types::GroupID;
+
+/// max bytes of one record value.
+pub const MAX_VALUE_SIZE: usize = 4096;
+
+/// max records stored in self.
+const MAX_RECORDS: usize = 10000;
+
+/// max time to live of one record.
+pub const MAX_TTL: u32 = 24 * 3600;
+
+/// replicate the stored records to the k closest peers in this time.
+const REPUBLISH_TIME: u64 = 3600;
+
+/// seconds from unix epoch.
+pub(crate) fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// key/value record, signed by publisher, stored peers can not change it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DHTRecord {
+    pub value: Vec<u8>,
+    pub publisher: PublicKey,
+    pub expires: u64,
+    pub sign: Signature,
+}
+
+impl DHTRecord {
+    fn sign_data(group: &GroupID, key: &H256, value: &[u8], expires: u64) -> Vec<u8> {
+        let mut bytes = group.to_bytes().to_vec();
+        bytes.extend_from_slice(&key.to_bytes());
+        bytes.extend_from_slice(value);
+        bytes.extend_from_slice(&expires.to_be_bytes());
+        bytes
+    }
+
+    pub fn new(group: &GroupID, key: &H256, psk: &PrivateKey, value: Vec<u8>, ttl: u32) -> Self {
+        let expires = now_secs() + ttl.min(MAX_TTL) as u64;
+        let sign = psk.sign_bytes(&Self::sign_data(group, key, &value, expires));
+
+        DHTRecord {
+            value,
+            publisher: psk.generate_public_key(),
+            expires,
+            sign,
+        }
+    }
+
+    pub fn verify(&self, group: &GroupID, key: &H256) -> bool {
+        self.value.len() <= MAX_VALUE_SIZE
+            && self.expires <= now_secs() + MAX_TTL as u64
+            && self.publisher.verify_bytes(
+                &Self::sign_data(group, key, &self.value, self.expires),
+                &self.sign,
+            )
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires <= now_secs()
+    }
+
+    /// remain seconds of record.
+    pub fn ttl(&self) -> u32 {
+        self.expires.saturating_sub(now_secs()) as u32
+    }
+}
+
+/// records stored in self, expired on ttl and republished to the closest peers,
+/// the key is owned by the first publisher until the record expired.
+#[derive(Clone, Default)]
+pub(crate) struct RecordStore {
+    records: HashMap<(GroupID, H256), (DHTRecord, Instant)>,
+    changed: HashSet<(GroupID, H256)>,
+    removed: HashSet<(GroupID, H256)>,
+    keys_changed: bool,
+}
+
+impl RecordStore {
+    /// load the persisted records, republish them soon.
+    pub fn load(&mut self, records: Vec<(GroupID, H256, DHTRecord)>) {
+        let republish = Instant::now() - Duration::new(REPUBLISH_TIME, 0);
+        for (group, key, record) in records {
+            if !record.is_expired() {
+                self.records.insert((group, key), (record, republish));
+            }
+        }
+    }
+
+    /// store verified record, the newer record of the same publisher will replace old,
+    /// other publisher can not replace it.
+    pub fn put(&mut self, group: GroupID, key: H256, record: DHTRecord) -> bool {
+        if record.is_expired() {
+            return false;
+        }
+
+        let index = (group, key);
+        match self.records.get(&index) {
+            Some((old, _)) if !old.is_expired() && old.publisher != record.publisher => {
+                return false
+            }
+            Some((old, _))
+                if old.expires > record.expires
+                    || (old.expires == record.expires && old.value == record.value) =>
+            {
+                return false
+            }
+            None if self.records.len() >= MAX_RECORDS => return false,
+            None => self.keys_changed = true,
+            _ => {}
+        }
+
+        self.removed.remove(&index);
+        self.changed.insert(index.clone());
+        self.records.insert(index, (record, Instant::now()));
+        true
+    }
+
+    pub fn get(&self, group: &GroupID, key: &H256) -> Option<&DHTRecord> {
+        self.records
+            .get(&(group.clone(), key.clone()))
+            .map(|(record, _)| record)
+            .filter(|record| !record.is_expired())
+    }
+
+    /// records need replicate again, and mark the time.
+    pub fn republishes(&mut self) -> Vec<(GroupID, H256, DHTRecord)> {
+        let now = Instant::now();
+        let mut records = vec![];
+        for ((group, key), (record, ins)) in self.records.iter_mut() {
+            if now.duration_since(*ins) >= Duration::new(REPUBLISH_TIME, 0) {
+                *ins = now;
+                records.push((group.clone(), key.clone(), record.clone()));
+            }
+        }
+        records
+    }
+
+    /// changes since last time for persist, all keys if keys added or removed,
+    /// the changed records, and the removed keys.
+    pub fn take_changes(
+        &mut self,
+    ) -> (
+        Option<Vec<(GroupID, H256)>>,
+        Vec<(GroupID, H256, DHTRecord)>,
+        Vec<(GroupID, H256)>,
+    ) {
+        let keys = if self.keys_changed {
+            self.keys_changed = false;
+            Some(self.records.keys().cloned().collect())
+        } else {
+            None
+        };
+
+        let all = &self.records;
+        let records = self
+            .changed
+            .drain()
+            .filter_map(|index| {
+                all.get(&index)
+                    .map(|(record, _)| (index.0, index.1, record.clone()))
+            })
+            .collect();
+        let removed = self.removed.drain().collect();
+        (keys, records, removed)
+    }
+
+    /// remove the expired records.
+    pub fn evict(&mut self) {
+        let expired: Vec<(GroupID, H256)> = self
+            .records
+            .iter()
+            .filter(|(_, (record, _))| record.is_expired())
+            .map(|(index, _)| index.clone())
+            .collect();
+
+        for index in expired {
+            self.records.remove(&index);
+            self.changed.remove(&index);
+            self.removed.insert(index);
+            self.keys_changed = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_store() {
+        let psk = PrivateKey::generate();
+        let group: GroupID = Default::default();
+        let key = H256::new(b"key");
+        let record = DHTRecord::new(&group, &key, &psk, vec![1, 2, 3], 60);
+        assert!(record.verify(&group, &key));
+        assert!(!record.verify(&group, &H256::new(b"other")));
+
+        let mut store = RecordStore::default();
+        assert!(store.put(group.clone(), key.clone(), record.clone()));
+        assert!(!store.put(group.clone(), key.clone(), record));
+        assert_eq!(store.get(&group, &key).unwrap().value, vec![1, 2, 3]);
+        let (keys, records, removed) = store.take_changes();
+        assert_eq!(keys.unwrap().len(), 1);
+        assert_eq!(records.len(), 1);
+        assert!(removed.is_empty());
+        let (keys, records, removed) = store.take_changes();
+        assert!(keys.is_none() && records.is_empty() && removed.is_empty());
+        assert!(store.republishes().is_empty());
+
+        // other publisher can not replace the key
+        let other = DHTRecord::new(&group, &key, &PrivateKey::generate(), vec![4], 120);
+        assert!(!store.put(group.clone(), key.clone(), other));
+        let newer = DHTRecord::new(&group, &key, &psk, vec![5], 120);
+        assert!(store.put(group.clone(), key.clone(), newer));
+        assert_eq!(store.get(&group, &key).unwrap().value, vec![5]);
+
+        let expired = DHTRecord::new(&group, &key, &psk, vec![4], 0);
+        assert!(!store.put(group.clone(), H256::new(b"expired"), expired));
+    }
+}
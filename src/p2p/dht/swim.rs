@@ -0,0 +1,452 @@
+use rand::seq::SliceRandom;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use crate::crypto::keypair::PublicKey;
+
+/// direct ping not acked in this time, ask other members to ping it.
+const PING_TIMEOUT: u64 = 1;
+
+/// probe not acked in this time, suspect the member.
+const PROBE_TIMEOUT: u64 = 3;
+
+/// suspected member not refuted in this time, confirm it dead if self probe fail,
+/// the suspicion told by other members just expire.
+const SUSPECT_TIMEOUT: u64 = 10;
+
+/// members ask to ping the target indirectly.
+pub const PING_REQ_MEMBERS: usize = 3;
+
+/// max updates piggybacked on one message.
+const MAX_PIGGYBACK: usize = 6;
+
+/// every update piggybacked this times of log2(members).
+const RETRANSMIT_MULT: u32 = 3;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum MemberState {
+    Alive,
+    Suspect,
+    Dead,
+}
+
+/// member state change, disseminated by piggybacking on probes,
+/// the newer incarnation overrides, only the member can increase its incarnation.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MemberUpdate {
+    pub pk: PublicKey,
+    pub state: MemberState,
+    pub incarnation: u32,
+}
+
+/// probe need send.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Probe {
+    /// ping member directly, params is seq, member and socket.
+    Ping(u64, PublicKey, SocketAddr),
+    /// ask member to ping target, params is seq, member, socket, target and target socket.
+    PingReq(u64, PublicKey, SocketAddr, PublicKey, SocketAddr),
+}
+
+/// who the ack is for.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Acked {
+    /// self probe acked, params is member and round trip time milliseconds.
+    Probe(PublicKey, u32),
+    /// ping for other member acked, forward to it, params is member, socket and its seq.
+    Forward(PublicKey, SocketAddr, u64),
+}
+
+#[derive(Clone)]
+struct Probing {
+    seq: u64,
+    pk: PublicKey,
+    start: Instant,
+    indirect: bool,
+    helpers: Vec<PublicKey>,
+}
+
+/// SWIM failure detector of one group, probe one random member every period,
+/// if not acked, ping it indirectly by other members, then suspect it,
+/// only confirm it dead when suspicion not refuted in time.
+#[derive(Clone, Default)]
+pub(crate) struct Swim {
+    incarnation: u32,
+    incarnations: HashMap<PublicKey, u32>,
+    /// suspected members, bool is self probe fail, or only told by others.
+    suspects: HashMap<PublicKey, (Instant, bool)>,
+    order: Vec<PublicKey>,
+    probing: Option<Probing>,
+    forwards: HashMap<u64, (PublicKey, SocketAddr, u64, PublicKey, Instant)>,
+    seq: u64,
+    updates: Vec<(MemberUpdate, u32)>,
+}
+
+impl Swim {
+    fn next_seq(&mut self) -> u64 {
+        self.seq += 1;
+        self.seq
+    }
+
+    fn enqueue(&mut self, update: MemberUpdate) {
+        self.updates.retain(|(u, _)| u.pk != update.pk);
+        self.updates.push((update, 0));
+    }
+
+    /// suspected by self probe fail.
+    fn probe_suspected(&self, pk: &PublicKey) -> bool {
+        self.suspects
+            .get(pk)
+            .map(|(_, probed)| *probed)
+            .unwrap_or(false)
+    }
+
+    fn suspect(&mut self, pk: PublicKey) {
+        if self.probe_suspected(&pk) {
+            return;
+        }
+        let incarnation = *self.incarnations.get(&pk).unwrap_or(&0);
+        self.suspects.insert(pk.clone(), (Instant::now(), true));
+        self.enqueue(MemberUpdate {
+            pk,
+            state: MemberState::Suspect,
+            incarnation,
+        });
+    }
+
+    fn confirm(&mut self, pk: PublicKey) {
+        let incarnation = *self.incarnations.get(&pk).unwrap_or(&0);
+        self.remove(&pk);
+        self.enqueue(MemberUpdate {
+            pk,
+            state: MemberState::Dead,
+            incarnation,
+        });
+    }
+
+    /// one protocol period, the priority members probe first,
+    /// return probes, new suspected members and confirmed dead members.
+    pub fn next_probes(
+        &mut self,
+        members: &[(PublicKey, SocketAddr)],
+        priority: &mut Vec<(PublicKey, SocketAddr)>,
+    ) -> (Vec<Probe>, Vec<PublicKey>, Vec<PublicKey>) {
+        let now = Instant::now();
+        let mut probes = vec![];
+        let mut suspected = vec![];
+
+        if let Some(probing) = self.probing.clone() {
+            let elapsed = now.duration_since(probing.start);
+            if elapsed > Duration::new(PROBE_TIMEOUT, 0) {
+                self.probing = None;
+                if !self.probe_suspected(&probing.pk) {
+                    suspected.push(probing.pk.clone());
+                }
+                self.suspect(probing.pk);
+            } else if !probing.indirect && elapsed > Duration::new(PING_TIMEOUT, 0) {
+                if let Some((_, socket)) = members.iter().find(|(pk, _)| pk == &probing.pk) {
+                    let helpers: Vec<&(PublicKey, SocketAddr)> = members
+                        .iter()
+                        .filter(|(pk, _)| pk != &probing.pk && !self.suspects.contains_key(pk))
+                        .collect();
+                    let mut asked = vec![];
+                    for (helper, helper_socket) in helpers
+                        .choose_multiple(&mut rand::thread_rng(), PING_REQ_MEMBERS)
+                        .cloned()
+                    {
+                        asked.push(helper.clone());
+                        probes.push(Probe::PingReq(
+                            probing.seq,
+                            helper.clone(),
+                            *helper_socket,
+                            probing.pk.clone(),
+                            *socket,
+                        ));
+                    }
+                    self.probing.as_mut().map(|p| p.helpers = asked);
+                }
+                self.probing.as_mut().map(|p| p.indirect = true);
+            }
+        }
+
+        let expired: Vec<(PublicKey, bool)> = self
+            .suspects
+            .iter()
+            .filter(|(_, (ins, _))| now.duration_since(*ins) > Duration::new(SUSPECT_TIMEOUT, 0))
+            .map(|(pk, (_, probed))| (pk.clone(), *probed))
+            .collect();
+        let mut dead = vec![];
+        for (pk, probed) in expired {
+            if probed {
+                self.confirm(pk.clone());
+                dead.push(pk);
+            } else {
+                self.suspects.remove(&pk);
+            }
+        }
+
+        self.forwards.retain(|_, (_, _, _, _, ins)| {
+            now.duration_since(*ins) < Duration::new(PROBE_TIMEOUT, 0)
+        });
+
+        if self.probing.is_none() {
+            // round robin in random order, every member probed once a round
+            let mut next = priority.pop();
+            while next.is_none() {
+                if self.order.is_empty() {
+                    self.order = members.iter().map(|(pk, _)| pk.clone()).collect();
+                    self.order.shuffle(&mut rand::thread_rng());
+                    if self.order.is_empty() {
+                        break;
+                    }
+                }
+                let pk = self.order.pop().unwrap();
+                next = members.iter().find(|(p, _)| p == &pk).cloned();
+            }
+
+            if let Some((pk, socket)) = next {
+                let seq = self.next_seq();
+                self.probing = Some(Probing {
+                    seq,
+                    pk: pk.clone(),
+                    start: now,
+                    indirect: false,
+                    helpers: vec![],
+                });
+                probes.push(Probe::Ping(seq, pk, socket));
+            }
+        }
+
+        (probes, suspected, dead)
+    }
+
+    /// member ask self to ping target, ack will forward to it.
+    pub fn ping_req(
+        &mut self,
+        from: PublicKey,
+        socket: SocketAddr,
+        seq: u64,
+        target: PublicKey,
+        target_socket: SocketAddr,
+    ) -> Probe {
+        let own_seq = self.next_seq();
+        self.forwards
+            .insert(own_seq, (from, socket, seq, target.clone(), Instant::now()));
+        Probe::Ping(own_seq, target, target_socket)
+    }
+
+    /// receive ack of ping, only from the member pinged, or the helpers asked to ping it.
+    pub fn ack(&mut self, from: &PublicKey, seq: u64) -> Option<Acked> {
+        if self
+            .forwards
+            .get(&seq)
+            .map(|(_, _, _, target, _)| target == from)
+            .unwrap_or(false)
+        {
+            let (asker, socket, seq, _, _) = self.forwards.remove(&seq).unwrap();
+            return Some(Acked::Forward(asker, socket, seq));
+        }
+
+        match self.probing.take() {
+            Some(probing)
+                if probing.seq == seq
+                    && (&probing.pk == from || probing.helpers.contains(from)) =>
+            {
+                self.suspects.remove(&probing.pk);
+                let rtt = probing.start.elapsed().as_millis() as u32;
+                Some(Acked::Probe(probing.pk, rtt))
+            }
+            probing => {
+                self.probing = probing;
+                None
+            }
+        }
+    }
+
+    /// apply the updates of members, refute if self suspected,
+    /// suspect or dead told by others is only suspicion, until self probe fail,
+    /// return the members newly suspected, need probe them.
+    pub fn apply<F: Fn(&PublicKey) -> bool>(
+        &mut self,
+        base: &PublicKey,
+        updates: Vec<MemberUpdate>,
+        is_member: F,
+    ) -> Vec<PublicKey> {
+        let mut suspected = vec![];
+        for update in updates {
+            if &update.pk == base {
+                if update.state != MemberState::Alive && update.incarnation >= self.incarnation {
+                    self.incarnation = update.incarnation + 1;
+                    self.enqueue(MemberUpdate {
+                        pk: base.clone(),
+                        state: MemberState::Alive,
+                        incarnation: self.incarnation,
+                    });
+                }
+                continue;
+            }
+
+            if !is_member(&update.pk) {
+                continue;
+            }
+
+            let known = *self.incarnations.get(&update.pk).unwrap_or(&0);
+            match update.state {
+                MemberState::Alive if update.incarnation > known => {
+                    self.suspects.remove(&update.pk);
+                    self.incarnations
+                        .insert(update.pk.clone(), update.incarnation);
+                    self.enqueue(update);
+                }
+                MemberState::Suspect | MemberState::Dead
+                    if update.incarnation > known
+                        || (update.incarnation == known
+                            && !self.suspects.contains_key(&update.pk)) =>
+                {
+                    self.incarnations
+                        .insert(update.pk.clone(), update.incarnation);
+                    if !self.suspects.contains_key(&update.pk) {
+                        self.suspects
+                            .insert(update.pk.clone(), (Instant::now(), false));
+                        suspected.push(update.pk.clone());
+                    }
+                    // tell others as suspect, so the member can refute it
+                    self.enqueue(MemberUpdate {
+                        pk: update.pk,
+                        state: MemberState::Suspect,
+                        incarnation: update.incarnation,
+                    });
+                }
+                _ => {}
+            }
+        }
+        suspected
+    }
+
+    /// updates piggyback on next message, the least sent first.
+    pub fn updates(&mut self, members: usize) -> Vec<MemberUpdate> {
+        let limit = RETRANSMIT_MULT * (64 - (members as u64 + 1).leading_zeros());
+        self.updates.sort_by_key(|(_, times)| *times);
+        let updates = self
+            .updates
+            .iter_mut()
+            .take(MAX_PIGGYBACK)
+            .map(|(update, times)| {
+                *times += 1;
+                update.clone()
+            })
+            .collect();
+        self.updates.retain(|(_, times)| *times < limit);
+        updates
+    }
+
+    /// member removed from group.
+    pub fn remove(&mut self, pk: &PublicKey) {
+        self.incarnations.remove(pk);
+        self.suspects.remove(pk);
+        self.order.retain(|p| p != pk);
+        if self.probing.as_ref().map(|p| &p.pk == pk).unwrap_or(false) {
+            self.probing = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::keypair::PrivateKey;
+
+    #[test]
+    fn test_swim_suspect_and_refute() {
+        let socket: SocketAddr = "127.0.0.1:7364".parse().unwrap();
+        let base = PrivateKey::generate().generate_public_key();
+        let members: Vec<(PublicKey, SocketAddr)> = (0..4)
+            .map(|_| (PrivateKey::generate().generate_public_key(), socket))
+            .collect();
+
+        let mut swim = Swim::default();
+        let (probes, _, _) = swim.next_probes(&members, &mut vec![]);
+        let (seq, target) = match &probes[0] {
+            Probe::Ping(seq, pk, _) => (*seq, pk.clone()),
+            _ => panic!("need ping first"),
+        };
+        assert_eq!(swim.ack(&target, seq + 100), None);
+        let other = members
+            .iter()
+            .find(|(pk, _)| pk != &target)
+            .unwrap()
+            .0
+            .clone();
+        assert_eq!(swim.ack(&other, seq), None);
+        assert_eq!(
+            swim.ack(&target, seq).map(|a| match a {
+                Acked::Probe(pk, _) => pk,
+                _ => panic!("need probe ack"),
+            }),
+            Some(target.clone())
+        );
+
+        // ack forward to who asked
+        match swim.ping_req(members[1].0.clone(), socket, 9, target.clone(), socket) {
+            Probe::Ping(seq, pk, _) => {
+                assert_eq!(pk, target);
+                assert_eq!(swim.ack(&other, seq), None);
+                assert_eq!(
+                    swim.ack(&target, seq),
+                    Some(Acked::Forward(members[1].0.clone(), socket, 9))
+                );
+            }
+            _ => panic!("need ping target"),
+        }
+
+        // suspected by other, probe it first, not dead until confirmed
+        let is_member = |pk: &PublicKey| members.iter().any(|(p, _)| p == pk);
+        let suspect = MemberUpdate {
+            pk: target.clone(),
+            state: MemberState::Suspect,
+            incarnation: 0,
+        };
+        assert_eq!(
+            swim.apply(&base, vec![suspect], is_member),
+            vec![target.clone()]
+        );
+        let alive = MemberUpdate {
+            pk: target.clone(),
+            state: MemberState::Alive,
+            incarnation: 1,
+        };
+        assert!(swim.apply(&base, vec![alive], is_member).is_empty());
+        assert!(swim.suspects.is_empty());
+
+        // self suspected, refute with higher incarnation
+        let self_suspect = MemberUpdate {
+            pk: base.clone(),
+            state: MemberState::Suspect,
+            incarnation: 0,
+        };
+        swim.apply(&base, vec![self_suspect], is_member);
+        let updates = swim.updates(members.len());
+        assert!(updates
+            .iter()
+            .any(|u| u.pk == base && u.state == MemberState::Alive && u.incarnation == 1));
+
+        // dead told by other is only suspicion, expire without self probe fail
+        let dead = MemberUpdate {
+            pk: target.clone(),
+            state: MemberState::Dead,
+            incarnation: 1,
+        };
+        assert_eq!(
+            swim.apply(&base, vec![dead], is_member),
+            vec![target.clone()]
+        );
+        assert!(swim.incarnations.contains_key(&target));
+        swim.suspects.get_mut(&target).unwrap().0 -= Duration::new(SUSPECT_TIMEOUT + 1, 0);
+        swim.probing = None;
+        let (_, _, dead) = swim.next_probes(&members, &mut vec![]);
+        assert!(dead.is_empty());
+        assert!(!swim.suspects.contains_key(&target));
+    }
+}
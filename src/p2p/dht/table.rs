@@ -1,96 +1,237 @@
 use serde_derive::{Deserialize, Serialize};
 use std::collections::hash_map::HashMap;
+use std::collections::HashSet;
 use std::net::SocketAddr;
-use std::time::{Duration, Instant};
 
+use crate::crypto::hash::H256;
 use crate::crypto::keypair::PublicKey;
 
-use super::binary_tree::Node;
-//use crate::storage::append_node_list;
+use super::kbucket::{Insert, KBuckets};
+use super::store::now_secs;
+use super::swim::{Acked, MemberUpdate, Probe, Swim};
 
+/// liveness of peer, stored so good peers survive reboots.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct PeerMeta {
+    pub socket: SocketAddr,
+    /// last time peer alive, unix seconds.
+    pub last_seen: u64,
+    /// probe failures in a row.
+    pub failures: u32,
+    /// probe round trip time, milliseconds.
+    pub rtt: u32,
+}
+
+impl PeerMeta {
+    fn new(socket: SocketAddr) -> Self {
+        PeerMeta {
+            socket,
+            last_seen: now_secs(),
+            failures: 0,
+            rtt: 0,
+        }
+    }
+}
+
+/// routing table, only peers serialized in old format, now liveness is stored per peer.
 #[derive(Serialize, Deserialize, Clone)]
 pub(crate) struct DHTTable {
-    cells: Node,
+    cells: KBuckets,
     #[serde(skip)]
-    need_hb: HashMap<PublicKey, SocketAddr>,
+    pings: Vec<(PublicKey, SocketAddr)>,
     #[serde(skip)]
-    heartbeating: HashMap<PublicKey, Instant>,
+    swim: Swim,
     #[serde(skip)]
     tmp_cells: HashMap<PublicKey, Option<SocketAddr>>,
+    #[serde(skip)]
+    metas: HashMap<PublicKey, PeerMeta>,
+    #[serde(skip)]
+    changed: HashSet<PublicKey>,
+    #[serde(skip)]
+    removed: HashSet<PublicKey>,
+    #[serde(skip)]
+    members_changed: bool,
 }
 
 impl DHTTable {
     pub fn new(pk: &PublicKey) -> Self {
         DHTTable {
-            cells: Node::root(pk),
-            need_hb: HashMap::new(),
-            heartbeating: HashMap::new(),
+            cells: KBuckets::new(pk),
+            pings: vec![],
+            swim: Default::default(),
             tmp_cells: HashMap::new(),
+            metas: HashMap::new(),
+            changed: HashSet::new(),
+            removed: HashSet::new(),
+            members_changed: false,
         }
     }
 
-    pub fn next_hb_peers(&mut self) -> (Vec<(PublicKey, SocketAddr)>, Vec<PublicKey>) {
-        // update heartingbeating
-        let mut dis: Vec<PublicKey> = Vec::new();
+    /// restore the stored peers, least recently seen first, keep the buckets order,
+    /// and probe the stale peers first.
+    pub fn restore(&mut self, mut peers: Vec<(PublicKey, PeerMeta)>) {
+        peers.sort_by_key(|(_, meta)| meta.last_seen);
+        for (pk, meta) in peers.iter().rev() {
+            self.pings.push((pk.clone(), meta.socket));
+        }
+        for (pk, meta) in peers {
+            if let Insert::Inserted = self.cells.insert(&pk, meta.socket) {
+                self.metas.insert(pk, meta);
+            }
+        }
+    }
 
-        let need_remove: Vec<PublicKey> = self
-            .heartbeating
-            .iter()
-            .filter(|(_, ins)| Instant::now().duration_since(**ins) > Duration::new(20, 0))
-            .map(|(pk, _)| pk)
-            .cloned()
+    /// changes since last time, include all peers if peers joined or removed,
+    /// the changed peers liveness, and the removed peers.
+    pub fn take_changes(
+        &mut self,
+    ) -> (
+        Option<Vec<PublicKey>>,
+        Vec<(PublicKey, PeerMeta)>,
+        Vec<PublicKey>,
+    ) {
+        // peers not had liveness, promoted from replacements or loaded from old format
+        for (pk, socket) in self.cells.all() {
+            if !self.metas.contains_key(&pk) {
+                self.metas.insert(pk.clone(), PeerMeta::new(socket));
+                self.changed.insert(pk);
+                self.members_changed = true;
+            }
+        }
+
+        let peers = if self.members_changed {
+            self.members_changed = false;
+            Some(self.metas.keys().cloned().collect())
+        } else {
+            None
+        };
+
+        let all = &self.metas;
+        let metas = self
+            .changed
+            .drain()
+            .filter_map(|pk| all.get(&pk).map(|meta| (pk, meta.clone())))
             .collect();
+        let removed = self.removed.drain().collect();
+        (peers, metas, removed)
+    }
 
-        for pk in need_remove {
-            self.heartbeating.remove(&pk);
-            self.cells.remove(&pk);
-            dis.push(pk);
+    fn remove_meta(&mut self, pk: &PublicKey) {
+        if self.metas.remove(pk).is_some() {
+            self.changed.remove(pk);
+            self.removed.insert(pk.clone());
+            self.members_changed = true;
         }
+    }
 
-        if self.need_hb.len() == 0 {
-            self.need_hb = self
-                .cells
-                .all()
-                .iter()
-                .map(|(pk, socket)| ((*pk).clone(), (*socket).clone()))
-                .collect();
+    /// one SWIM protocol period, the least recently seen peers of full buckets probe first,
+    /// only remove the peers confirmed dead, return probes and dead peers.
+    pub fn next_probes(&mut self) -> (Vec<Probe>, Vec<PublicKey>) {
+        let members = self.cells.all();
+        let (probes, suspected, dead) = self.swim.next_probes(&members, &mut self.pings);
+        for pk in suspected {
+            if let Some(meta) = self.metas.get_mut(&pk) {
+                meta.failures += 1;
+                self.changed.insert(pk);
+            }
+        }
+        for pk in dead.iter() {
+            self.remove_peer(pk);
+        }
+        (probes, dead)
+    }
+
+    /// peer maybe unreachable, probe it in next protocol period.
+    pub fn probe_first(&mut self, pk: PublicKey, socket: SocketAddr) {
+        if !self.pings.iter().any(|(p, _)| p == &pk) {
+            self.pings.push((pk, socket));
         }
+    }
 
-        let key = self.need_hb.keys().into_iter().last();
-        if key.is_none() {
-            return (vec![], dis);
+    /// member ask self to ping target indirectly.
+    pub fn ping_req(
+        &mut self,
+        from: PublicKey,
+        socket: SocketAddr,
+        seq: u64,
+        target: PublicKey,
+        target_socket: SocketAddr,
+    ) -> Probe {
+        self.swim.ping_req(from, socket, seq, target, target_socket)
+    }
+
+    /// receive ack, update the probed peer liveness.
+    pub fn ack(&mut self, from: &PublicKey, seq: u64) -> Option<Acked> {
+        let acked = self.swim.ack(from, seq);
+        if let Some(Acked::Probe(pk, rtt)) = &acked {
+            self.update_hb_peers(pk);
+            if let Some(meta) = self.metas.get_mut(pk) {
+                meta.rtt = *rtt;
+            }
         }
+        acked
+    }
 
-        let hb_pk = key.unwrap().clone();
-        if let Some(socket_addr) = self.need_hb.remove(&hb_pk) {
-            if self.heartbeating.get(&hb_pk).is_some() {
-                (vec![], dis)
-            } else {
-                self.heartbeating.insert(hb_pk.clone(), Instant::now());
-                (vec![(hb_pk, socket_addr)], dis)
+    /// apply membership updates piggybacked, the peers suspected by others probe first.
+    pub fn apply_updates(&mut self, updates: Vec<MemberUpdate>) {
+        let base = self.cells.base().clone();
+        let cells = &self.cells;
+        let suspected = self.swim.apply(&base, updates, |pk| cells.contains(pk));
+        for pk in suspected {
+            if let Some(socket) = self.cells.get(&pk) {
+                self.probe_first(pk, socket);
             }
-        } else {
-            (vec![], dis)
         }
     }
 
-    /// update peers liveness when receive heartboat, return disconnect peer
+    /// membership updates need piggyback.
+    pub fn updates(&mut self) -> Vec<MemberUpdate> {
+        let members = self.cells.all().len();
+        self.swim.updates(members)
+    }
+
+    /// update peers liveness when receive heartbeat or ping.
     pub fn update_hb_peers(&mut self, pk: &PublicKey) {
-        self.heartbeating.remove(pk);
-        //self.cells.get_mut(&id.pk).and_then(|c| Some(c.liveness(1)));
+        self.cells.seen(pk);
+        if let Some(meta) = self.metas.get_mut(pk) {
+            meta.last_seen = now_secs();
+            meta.failures = 0;
+            self.changed.insert(pk.clone());
+        }
     }
 
     /// peer leave or remove
     pub fn remove_peer(&mut self, pk: &PublicKey) {
-        self.need_hb.remove(pk);
-        self.heartbeating.remove(pk);
+        self.pings.retain(|(p, _)| p != pk);
+        self.swim.remove(pk);
         self.cells.remove(pk);
         self.tmp_cells.remove(pk);
+        self.remove_meta(pk);
     }
 
-    /// peer join return is_new bool
+    /// peer join return is_new bool, if bucket is full, ping the least recently seen peer,
+    /// new peer will replace it when it not alive.
     pub fn add_peer(&mut self, pk: &PublicKey, socket_addr: SocketAddr) -> bool {
-        self.cells.insert(self.cells.new(pk, socket_addr))
+        match self.cells.insert(pk, socket_addr) {
+            Insert::Inserted => {
+                self.metas.insert(pk.clone(), PeerMeta::new(socket_addr));
+                self.changed.insert(pk.clone());
+                self.members_changed = true;
+                true
+            }
+            Insert::Full(lru, lru_socket) => {
+                self.pings.push((lru, lru_socket));
+                false
+            }
+            Insert::Updated => {
+                if let Some(meta) = self.metas.get_mut(pk) {
+                    meta.socket = socket_addr;
+                    self.changed.insert(pk.clone());
+                }
+                false
+            }
+            Insert::Ignored => false,
+        }
     }
 
     /// when peer first join, remeber it's peer_id, and socket_addr
@@ -118,7 +259,6 @@ impl DHTTable {
             if sock.is_some() {
                 return self.add_peer(pk, sock.unwrap());
             }
-            //append_node_list(self.cells.pk(), vec![(pk.clone(), sock)]);
         }
         return false;
     }
@@ -131,9 +271,9 @@ impl DHTTable {
 
     /// get peer's socket addr by public key
     pub fn get_socket_addr(&self, pk: &PublicKey) -> Option<SocketAddr> {
-        match self.cells.search(pk) {
-            Some((socket, true)) => Some(socket),
-            Some((_, false)) | None => match self.tmp_cells.get(&pk) {
+        match self.cells.get(pk) {
+            Some(socket) => Some(socket),
+            None => match self.tmp_cells.get(&pk) {
                 Some(cell) => *cell,
                 None => None,
             },
@@ -148,13 +288,79 @@ impl DHTTable {
         self.cells.contains(pk)
     }
 
-    /// Help Peer Build DHT
-    pub fn _dht_help(&self, _pk: &PublicKey) -> Vec<(PublicKey, SocketAddr)> {
-        // TODO
+    /// all fixed peers
+    pub fn peers(&self) -> Vec<(PublicKey, SocketAddr)> {
+        self.cells.all()
+    }
+
+    /// fixed peers alive in max age seconds, with last seen time.
+    pub fn recent_peers(&self, max_age: u64) -> Vec<(PublicKey, SocketAddr, u64)> {
+        let now = now_secs();
         self.cells
             .all()
-            .iter()
-            .map(|(pk, socket)| ((*pk).clone(), (*socket).clone()))
+            .into_iter()
+            .filter_map(|(pk, socket)| {
+                self.metas
+                    .get(&pk)
+                    .filter(|meta| meta.failures == 0 && meta.last_seen + max_age >= now)
+                    .map(|meta| (pk, socket, meta.last_seen))
+            })
             .collect()
     }
+
+    /// n fixed peers closest to target key
+    pub fn closest(&self, target: &[u8], n: usize) -> Vec<(PublicKey, SocketAddr)> {
+        self.cells.closest(target, n)
+    }
+
+    /// keys need lookup for refresh buckets
+    pub fn refreshes(&mut self) -> Vec<H256> {
+        self.cells.refreshes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::keypair::PrivateKey;
+
+    #[test]
+    fn test_table_liveness() {
+        let base = PrivateKey::generate().generate_public_key();
+        let socket: SocketAddr = "127.0.0.1:7364".parse().unwrap();
+        let (a, b) = (
+            PrivateKey::generate().generate_public_key(),
+            PrivateKey::generate().generate_public_key(),
+        );
+
+        let mut table = DHTTable::new(&base);
+        table.add_peer(&a, socket);
+        table.add_peer(&b, socket);
+        let (peers, metas, removed) = table.take_changes();
+        assert_eq!(peers.unwrap().len(), 2);
+        assert_eq!(metas.len(), 2);
+        assert!(removed.is_empty());
+
+        // only liveness changed, peers not changed
+        table.update_hb_peers(&a);
+        let (peers, metas, _) = table.take_changes();
+        assert!(peers.is_none());
+        assert_eq!(metas[0].0, a);
+
+        // restored stale peer is probed first
+        let mut stale = PeerMeta::new(socket);
+        stale.last_seen -= 3600;
+        let mut restored = DHTTable::new(&base);
+        restored.restore(vec![(a.clone(), PeerMeta::new(socket)), (b.clone(), stale)]);
+        match &restored.next_probes().0[0] {
+            Probe::Ping(_, pk, _) => assert_eq!(pk, &b),
+            _ => panic!("need ping first"),
+        }
+        assert!(restored.take_changes().0.is_none());
+
+        table.remove_peer(&b);
+        let (peers, _, removed) = table.take_changes();
+        assert_eq!(peers.unwrap(), vec![a]);
+        assert_eq!(removed, vec![b]);
+    }
 }
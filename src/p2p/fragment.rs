@@ -0,0 +1,346 @@
+use byteorder::{BigEndian, ByteOrder};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// fragment head: message id (8 bytes), index (2 bytes), count (2 bytes).
+pub const FRAGMENT_HEAD_LENGTH: usize = 8 + 2 + 2;
+
+/// partial message not completed after this time will evict.
+const FRAGMENT_TIMEOUT: u64 = 10;
+
+/// max partial messages in flight from one source.
+const MAX_PARTIALS_PER_SOURCE: usize = 32;
+
+/// max bytes of partial messages from one source.
+const MAX_BYTES_PER_SOURCE: usize = 8 * 1024 * 1024;
+
+/// max sources have partial messages at same time, more will evict the oldest.
+const MAX_SOURCES: usize = 1024;
+
+/// max bytes of partial messages from all sources, more will evict the oldest.
+const MAX_TOTAL_BYTES: usize = 64 * 1024 * 1024;
+
+/// max bytes of one message, bigger will not send.
+const MAX_MESSAGE_SIZE: usize = MAX_BYTES_PER_SOURCE;
+
+/// min fragment size, smaller fragment size will use it.
+const MIN_FRAGMENT_SIZE: usize = 512;
+
+/// max fragments of one message.
+const MAX_FRAGMENTS: usize = MAX_MESSAGE_SIZE / (MIN_FRAGMENT_SIZE - FRAGMENT_HEAD_LENGTH) + 1;
+
+/// split bytes to fragments, every fragment has fragment head.
+pub fn split(bytes: &[u8], fragment_size: usize) -> Vec<Vec<u8>> {
+    if bytes.len() > MAX_MESSAGE_SIZE {
+        return vec![];
+    }
+
+    let payload_size = std::cmp::max(fragment_size, MIN_FRAGMENT_SIZE) - FRAGMENT_HEAD_LENGTH;
+    let chunks: Vec<&[u8]> = if bytes.is_empty() {
+        vec![bytes]
+    } else {
+        bytes.chunks(payload_size).collect()
+    };
+
+    let id: u64 = rand::random();
+    let count = chunks.len();
+
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let mut head = [0u8; FRAGMENT_HEAD_LENGTH];
+            BigEndian::write_u64(&mut head[0..8], id);
+            BigEndian::write_u16(&mut head[8..10], index as u16);
+            BigEndian::write_u16(&mut head[10..12], count as u16);
+
+            let mut fragment = head.to_vec();
+            fragment.extend_from_slice(chunk);
+            fragment
+        })
+        .collect()
+}
+
+/// message id of the fragment.
+pub fn message_id(fragment: &[u8]) -> Option<u64> {
+    if fragment.len() < FRAGMENT_HEAD_LENGTH {
+        None
+    } else {
+        Some(BigEndian::read_u64(&fragment[0..8]))
+    }
+}
+
+struct Partial {
+    fragments: Vec<Option<Vec<u8>>>,
+    received: usize,
+    size: usize,
+    start: Instant,
+}
+
+struct Source {
+    partials: HashMap<u64, Partial>,
+    size: usize,
+    last: Instant,
+}
+
+impl Source {
+    fn new() -> Self {
+        Source {
+            partials: HashMap::new(),
+            size: 0,
+            last: Instant::now(),
+        }
+    }
+
+    fn remove(&mut self, id: &u64) -> Option<Partial> {
+        self.partials.remove(id).map(|partial| {
+            self.size -= partial.size;
+            partial
+        })
+    }
+
+    /// receive one fragment of message, if message is completed, return it.
+    fn receive(&mut self, id: u64, index: usize, count: usize, payload: &[u8]) -> Option<Vec<u8>> {
+        let slots = count * std::mem::size_of::<Option<Vec<u8>>>();
+        self.last = Instant::now();
+
+        if !self.partials.contains_key(&id) {
+            if self.partials.len() >= MAX_PARTIALS_PER_SOURCE {
+                self.remove_oldest();
+            }
+
+            while self.size + slots > MAX_BYTES_PER_SOURCE && !self.partials.is_empty() {
+                self.remove_oldest();
+            }
+
+            self.partials.insert(
+                id,
+                Partial {
+                    fragments: vec![None; count],
+                    received: 0,
+                    size: slots,
+                    start: Instant::now(),
+                },
+            );
+            self.size += slots;
+        }
+
+        while self.size + payload.len() > MAX_BYTES_PER_SOURCE && self.partials.len() > 1 {
+            self.remove_oldest();
+        }
+        if self.size + payload.len() > MAX_BYTES_PER_SOURCE {
+            self.remove(&id);
+            return None;
+        }
+
+        let partial = self.partials.get_mut(&id)?;
+        if partial.fragments.len() != count || partial.fragments[index].is_some() {
+            return None;
+        }
+
+        partial.fragments[index] = Some(payload.to_vec());
+        partial.received += 1;
+        partial.size += payload.len();
+        self.size += payload.len();
+
+        if partial.received < count {
+            return None;
+        }
+
+        self.remove(&id).map(|partial| {
+            partial
+                .fragments
+                .into_iter()
+                .filter_map(|fragment| fragment)
+                .flatten()
+                .collect()
+        })
+    }
+
+    fn remove_oldest(&mut self) {
+        let oldest = self
+            .partials
+            .iter()
+            .min_by_key(|(_, partial)| partial.start)
+            .map(|(id, _)| *id);
+
+        if let Some(id) = oldest {
+            self.remove(&id);
+        }
+    }
+}
+
+/// reassembly fragments by source and message id, fragments can receive
+/// out of order, and partial messages is limited and evicted when timeout.
+/// the memory of partial message include the fragment slots.
+#[derive(Default)]
+pub struct Reassembler {
+    sources: HashMap<SocketAddr, Source>,
+    size: usize,
+}
+
+impl Reassembler {
+    /// receive one fragment, if message is completed, return it.
+    pub fn receive(&mut self, socket: SocketAddr, bytes: &[u8]) -> Option<Vec<u8>> {
+        if bytes.len() < FRAGMENT_HEAD_LENGTH {
+            return None;
+        }
+
+        let id = BigEndian::read_u64(&bytes[0..8]);
+        let index = BigEndian::read_u16(&bytes[8..10]) as usize;
+        let count = BigEndian::read_u16(&bytes[10..12]) as usize;
+        let payload = &bytes[FRAGMENT_HEAD_LENGTH..];
+
+        if count == 0 || index >= count || count > MAX_FRAGMENTS {
+            return None;
+        }
+
+        if count == 1 {
+            return Some(payload.to_vec());
+        }
+
+        if !self.sources.contains_key(&socket) && self.sources.len() >= MAX_SOURCES {
+            // source address can be spoofed, so evict the oldest, not reject the new
+            let oldest = self
+                .sources
+                .iter()
+                .min_by_key(|(_, source)| source.last)
+                .map(|(socket, _)| *socket);
+            if let Some(source) = oldest.and_then(|oldest| self.sources.remove(&oldest)) {
+                self.size -= source.size;
+            }
+        }
+
+        let slots = count * std::mem::size_of::<Option<Vec<u8>>>();
+        let is_new = self
+            .sources
+            .get(&socket)
+            .map(|source| !source.partials.contains_key(&id))
+            .unwrap_or(true);
+        let needed = payload.len() + if is_new { slots } else { 0 };
+        while self.size + needed > MAX_TOTAL_BYTES && self.remove_oldest(&socket, id) {}
+
+        let source = self.sources.entry(socket).or_insert_with(Source::new);
+        let before = source.size;
+        let data = source.receive(id, index, count, payload);
+        self.size = self.size + source.size - before;
+        data
+    }
+
+    /// remove the oldest partial message of all sources, except the receiving one.
+    fn remove_oldest(&mut self, socket: &SocketAddr, id: u64) -> bool {
+        let oldest = self
+            .sources
+            .iter()
+            .flat_map(|(s, source)| {
+                source
+                    .partials
+                    .iter()
+                    .map(move |(i, partial)| (partial.start, *s, *i))
+            })
+            .filter(|(_, s, i)| s != socket || *i != id)
+            .min_by_key(|(start, _, _)| *start);
+
+        match oldest {
+            Some((_, s, i)) => {
+                if let Some(partial) = self
+                    .sources
+                    .get_mut(&s)
+                    .and_then(|source| source.remove(&i))
+                {
+                    self.size -= partial.size;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// evict partial messages which is timeout.
+    pub fn evict(&mut self) {
+        let now = Instant::now();
+        for source in self.sources.values_mut() {
+            let timeouts: Vec<u64> = source
+                .partials
+                .iter()
+                .filter(|(_, partial)| {
+                    now.duration_since(partial.start) > Duration::new(FRAGMENT_TIMEOUT, 0)
+                })
+                .map(|(id, _)| *id)
+                .collect();
+
+            for id in timeouts {
+                source.remove(&id);
+            }
+        }
+
+        self.sources.retain(|_, source| !source.partials.is_empty());
+        self.size = self.sources.values().map(|source| source.size).sum();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_reassembly_out_of_order() {
+        let socket: SocketAddr = "127.0.0.1:7364".parse().unwrap();
+        let bytes: Vec<u8> = (0..5000u32).map(|i| i as u8).collect();
+        let mut fragments = split(&bytes, 600);
+        fragments.reverse();
+
+        let mut reassembler = Reassembler::default();
+        let last = fragments.pop().unwrap();
+        for fragment in fragments {
+            assert_eq!(reassembler.receive(socket, &fragment), None);
+        }
+        assert_eq!(reassembler.receive(socket, &last), Some(bytes));
+
+        // too many fragments is dropped before allocate
+        let mut fragment = split(&[0u8; 1000], 600).remove(0);
+        BigEndian::write_u16(&mut fragment[10..12], u16::max_value());
+        assert_eq!(reassembler.receive(socket, &fragment), None);
+        assert!(reassembler.sources[&socket].partials.is_empty());
+
+        // new source evict the oldest when sources full
+        for port in 0..MAX_SOURCES + 1 {
+            let socket = SocketAddr::new(socket.ip(), port as u16);
+            assert_eq!(
+                reassembler.receive(socket, &split(&[0u8; 1000], 600)[0]),
+                None
+            );
+        }
+        assert_eq!(reassembler.sources.len(), MAX_SOURCES);
+        assert!(!reassembler
+            .sources
+            .contains_key(&SocketAddr::new(socket.ip(), 0)));
+    }
+
+    #[test]
+    fn test_reassembly_total_bytes() {
+        let ip = "127.0.0.1".parse().unwrap();
+        let bytes = vec![0u8; MAX_BYTES_PER_SOURCE - 1024 * 1024];
+        let mut reassembler = Reassembler::default();
+
+        // every source has one partial message, the oldest is evicted when total full
+        let sources = MAX_TOTAL_BYTES / bytes.len() + 1;
+        for port in 0..sources {
+            let socket = SocketAddr::new(ip, port as u16);
+            let mut fragments = split(&bytes, 60000);
+            fragments.pop();
+            for fragment in fragments {
+                assert_eq!(reassembler.receive(socket, &fragment), None);
+            }
+            assert!(reassembler.size <= MAX_TOTAL_BYTES);
+        }
+
+        let first = SocketAddr::new(ip, 0);
+        let last = SocketAddr::new(ip, sources as u16 - 1);
+        assert!(reassembler.sources[&first].partials.is_empty());
+        assert_eq!(reassembler.sources[&last].partials.len(), 1);
+        let total: usize = reassembler.sources.values().map(|s| s.size).sum();
+        assert_eq!(reassembler.size, total);
+    }
+}
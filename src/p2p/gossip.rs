@@ -0,0 +1,271 @@
+use rand::seq::SliceRandom;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::crypto::hash::H256;
+use crate::crypto::keypair::{PrivateKey, PublicKey, Signature};
+use crate::primitives::types::{EventByte, GroupID};
+
+use super::dht::now_secs;
+
+/// peers will eager push the full message.
+const EAGER_FANOUT: usize = 6;
+
+/// peers will lazy push the message id.
+const LAZY_FANOUT: usize = 6;
+
+/// max hops of one message, after it only lazy push.
+const MAX_HOPS: u8 = 16;
+
+/// message cached time, for deduplicate and pull.
+const CACHE_TIME: u64 = 120;
+
+/// max cached messages.
+const MAX_CACHES: usize = 10000;
+
+/// wait eager push after receive message id, if not received, pull it.
+const PULL_TIMEOUT: u64 = 1;
+
+/// max missing message ids waiting for pull.
+const MAX_MISSINGS: usize = 1000;
+
+/// broadcast message, signed by origin, relay peers can not change it,
+/// timestamp is origin created time (seconds), the old message is dropped.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GossipItem {
+    pub origin: PublicKey,
+    pub nonce: u64,
+    pub timestamp: u64,
+    pub event: EventByte,
+    pub sign: Signature,
+    pub hops: u8,
+}
+
+impl GossipItem {
+    fn sign_data(
+        group: &GroupID,
+        origin: &PublicKey,
+        nonce: u64,
+        timestamp: u64,
+        event: &EventByte,
+    ) -> Vec<u8> {
+        let mut bytes = group.to_bytes().to_vec();
+        bytes.extend_from_slice(&origin.to_bytes());
+        bytes.extend_from_slice(&nonce.to_be_bytes());
+        bytes.extend_from_slice(&timestamp.to_be_bytes());
+        bytes.extend_from_slice(event);
+        bytes
+    }
+
+    pub fn new(group: &GroupID, psk: &PrivateKey, event: EventByte) -> Self {
+        let origin = psk.generate_public_key();
+        let nonce = rand::random();
+        let timestamp = now_secs();
+        let sign = psk.sign_bytes(&Self::sign_data(group, &origin, nonce, timestamp, &event));
+
+        GossipItem {
+            origin,
+            nonce,
+            timestamp,
+            event,
+            sign,
+            hops: 0,
+        }
+    }
+
+    /// message id, hash of signed data.
+    pub fn id(&self, group: &GroupID) -> H256 {
+        H256::new(&Self::sign_data(
+            group,
+            &self.origin,
+            self.nonce,
+            self.timestamp,
+            &self.event,
+        ))
+    }
+
+    pub fn verify(&self, group: &GroupID) -> bool {
+        self.origin.verify_bytes(
+            &Self::sign_data(group, &self.origin, self.nonce, self.timestamp, &self.event),
+            &self.sign,
+        )
+    }
+
+    /// created in cache time, older one maybe evicted from cache and seen as new.
+    pub fn is_fresh(&self) -> bool {
+        let now = now_secs();
+        self.timestamp + CACHE_TIME > now && self.timestamp < now + CACHE_TIME
+    }
+
+    pub fn can_relay(&self) -> bool {
+        self.hops < MAX_HOPS
+    }
+}
+
+/// gossip overlay of groups, eager push to some random peers,
+/// lazy push message ids to others, and pull the missing.
+#[derive(Default, Clone)]
+pub(crate) struct Gossip {
+    caches: HashMap<H256, (GroupID, GossipItem, Instant)>,
+    /// cached message ids by insert time, oldest first.
+    order: VecDeque<(Instant, H256)>,
+    announces: HashMap<(GroupID, PublicKey), Vec<H256>>,
+    missings: HashMap<H256, (GroupID, PublicKey, Instant)>,
+}
+
+impl Gossip {
+    /// select eager push peers and lazy push peers randomly.
+    pub fn select(
+        mut peers: Vec<PublicKey>,
+        excepts: &[&PublicKey],
+    ) -> (Vec<PublicKey>, Vec<PublicKey>) {
+        peers.retain(|pk| !excepts.contains(&pk));
+        peers.shuffle(&mut rand::thread_rng());
+
+        let lazy = peers.split_off(std::cmp::min(EAGER_FANOUT, peers.len()));
+        let lazy = lazy.into_iter().take(LAZY_FANOUT).collect();
+        (peers, lazy)
+    }
+
+    /// cache new message, return false if it is duplicate or not fresh.
+    pub fn insert(&mut self, group: GroupID, id: H256, item: GossipItem) -> bool {
+        if !item.is_fresh() || self.caches.contains_key(&id) {
+            return false;
+        }
+
+        if self.caches.len() >= MAX_CACHES {
+            self.evict();
+        }
+
+        let now = Instant::now();
+        self.missings.remove(&id);
+        self.order.push_back((now, id.clone()));
+        self.caches.insert(id, (group, item, now));
+        true
+    }
+
+    /// cached message of the group.
+    pub fn get(&self, group: &GroupID, id: &H256) -> Option<&GossipItem> {
+        self.caches
+            .get(id)
+            .filter(|(g, _, _)| g == group)
+            .map(|(_, item, _)| item)
+    }
+
+    /// wait to lazy push message id to peer.
+    pub fn announce(&mut self, group: GroupID, pk: PublicKey, id: H256) {
+        self.announces.entry((group, pk)).or_insert(vec![]).push(id);
+    }
+
+    /// take the waiting message ids need lazy push.
+    pub fn take_announces(&mut self) -> Vec<(GroupID, PublicKey, Vec<H256>)> {
+        self.announces
+            .drain()
+            .map(|((group, pk), ids)| (group, pk, ids))
+            .collect()
+    }
+
+    /// peer have the messages, remember the missing, not more than max missings.
+    pub fn have(&mut self, group: GroupID, pk: PublicKey, ids: Vec<H256>) {
+        let now = Instant::now();
+        for id in ids {
+            if self.missings.len() >= MAX_MISSINGS {
+                break;
+            }
+            if !self.caches.contains_key(&id) && !self.missings.contains_key(&id) {
+                self.missings.insert(id, (group.clone(), pk.clone(), now));
+            }
+        }
+    }
+
+    /// the missing messages not received by eager push in time, need pull.
+    pub fn take_wants(&mut self) -> Vec<(GroupID, PublicKey, Vec<H256>)> {
+        let now = Instant::now();
+        let timeouts: Vec<H256> = self
+            .missings
+            .iter()
+            .filter(|(_, (_, _, ins))| now.duration_since(*ins) > Duration::new(PULL_TIMEOUT, 0))
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        let mut wants: HashMap<(GroupID, PublicKey), Vec<H256>> = HashMap::new();
+        for id in timeouts {
+            if let Some((group, pk, _)) = self.missings.remove(&id) {
+                wants.entry((group, pk)).or_insert(vec![]).push(id);
+            }
+        }
+
+        wants
+            .into_iter()
+            .map(|((group, pk), ids)| (group, pk, ids))
+            .collect()
+    }
+
+    /// remove the timeout cached messages, and the oldest when too many.
+    pub fn evict(&mut self) {
+        let now = Instant::now();
+        while let Some((ins, id)) = self.order.pop_front() {
+            if self.caches.len() < MAX_CACHES
+                && now.duration_since(ins) < Duration::new(CACHE_TIME, 0)
+            {
+                self.order.push_front((ins, id));
+                break;
+            }
+            self.caches.remove(&id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gossip_dedup_and_pull() {
+        let group: GroupID = Default::default();
+        let psk = PrivateKey::generate();
+        let peer = PrivateKey::generate().generate_public_key();
+        let item = GossipItem::new(&group, &psk, vec![1, 2, 3]);
+        let id = item.id(&group);
+        assert!(item.verify(&group));
+
+        let mut gossip = Gossip::default();
+        gossip.have(group.clone(), peer.clone(), vec![id.clone()]);
+        assert!(gossip.insert(group.clone(), id.clone(), item.clone()));
+        assert!(!gossip.insert(group.clone(), id.clone(), item.clone()));
+        assert!(gossip.take_wants().is_empty());
+
+        // old message is dropped, even if not in cache
+        let mut old = item;
+        old.timestamp -= CACHE_TIME;
+        assert!(!old.verify(&group));
+        assert!(!gossip.insert(group.clone(), old.id(&group), old));
+
+        // missing ids is bounded
+        let ids = (0..MAX_MISSINGS + 1)
+            .map(|i| H256::new(&i.to_le_bytes()))
+            .collect();
+        gossip.have(group, peer, ids);
+        assert_eq!(gossip.missings.len(), MAX_MISSINGS);
+    }
+
+    #[test]
+    fn test_gossip_evict_oldest() {
+        let group: GroupID = Default::default();
+        let mut item = GossipItem::new(&group, &PrivateKey::generate(), vec![1]);
+        let mut gossip = Gossip::default();
+        let mut ids = vec![];
+        for i in 0..MAX_CACHES + 1 {
+            item.nonce = i as u64;
+            let id = item.id(&group);
+            assert!(gossip.insert(group.clone(), id.clone(), item.clone()));
+            ids.push(id);
+        }
+
+        assert_eq!(gossip.caches.len(), MAX_CACHES);
+        assert!(gossip.get(&group, &ids[0]).is_none());
+        assert!(gossip.get(&group, &ids[1]).is_some());
+        assert!(gossip.get(&group, &ids[MAX_CACHES]).is_some());
+    }
+}
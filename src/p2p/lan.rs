@@ -0,0 +1,201 @@
+use bytes::BytesMut;
+use futures::Stream;
+use serde_derive::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io::Result;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket as StdUdpSocket};
+use tokio::codec::BytesCodec;
+use tokio::net::{UdpFramed, UdpSocket};
+use tokio::reactor::Handle;
+
+use crate::actor::prelude::*;
+use crate::crypto::keypair::PublicKey;
+use crate::primitives::functions::{try_resend_times, DEFAULT_TIMES};
+use crate::primitives::types::GroupID;
+use crate::traits::actor::P2PBridgeActor;
+
+use super::p2p::P2PActor;
+use super::session::P2PAddrMessage;
+
+/// announce self to local network in this time.
+pub const ANNOUNCE_TIME: u64 = 5;
+
+/// prefix of announcement datagram, other datagram in multicast group is ignored.
+const MAGIC: &[u8] = b"TEATREE-LAN";
+
+/// announcement of node, peers in local network will add it as tmp peer.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct LanAnnounce {
+    pub pk: PublicKey,
+    pub port: u16,
+    pub groups: Vec<GroupID>,
+}
+
+impl LanAnnounce {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.append(&mut bincode::serialize(self).unwrap_or(vec![]));
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() <= MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+            return None;
+        }
+        bincode::deserialize(&bytes[MAGIC.len()..]).ok()
+    }
+}
+
+/// bind the multicast port and join the multicast group on interface,
+/// many nodes in one host can bind the same port.
+pub(crate) fn lan_socket(multicast: SocketAddrV4, interface: Ipv4Addr) -> Result<StdUdpSocket> {
+    let socket = Socket::new(Domain::ipv4(), Type::dgram(), Some(Protocol::udp()))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, multicast.port()).into())?;
+    socket.join_multicast_v4(multicast.ip(), &interface)?;
+    socket.set_multicast_loop_v4(true)?;
+    if !interface.is_unspecified() {
+        socket.set_multicast_if_v4(&interface)?;
+    }
+    Ok(socket.into_udp_socket())
+}
+
+/// self announcement, send to local network.
+#[derive(Clone)]
+pub(crate) struct LanAnnounceMessage(pub PublicKey, pub Vec<GroupID>);
+
+impl Message for LanAnnounceMessage {
+    type Result = ();
+}
+
+/// peer found in local network, send to p2p actor.
+/// Params is peer, p2p socket of peer, groups of peer.
+#[derive(Clone)]
+pub(crate) struct LanPeerMessage(pub PublicKey, pub SocketAddr, pub Vec<GroupID>);
+
+impl Message for LanPeerMessage {
+    type Result = ();
+}
+
+/// datagram received from multicast group.
+struct LanDatagramMessage(BytesMut, SocketAddr);
+
+impl Message for LanDatagramMessage {
+    type Result = ();
+}
+
+/// local network discovery, announce self on multicast group,
+/// and send the peers found to p2p actor.
+pub struct LanDiscoveryActor<A: P2PBridgeActor> {
+    port: u16,
+    multicast: SocketAddrV4,
+    sender: StdUdpSocket,
+    p2p_addr: Option<Addr<P2PActor<A>>>,
+}
+
+impl<A: P2PBridgeActor> LanDiscoveryActor<A> {
+    /// start discovery, port is the p2p port announced to peers.
+    pub fn start(
+        port: u16,
+        multicast: SocketAddrV4,
+        interface: Ipv4Addr,
+    ) -> Result<Addr<LanDiscoveryActor<A>>> {
+        let socket = lan_socket(multicast, interface)?;
+        let sender = socket.try_clone()?;
+        let socket = UdpSocket::from_std(socket, &Handle::default())?;
+
+        Ok(LanDiscoveryActor::create(move |ctx| {
+            let (_, stream) = UdpFramed::new(socket, BytesCodec::new()).split();
+            ctx.add_stream(stream.map(|(data, sender)| LanDatagramMessage(data, sender)));
+            LanDiscoveryActor {
+                port,
+                multicast,
+                sender,
+                p2p_addr: None,
+            }
+        }))
+    }
+}
+
+impl<A: P2PBridgeActor> Actor for LanDiscoveryActor<A> {
+    type Context = Context<Self>;
+}
+
+impl<A: P2PBridgeActor> Handler<P2PAddrMessage<A>> for LanDiscoveryActor<A> {
+    type Result = ();
+
+    fn handle(&mut self, msg: P2PAddrMessage<A>, _ctx: &mut Context<Self>) {
+        self.p2p_addr = Some(msg.0);
+    }
+}
+
+/// send self announcement to multicast group.
+impl<A: P2PBridgeActor> Handler<LanAnnounceMessage> for LanDiscoveryActor<A> {
+    type Result = ();
+
+    fn handle(&mut self, msg: LanAnnounceMessage, _ctx: &mut Context<Self>) {
+        let announce = LanAnnounce {
+            pk: msg.0,
+            port: self.port,
+            groups: msg.1,
+        };
+        if let Err(e) = self.sender.send_to(&announce.encode(), self.multicast) {
+            println!("DEBUG: lan announce fail: {}", e);
+        }
+    }
+}
+
+/// when receive announcement, send the peer to p2p actor,
+/// peer socket is the source ip and announced p2p port.
+impl<A: P2PBridgeActor> StreamHandler<LanDatagramMessage, std::io::Error> for LanDiscoveryActor<A> {
+    fn handle(&mut self, msg: LanDatagramMessage, _ctx: &mut Context<Self>) {
+        let (data, source) = (msg.0, msg.1);
+        if let Some(announce) = LanAnnounce::decode(&data) {
+            if let Some(addr) = self.p2p_addr.clone() {
+                let socket = SocketAddr::new(source.ip(), announce.port);
+                let _ = try_resend_times(
+                    addr,
+                    LanPeerMessage(announce.pk, socket, announce.groups),
+                    DEFAULT_TIMES,
+                )
+                .map_err(|_| println!("Send Message to p2p fail"));
+            }
+        }
+    }
+
+    /// udp receive error, keep receiving.
+    fn error(&mut self, err: std::io::Error, _ctx: &mut Self::Context) -> Running {
+        println!("DEBUG: lan receive error: {}", err);
+        Running::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::keypair::PrivateKey;
+    use std::time::Duration;
+
+    #[test]
+    fn test_lan_announce_on_loopback() {
+        let multicast = SocketAddrV4::new(Ipv4Addr::new(239, 255, 70, 77), 17365);
+        let a = lan_socket(multicast, Ipv4Addr::LOCALHOST).unwrap();
+        let b = lan_socket(multicast, Ipv4Addr::LOCALHOST).unwrap();
+        b.set_read_timeout(Some(Duration::new(5, 0))).unwrap();
+
+        let announce = LanAnnounce {
+            pk: PrivateKey::generate().generate_public_key(),
+            port: 7364,
+            groups: vec![Default::default()],
+        };
+        a.send_to(&announce.encode(), multicast).unwrap();
+
+        let mut buf = [0u8; 1024];
+        let (size, source) = b.recv_from(&mut buf).unwrap();
+        assert_eq!(LanAnnounce::decode(&buf[..size]), Some(announce));
+        assert!(source.ip().is_loopback());
+        assert_eq!(LanAnnounce::decode(b"other"), None);
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::crypto::keypair::PublicKey;
+use crate::primitives::types::GroupID;
+
+/// throttled peer, if throttle times more than limit in this time, will disconnect.
+const THROTTLE_WINDOW: u64 = 60;
+
+/// disconnected peer will be blocked in this time.
+const BLOCK_TIME: u64 = 300;
+
+/// idle limit state will evict after this time.
+const IDLE_TIME: u64 = 120;
+
+/// first pacing rate (bytes/second) of new socket.
+const INITIAL_RATE: f64 = 256.0 * 1024.0;
+
+/// min pacing rate (bytes/second).
+const MIN_RATE: f64 = 16.0 * 1024.0;
+
+/// max pacing rate (bytes/second).
+const MAX_RATE: f64 = 64.0 * 1024.0 * 1024.0;
+
+/// additive increase (bytes/second) when peer ack.
+const ADDITIVE_INCREASE: f64 = 16.0 * 1024.0;
+
+/// multiplicative decrease when lost.
+const MULTIPLICATIVE_DECREASE: f64 = 0.5;
+
+/// max bytes waiting in pacing queue of one socket.
+const MAX_QUEUE_BYTES: usize = 4 * 1024 * 1024;
+
+/// token bucket, tokens refill by rate every second, max is burst.
+#[derive(Clone)]
+pub(crate) struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: f64, burst: f64) -> Self {
+        TokenBucket {
+            rate,
+            burst,
+            tokens: burst,
+            last: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last);
+        let seconds = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+        self.tokens = (self.tokens + seconds * self.rate).min(self.burst);
+        self.last = now;
+    }
+
+    /// take n tokens, if not enough, return false and take nothing.
+    pub fn take(&mut self, n: f64) -> bool {
+        self.refill();
+        if self.tokens >= n {
+            self.tokens -= n;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// take n tokens when have any, tokens can be negative,
+    /// so item bigger than burst also can pass.
+    pub fn take_deficit(&mut self, n: f64) -> bool {
+        self.refill();
+        if self.tokens > 0.0 {
+            self.tokens -= n;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    pub fn set_rate(&mut self, rate: f64, burst: f64) {
+        self.refill();
+        self.rate = rate;
+        self.burst = burst;
+        self.tokens = self.tokens.min(burst);
+    }
+}
+
+/// result of inbound limit check.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Limit {
+    Pass,
+    Throttle,
+    Disconnect,
+}
+
+#[derive(Clone)]
+struct PeerLimit {
+    messages: TokenBucket,
+    bytes: TokenBucket,
+    throttles: u32,
+    since: Instant,
+    last: Instant,
+    blocked: Option<Instant>,
+}
+
+/// inbound rate limit, messages and bytes per second, by peer and by group.
+#[derive(Clone)]
+pub(crate) struct InboundLimiter {
+    peer_rates: (f64, f64),
+    group_rates: (f64, f64),
+    throttle_limit: u32,
+    peers: HashMap<PublicKey, PeerLimit>,
+    groups: HashMap<GroupID, (TokenBucket, TokenBucket)>,
+}
+
+impl InboundLimiter {
+    pub fn new(peer_rates: (u32, u32), group_rates: (u32, u32), throttle_limit: u32) -> Self {
+        InboundLimiter {
+            peer_rates: (peer_rates.0 as f64, peer_rates.1 as f64),
+            group_rates: (group_rates.0 as f64, group_rates.1 as f64),
+            throttle_limit,
+            peers: HashMap::new(),
+            groups: HashMap::new(),
+        }
+    }
+
+    /// check one message with bytes from peer.
+    pub fn check(&mut self, pk: &PublicKey, bytes: usize) -> Limit {
+        let now = Instant::now();
+        let (message_rate, byte_rate) = self.peer_rates;
+        let peer = self.peers.entry(pk.clone()).or_insert(PeerLimit {
+            messages: TokenBucket::new(message_rate, message_rate),
+            bytes: TokenBucket::new(byte_rate, byte_rate),
+            throttles: 0,
+            since: now,
+            last: now,
+            blocked: None,
+        });
+        peer.last = now;
+
+        if let Some(ins) = peer.blocked {
+            if now.duration_since(ins) < Duration::new(BLOCK_TIME, 0) {
+                return Limit::Throttle;
+            }
+            peer.blocked = None;
+        }
+
+        // bytes take deficit, the message bigger than burst also can pass.
+        if !peer.messages.take(1.0) || !peer.bytes.take_deficit(bytes as f64) {
+            if now.duration_since(peer.since) > Duration::new(THROTTLE_WINDOW, 0) {
+                peer.throttles = 0;
+                peer.since = now;
+            }
+            peer.throttles += 1;
+
+            if peer.throttles >= self.throttle_limit {
+                peer.throttles = 0;
+                peer.blocked = Some(now);
+                return Limit::Disconnect;
+            }
+            return Limit::Throttle;
+        }
+
+        Limit::Pass
+    }
+
+    /// check one message with bytes of group members, return false if group over limit,
+    /// it is not the fault of the peer.
+    pub fn check_group(&mut self, group: &GroupID, bytes: usize) -> bool {
+        let (message_rate, byte_rate) = self.group_rates;
+        let (messages, bytes_bucket) = self.groups.entry(group.clone()).or_insert((
+            TokenBucket::new(message_rate, message_rate),
+            TokenBucket::new(byte_rate, byte_rate),
+        ));
+
+        messages.take(1.0) && bytes_bucket.take_deficit(bytes as f64)
+    }
+
+    /// evict idle peers which not blocked.
+    pub fn evict(&mut self) {
+        let now = Instant::now();
+        self.peers.retain(|_, peer| {
+            peer.blocked.is_some() || now.duration_since(peer.last) < Duration::new(IDLE_TIME, 0)
+        });
+    }
+}
+
+/// outbound pacing of one socket, rate is changed by AIMD,
+/// additive increase when peer ack, multiplicative decrease when lost.
+pub(crate) struct Pacer {
+    bucket: TokenBucket,
+    queue: VecDeque<Vec<u8>>,
+    queued: usize,
+    last: Instant,
+}
+
+impl Pacer {
+    pub fn new() -> Self {
+        Pacer {
+            bucket: TokenBucket::new(INITIAL_RATE, Self::burst(INITIAL_RATE)),
+            queue: VecDeque::new(),
+            queued: 0,
+            last: Instant::now(),
+        }
+    }
+
+    /// burst of rate, tokens of 50 millis.
+    fn burst(rate: f64) -> f64 {
+        rate / 20.0
+    }
+
+    /// push fragments to queue, return false if queue is full and fragments dropped.
+    pub fn push(&mut self, fragments: Vec<Vec<u8>>) -> bool {
+        let size: usize = fragments.iter().map(|f| f.len()).sum();
+        if self.queued + size > MAX_QUEUE_BYTES {
+            return false;
+        }
+
+        self.queued += size;
+        self.queue.extend(fragments);
+        self.last = Instant::now();
+        true
+    }
+
+    /// put back the fragments which send fail, they will send first.
+    pub fn requeue(&mut self, fragments: Vec<Vec<u8>>) {
+        for fragment in fragments.into_iter().rev() {
+            self.queued += fragment.len();
+            self.queue.push_front(fragment);
+        }
+        self.last = Instant::now();
+    }
+
+    /// drop the queued fragments which matched.
+    pub fn drop_fragments<F: Fn(&[u8]) -> bool>(&mut self, matched: F) {
+        let mut queued = 0;
+        self.queue.retain(|fragment| {
+            let keep = !matched(fragment);
+            if keep {
+                queued += fragment.len();
+            }
+            keep
+        });
+        self.queued = queued;
+    }
+
+    /// the fragments can send now.
+    pub fn ready(&mut self) -> Vec<Vec<u8>> {
+        let mut fragments = vec![];
+        while let Some(size) = self.queue.front().map(|f| f.len()) {
+            if !self.bucket.take_deficit(size as f64) {
+                break;
+            }
+            self.queued -= size;
+            fragments.push(self.queue.pop_front().unwrap());
+        }
+        fragments
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.is_empty() && Instant::now().duration_since(self.last) > Duration::new(IDLE_TIME, 0)
+    }
+
+    pub fn increase(&mut self) {
+        let rate = (self.bucket.rate() + ADDITIVE_INCREASE).min(MAX_RATE);
+        self.bucket.set_rate(rate, Self::burst(rate));
+    }
+
+    pub fn decrease(&mut self) {
+        let rate = (self.bucket.rate() * MULTIPLICATIVE_DECREASE).max(MIN_RATE);
+        self.bucket.set_rate(rate, Self::burst(rate));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::keypair::PrivateKey;
+
+    #[test]
+    fn test_inbound_throttle_and_disconnect() {
+        let group: GroupID = Default::default();
+        let pk: PublicKey = Default::default();
+        let mut limiter = InboundLimiter::new((2, 1024), (100, 1024 * 1024), 3);
+
+        assert_eq!(limiter.check(&pk, 10), Limit::Pass);
+        assert_eq!(limiter.check(&pk, 10), Limit::Pass);
+        assert_eq!(limiter.check(&pk, 10), Limit::Throttle);
+        assert_eq!(limiter.check(&pk, 10), Limit::Throttle);
+        assert_eq!(limiter.check(&pk, 10), Limit::Disconnect);
+        assert_eq!(limiter.check(&pk, 10), Limit::Throttle);
+
+        // message bigger than byte burst can pass, then throttled until refilled
+        let other = PrivateKey::generate().generate_public_key();
+        assert_eq!(limiter.check(&other, 4096), Limit::Pass);
+        assert_eq!(limiter.check(&other, 10), Limit::Throttle);
+
+        // group over limit not throttle the peer
+        let mut limiter = InboundLimiter::new((100, 1024 * 1024), (1, 1024), 3);
+        assert!(limiter.check_group(&group, 10));
+        assert!(!limiter.check_group(&group, 10));
+        assert_eq!(limiter.check(&pk, 10), Limit::Pass);
+    }
+
+    #[test]
+    fn test_pacer_drop_fragments() {
+        let mut pacer = Pacer::new();
+        assert!(pacer.push(vec![vec![1, 0], vec![2, 0, 0], vec![1, 1]]));
+        pacer.drop_fragments(|f| f[0] == 1);
+        assert_eq!(pacer.queued, 3);
+        assert_eq!(pacer.ready(), vec![vec![2, 0, 0]]);
+        assert!(pacer.is_empty());
+    }
+}
@@ -1,46 +1,141 @@
 use futures::Stream;
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use tokio::codec::BytesCodec;
-use tokio::net::{UdpFramed, UdpSocket};
+use tokio::net::{TcpListener, UdpFramed};
 
 use crate::actor::prelude::*;
-use crate::crypto::keypair::PrivateKey;
+use crate::config::P2PConfig;
+use crate::crypto::keypair::{PrivateKey, PublicKey};
+use crate::primitives::types::GroupID;
 use crate::traits::actor::P2PBridgeActor;
 
+mod bootstrap;
 mod codec;
 mod content;
 mod dht;
+mod fragment;
+mod gossip;
+mod lan;
+mod limit;
+mod mtu;
+mod nat;
 mod p2p;
+mod pex;
+mod puzzle;
+mod relay;
+mod reliable;
+mod route;
+mod score;
+mod secure;
 mod session;
+mod tcp;
+mod transport;
+mod version;
 
+pub use lan::LanDiscoveryActor;
 pub use p2p::P2PActor;
 pub use session::{CodecMessage, P2PSessionActor};
+pub use tcp::TcpTransportActor;
+pub use transport::TransportType;
+
+use tcp::TcpConnectMessage;
+use transport::Transport;
 
 pub fn p2p_start<B: P2PBridgeActor>(
     p2p_socket: SocketAddr,
     psk: Option<PrivateKey>,
+    config: P2PConfig,
+    bootstrap_peers: Vec<(PublicKey, SocketAddr)>,
+    bootstrap_groups: Vec<GroupID>,
 ) -> Addr<P2PActor<B>> {
     // bind to udp
-    let sock =
-        UdpSocket::bind(&p2p_socket).expect(&format!("P2P Socket bind: {} fail!", p2p_socket));
+    let (sock, dup) =
+        session::bind_udp(&p2p_socket).expect(&format!("P2P Socket bind: {} fail!", p2p_socket));
 
     // start p2p session
+    let fragment_size = config.fragment_size;
     let (sink, stream) = UdpFramed::new(sock, BytesCodec::new()).split();
-    let session_addr = P2PSessionActor::create(|ctx| {
+    let session_addr = P2PSessionActor::create(move |ctx| {
         ctx.set_mailbox_capacity(100);
-        ctx.add_stream(stream.map(|(data, sender)| CodecMessage(data, sender)));
+        let handle = ctx.add_stream(stream.map(|(data, sender)| CodecMessage(data, sender)));
         P2PSessionActor {
+            p2p_socket: p2p_socket,
+            stream: Some(handle),
             sinks: vec![sink],
+            socket: dup,
             p2p_addr: None,
             waitings: vec![],
             receivings: Default::default(),
+            fragment_size: fragment_size,
+            mtus: Default::default(),
+            pacers: Default::default(),
+            failures: Default::default(),
+            banned: Default::default(),
         }
     });
 
+    let mut transports = HashMap::new();
+    let (udp_type, udp) = Transport::new(session_addr.clone());
+    transports.insert(udp_type, udp);
+
+    // start tcp transport, listen tcp on same socket, if fail, only use udp
+    if config.tcp || config.transport == TransportType::TCP {
+        match TcpListener::bind(&p2p_socket) {
+            Ok(listener) => {
+                let tcp_addr = TcpTransportActor::create(|ctx| {
+                    ctx.set_mailbox_capacity(100);
+                    ctx.add_message_stream(listener.incoming().map_err(|_| ()).filter_map(
+                        |stream| {
+                            stream
+                                .peer_addr()
+                                .ok()
+                                .map(|socket| TcpConnectMessage(stream, socket))
+                        },
+                    ));
+                    TcpTransportActor::new()
+                });
+                let (tcp_type, tcp) = Transport::new(tcp_addr);
+                transports.insert(tcp_type, tcp);
+                println!("DEBUG: P2P TCP listen: {}", p2p_socket);
+            }
+            Err(e) => println!("DEBUG: P2P TCP Socket bind: {} fail: {}", p2p_socket, e),
+        }
+    }
+
+    // start local network discovery, it is optional, so only warn when fail
+    let lan = if config.lan_discovery {
+        match LanDiscoveryActor::start(
+            p2p_socket.port(),
+            config.lan_multicast,
+            config.lan_interface,
+        ) {
+            Ok(addr) => {
+                println!("DEBUG: P2P LAN discovery: {}", config.lan_multicast);
+                Some(addr)
+            }
+            Err(e) => {
+                println!("DEBUG: P2P LAN discovery fail: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
     println!("DEBUG: P2P listen: {}", p2p_socket);
     // start p2p actor
-    P2PActor::create(|ctx| {
+    P2PActor::create(move |ctx| {
         ctx.set_mailbox_capacity(100);
-        P2PActor::load(session_addr, psk)
+        P2PActor::load(
+            session_addr,
+            transports,
+            p2p_socket,
+            psk,
+            config,
+            bootstrap_peers,
+            bootstrap_groups,
+            lan,
+        )
     })
 }
@@ -0,0 +1,137 @@
+use std::time::{Duration, Instant};
+
+use super::codec::HEAD_LENGTH;
+use super::fragment::FRAGMENT_HEAD_LENGTH;
+
+/// datagram sizes will probe, from small to big. the socket not set DF,
+/// bigger probe will pass by ip fragmentation, so max is ethernet mtu without ip and udp head.
+const PROBE_SIZES: [usize; 3] = [1280, 1400, 1472];
+
+/// probe not ack after this time, will retry.
+const PROBE_TIMEOUT: u64 = 3;
+
+/// probe retry times, if all lost, the size is too big.
+const PROBE_RETRIES: u32 = 2;
+
+/// probe again from begin after this time, path maybe changed.
+const REPROBE_TIME: u64 = 600;
+
+/// bytes of probe content except padding: enum index, size, padding length.
+const PROBE_CONTENT_LENGTH: usize = 4 + 4 + 8;
+
+/// path mtu probing state with one peer socket.
+#[derive(Clone)]
+pub(crate) struct PathMtu {
+    size: usize,
+    ceiling: usize,
+    probing: Option<(usize, Instant, u32)>,
+    start: Instant,
+}
+
+impl PathMtu {
+    pub fn new(base: usize) -> Self {
+        PathMtu {
+            size: base,
+            ceiling: usize::max_value(),
+            probing: None,
+            start: Instant::now(),
+        }
+    }
+
+    /// the largest datagram size confirmed.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// next probe size need send, None if probing is done or waiting ack.
+    pub fn next_probe(&mut self) -> Option<usize> {
+        let now = Instant::now();
+        if now.duration_since(self.start) > Duration::new(REPROBE_TIME, 0) {
+            self.ceiling = usize::max_value();
+            self.probing = None;
+            self.start = now;
+        }
+
+        if let Some((size, ins, tries)) = self.probing {
+            if now.duration_since(ins) < Duration::new(PROBE_TIMEOUT, 0) {
+                return None;
+            }
+
+            if tries >= PROBE_RETRIES {
+                // all probes lost, this size cannot pass the path
+                self.ceiling = size;
+                self.probing = None;
+            } else {
+                self.probing = Some((size, now, tries + 1));
+                return Some(size);
+            }
+        }
+
+        let next = PROBE_SIZES
+            .iter()
+            .find(|s| **s > self.size && **s < self.ceiling)
+            .cloned();
+
+        if let Some(size) = next {
+            self.probing = Some((size, now, 0));
+        }
+        next
+    }
+
+    /// receive probe ack, return true if the size is bigger than before.
+    pub fn confirm(&mut self, size: usize) -> bool {
+        if let Some((probing, _, _)) = self.probing {
+            if probing == size {
+                self.probing = None;
+            }
+        }
+
+        if size > self.size && size < self.ceiling {
+            self.size = size;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// the probe padding length, make whole datagram is the size.
+    pub fn padding(size: usize) -> usize {
+        size.saturating_sub(FRAGMENT_HEAD_LENGTH + HEAD_LENGTH + PROBE_CONTENT_LENGTH)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_mtu_probe() {
+        let mut mtu = PathMtu::new(1200);
+        assert_eq!(mtu.next_probe(), Some(1280));
+        assert_eq!(mtu.next_probe(), None);
+        assert!(mtu.confirm(1280));
+        assert_eq!(mtu.size(), 1280);
+
+        // all probes of size lost, not probe it and bigger again
+        assert_eq!(mtu.next_probe(), Some(1400));
+        let timeout = Instant::now() - Duration::new(PROBE_TIMEOUT + 1, 0);
+        mtu.probing = Some((1400, timeout, PROBE_RETRIES));
+        assert_eq!(mtu.next_probe(), None);
+        assert!(!mtu.confirm(1400));
+        assert!(!mtu.confirm(1472));
+        assert_eq!(mtu.size(), 1280);
+
+        // never probe bigger than the unfragmented size
+        let mut mtu = PathMtu::new(1200);
+        for size in PROBE_SIZES.iter() {
+            assert_eq!(mtu.next_probe(), Some(*size));
+            assert!(mtu.confirm(*size));
+        }
+        assert_eq!(mtu.next_probe(), None);
+        assert_eq!(mtu.size(), 1472);
+        assert_eq!(
+            PathMtu::padding(1472) + FRAGMENT_HEAD_LENGTH + HEAD_LENGTH + PROBE_CONTENT_LENGTH,
+            1472
+        );
+    }
+}
@@ -0,0 +1,180 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use crate::crypto::keypair::PublicKey;
+
+/// observe external address again in this time.
+const OBSERVE_INTERVAL: u64 = 60;
+
+/// peers observe external address every time.
+pub const OBSERVE_PEERS: usize = 4;
+
+/// observed address kept in this time.
+const OBSERVE_TIME: u64 = 600;
+
+/// wait the observed reply in this time.
+const OBSERVE_TIMEOUT: u64 = 10;
+
+/// NAT type inferred by the observed addresses.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum NatType {
+    Unknown,
+    /// no NAT, external address is the listen address.
+    Public,
+    /// same mapping for all peers, and any peer can send to it.
+    FullCone,
+    /// same mapping for all peers, only peers sent to can send to it.
+    Restricted,
+    /// mapping is different for every peer, hole punching will fail.
+    Symmetric,
+}
+
+/// aggregate the source addresses echoed by peers,
+/// infer the external address and NAT type of self.
+#[derive(Clone)]
+pub(crate) struct NatDetector {
+    local: SocketAddr,
+    last: Option<Instant>,
+    observings: HashMap<PublicKey, Instant>,
+    observeds: HashMap<PublicKey, (SocketAddr, Instant)>,
+    probed: Option<Instant>,
+}
+
+impl NatDetector {
+    pub fn new(local: SocketAddr) -> Self {
+        NatDetector {
+            local,
+            last: None,
+            observings: HashMap::new(),
+            observeds: HashMap::new(),
+            probed: None,
+        }
+    }
+
+    /// check if need observe again, and mark the time.
+    pub fn need_observe(&mut self) -> bool {
+        let now = Instant::now();
+        match self.last {
+            Some(ins) if now.duration_since(ins) < Duration::new(OBSERVE_INTERVAL, 0) => false,
+            _ => {
+                self.last = Some(now);
+                true
+            }
+        }
+    }
+
+    /// send observe request to peer.
+    pub fn observe(&mut self, pk: PublicKey) {
+        self.observings.insert(pk, Instant::now());
+    }
+
+    /// peer echo the source address, only accept requested.
+    pub fn observed(&mut self, pk: PublicKey, addr: SocketAddr) -> bool {
+        if self.observings.remove(&pk).is_none() {
+            return false;
+        }
+        self.observeds.insert(pk, (addr, Instant::now()));
+        true
+    }
+
+    /// received probe from peer never sent to, NAT is not restricted.
+    pub fn probed(&mut self) {
+        self.probed = Some(Instant::now());
+    }
+
+    /// the external address most peers observed.
+    pub fn external(&self) -> Option<SocketAddr> {
+        let mut counts: HashMap<SocketAddr, usize> = HashMap::new();
+        for (addr, _) in self.observeds.values() {
+            *counts.entry(*addr).or_insert(0) += 1;
+        }
+
+        counts
+            .into_iter()
+            .max_by_key(|(_, count)| *count)
+            .filter(|(_, count)| count * 2 > self.observeds.len())
+            .map(|(addr, _)| addr)
+    }
+
+    /// the address self advertised, external address if known, or the listen address.
+    pub fn advertised(&self) -> SocketAddr {
+        self.external().unwrap_or(self.local)
+    }
+
+    /// the addresses of self record, external address and the specified listen address.
+    pub fn addrs(&self) -> Vec<SocketAddr> {
+        let mut addrs: Vec<SocketAddr> = self.external().into_iter().collect();
+        if !self.local.ip().is_unspecified() && !addrs.contains(&self.local) {
+            addrs.push(self.local);
+        }
+        addrs
+    }
+
+    pub fn nat_type(&self) -> NatType {
+        if self.observeds.len() < 2 {
+            return NatType::Unknown;
+        }
+
+        let external = match self.external() {
+            Some(external) => external,
+            None => return NatType::Symmetric,
+        };
+
+        if self.observeds.values().any(|(addr, _)| addr != &external) {
+            return NatType::Symmetric;
+        }
+
+        let is_local = external == self.local
+            || (self.local.ip().is_unspecified() && external.port() == self.local.port());
+        match (is_local, self.probed.is_some()) {
+            (true, true) => NatType::Public,
+            (false, true) => NatType::FullCone,
+            (_, false) => NatType::Restricted,
+        }
+    }
+
+    /// remove the timeout observings and old observed addresses.
+    pub fn evict(&mut self) {
+        let now = Instant::now();
+        self.observings
+            .retain(|_, ins| now.duration_since(*ins) < Duration::new(OBSERVE_TIMEOUT, 0));
+        self.observeds
+            .retain(|_, (_, ins)| now.duration_since(*ins) < Duration::new(OBSERVE_TIME, 0));
+        if let Some(ins) = self.probed {
+            if now.duration_since(ins) > Duration::new(OBSERVE_TIME, 0) {
+                self.probed = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::keypair::PrivateKey;
+
+    #[test]
+    fn test_nat_type() {
+        let a = PrivateKey::generate().generate_public_key();
+        let b = PrivateKey::generate().generate_public_key();
+        let external: SocketAddr = "1.2.3.4:7000".parse().unwrap();
+        let mut nat = NatDetector::new("0.0.0.0:7364".parse().unwrap());
+
+        assert!(!nat.observed(a.clone(), external));
+        nat.observe(a.clone());
+        nat.observe(b.clone());
+        assert!(nat.observed(a.clone(), external));
+        assert_eq!(nat.nat_type(), NatType::Unknown);
+        assert!(nat.observed(b.clone(), external));
+        assert_eq!(nat.external(), Some(external));
+        assert_eq!(nat.nat_type(), NatType::Restricted);
+        nat.probed();
+        assert_eq!(nat.nat_type(), NatType::FullCone);
+
+        nat.observe(b.clone());
+        nat.observed(b, "1.2.3.4:7001".parse().unwrap());
+        assert_eq!(nat.nat_type(), NatType::Symmetric);
+    }
+}
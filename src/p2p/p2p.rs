@@ -1,63 +1,186 @@
+use futures::future::join_all;
+use rand::seq::SliceRandom;
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::time::{Duration, Instant};
 
 use crate::actor::prelude::*;
+use crate::config::P2PConfig;
 use crate::crypto::hash::H256;
 use crate::crypto::keypair::{PrivateKey, PublicKey};
+use crate::primitives::consts::K_BUCKET;
 use crate::primitives::functions::get_default_storage_path;
 use crate::primitives::functions::{try_resend_times, DEFAULT_TIMES};
-use crate::primitives::types::GroupID;
-use crate::storage::{DiskStorageActor, Entity, EntityRead, EntityWrite};
+use crate::primitives::types::{GroupID, PeerInfoByte};
+use crate::storage::{DiskStorageActor, Entity, EntityDelete, EntityRead, EntityWrite};
 use crate::traits::actor::P2PBridgeActor;
 use crate::traits::message::p2p_message::*;
 
-use super::codec::P2PHead;
+use super::bootstrap::Bootstrap;
+use super::codec::{P2PBody, P2PHead, HEAD_LENGTH};
 use super::content::P2PContent;
-use super::dht::DHTTable;
-use super::session::{P2PAddrMessage, P2PMessage, P2PSessionActor};
+use super::dht::{
+    node_id, now_secs, Acked, DHTRecord, DHTTable, LegacyDHTTable, Lookup, LookupKind,
+    MemberUpdate, PeerMeta, Probe, Providers, RecordStore, MAX_VALUE_SIZE,
+};
+use super::gossip::{Gossip, GossipItem};
+use super::lan::{LanAnnounceMessage, LanDiscoveryActor, LanPeerMessage, ANNOUNCE_TIME};
+use super::limit::{InboundLimiter, Limit};
+use super::mtu::PathMtu;
+use super::nat::{NatDetector, OBSERVE_PEERS};
+use super::pex::{self, PexRecord, MAX_AGE, PEX_TIME};
+use super::puzzle;
+use super::relay::Relay;
+use super::reliable::ReliableChannel;
+use super::route::{closest, next_hop, Router, MAX_HOPS};
+use super::score::{Ban, Behaviour, Scores};
+use super::secure::SecureSession;
+use super::session::{
+    P2PAddrMessage, P2PBanMessage, P2PCongestionMessage, P2PMessage, P2PMtuMessage,
+    P2PSessionActor, P2PUnreachableMessage,
+};
+use super::transport::{Transport, TransportSocketMessage, TransportType};
+use super::version::{
+    negotiate, PeerVersions, FEATURE_MTU_PROBE, FEATURE_RELAY, FEATURE_RELIABLE, FEATURE_TCP,
+};
 
 /// p2p actor service.
 /// it will handle every event message and p2p peer.
 /// outside use donot need care how to send, only care send to who.
 #[derive(Clone)]
 pub struct P2PActor<A: P2PBridgeActor> {
-    version: u16,
+    versions: PeerVersions,
     psk: PrivateKey,
     pk: PublicKey,
+    config: P2PConfig,
     bridge: Option<Addr<A>>,
     storage: Addr<DiskStorageActor>,
     tables: HashMap<GroupID, DHTTable>,
     session: Addr<P2PSessionActor<A>>,
+    transports: HashMap<TransportType, Transport<A>>,
+    sockets: HashMap<SocketAddr, TransportType>,
     holepunching: HashMap<PublicKey, (Instant, SocketAddr, GroupID, Vec<P2PMessage>)>,
+    invalid_messages: u64,
+    secures: HashMap<PublicKey, SecureSession>,
+    reliables: HashMap<(GroupID, PublicKey), ReliableChannel>,
+    mtus: HashMap<SocketAddr, PathMtu>,
+    limiter: InboundLimiter,
+    gossip: Gossip,
+    router: Router,
+    relay: Relay,
+    nat: NatDetector,
+    /// signed self records of peers received by exchange, forward them to others.
+    pex_records: HashMap<(GroupID, PublicKey), PexRecord>,
+    lookups: HashMap<u64, Lookup>,
+    records: RecordStore,
+    providers: Providers,
+    difficulties: HashMap<GroupID, u8>,
+    scores: Scores<PublicKey>,
+    ip_scores: Scores<IpAddr>,
+    bootstrap: Bootstrap,
+    lan: Option<Addr<LanDiscoveryActor<A>>>,
 }
 
 impl<A: P2PBridgeActor> P2PActor<A> {
-    pub fn load(session: Addr<P2PSessionActor<A>>, psk: Option<PrivateKey>) -> Self {
+    pub(crate) fn load(
+        session: Addr<P2PSessionActor<A>>,
+        transports: HashMap<TransportType, Transport<A>>,
+        p2p_socket: SocketAddr,
+        psk: Option<PrivateKey>,
+        config: P2PConfig,
+        bootstrap_peers: Vec<(PublicKey, SocketAddr)>,
+        bootstrap_groups: Vec<GroupID>,
+        lan: Option<Addr<LanDiscoveryActor<A>>>,
+    ) -> Self {
+        // the hardest group difficulty of identity puzzle is enough for all.
+        let difficulties: HashMap<GroupID, u8> = config
+            .group_puzzle_difficulty
+            .iter()
+            .filter_map(|(group, d)| GroupID::from_string(group).ok().map(|g| (g, *d)))
+            .collect();
+        let max_difficulty = difficulties
+            .values()
+            .fold(config.puzzle_difficulty, |a, b| a.max(*b));
+
         let psk = if psk.is_none() {
             // TODO load from storage or generate
-            PrivateKey::generate()
+            puzzle::solve(max_difficulty)
         } else {
             psk.unwrap()
         };
         let pk = psk.generate_public_key();
+        if !puzzle::verify(&pk, max_difficulty) {
+            println!(
+                "DEBUG: P2P key not solve identity puzzle: {}",
+                max_difficulty
+            );
+        }
         let mut path = get_default_storage_path();
         path.push("p2p");
         path.push(format!("{}", pk));
 
         let storage = DiskStorageActor::run(Some(path));
+        let limiter = InboundLimiter::new(
+            (config.peer_message_rate, config.peer_byte_rate),
+            (config.group_message_rate, config.group_byte_rate),
+            config.throttle_limit,
+        );
+
+        let mut features = FEATURE_RELIABLE | FEATURE_MTU_PROBE;
+        if config.tcp {
+            features |= FEATURE_TCP;
+        }
+        if config.relay {
+            features |= FEATURE_RELAY;
+        }
+        let relay = Relay::new(
+            config.relay,
+            config.relay_reservations,
+            config.relay_byte_rate,
+        );
+
+        // bootstrap the configured groups at once, bridge can change the join info later
+        let mut tables = HashMap::new();
+        let mut bootstrap = Bootstrap::new(bootstrap_peers);
+        for group in bootstrap_groups {
+            if !tables.contains_key(&group) {
+                tables.insert(group.clone(), DHTTable::new(&pk));
+                bootstrap.start(group, vec![]);
+            }
+        }
 
         // load psk and tables
         Self {
-            version: 1u16,
+            versions: PeerVersions::new(features),
             psk: psk,
             pk: pk,
+            config: config,
             bridge: None,
             storage: storage,
-            tables: HashMap::new(), // load
+            tables: tables, // load
             session: session,
+            transports: transports,
+            sockets: HashMap::new(),
             holepunching: HashMap::new(),
+            invalid_messages: 0,
+            secures: HashMap::new(),
+            reliables: HashMap::new(),
+            mtus: HashMap::new(),
+            limiter: limiter,
+            gossip: Default::default(),
+            router: Default::default(),
+            relay: relay,
+            nat: NatDetector::new(p2p_socket),
+            pex_records: HashMap::new(),
+            lookups: HashMap::new(),
+            records: Default::default(),
+            providers: Default::default(),
+            difficulties: difficulties,
+            scores: Default::default(),
+            ip_scores: Default::default(),
+            bootstrap: bootstrap,
+            lan: lan,
         }
     }
 
@@ -89,117 +212,65 @@ impl<A: P2PBridgeActor> P2PActor<A> {
             .map_err(|_| println!("Send Message to udp session fail"));
     }
 
-    fn new_p2p_message(
-        &self,
-        group: GroupID,
-        to: PublicKey,
-        socket: SocketAddr,
-        content: P2PContent,
-    ) -> P2PMessage {
-        let mut head = P2PHead::new(self.version, group, self.pk.clone(), to);
-        let hash_content = H256::new(&bincode::serialize(&content).unwrap_or(vec![])[..]);
-        head.update_signature(self.psk.sign_bytes(&hash_content.to_vec()));
-        P2PMessage(head, content, socket)
-    }
-
-    /// Timed task, include: Heart Beat and NAT holepunching
-    fn hb(&self, ctx: &mut Context<Self>) {
-        ctx.run_later(Duration::new(5, 0), |act, ctx| {
-            let mut send_peer_leave: Vec<ReceivePeerLeaveMessage> = vec![];
-            let mut send_heartbeat: Vec<(GroupID, PublicKey, SocketAddr, P2PContent)> = vec![];
-            for (group, table) in act.tables.iter_mut() {
-                let (mut next, mut dis) = table.next_hb_peers();
-                for _ in 0..dis.len() {
-                    if let Some(id) = dis.pop() {
-                        table.remove_peer(&id);
-                        send_peer_leave.push(ReceivePeerLeaveMessage(group.clone(), id, false));
-                    }
-                }
-
-                while !next.is_empty() {
-                    let (pk, socket) = next.pop().unwrap();
-                    send_heartbeat.push((group.clone(), pk, socket, P2PContent::HeartBeat));
-                }
-            }
+    /// message to relayed peer by the socket of its relay, wrap it to relay message.
+    fn relay_wrap(&self, message: P2PMessage) -> P2PMessage {
+        let relay = match self.relay.relay_by(&message.0.to) {
+            Some((group, relay)) => self
+                .tables
+                .get(&group)
+                .and_then(|table| table.get_socket_addr(&relay))
+                .filter(|socket| socket == &message.2)
+                .map(|_| (group, relay)),
+            None => None,
+        };
 
-            while !send_peer_leave.is_empty() {
-                let message = send_peer_leave.pop().unwrap();
-                act.send_bridge(message);
+        match relay {
+            Some((group, relay)) => {
+                let P2PMessage(head, content, socket) = message;
+                let body_bytes = bincode::serialize(&P2PBody(content)).unwrap_or(vec![]);
+                let content = P2PContent::Relay(head.encode().to_vec(), body_bytes);
+                self.new_p2p_message(group, relay, socket, content)
             }
+            None => message,
+        }
+    }
 
-            while !send_heartbeat.is_empty() {
-                let (group, to, socket, content) = send_heartbeat.pop().unwrap();
-                let message = act.new_p2p_message(group, to, socket, content);
-                act.send_session(message);
+    /// transport of the socket, connected socket use its connection,
+    /// otherwise use the config transport. mtu probe only use udp.
+    fn transport_type(&self, socket: &SocketAddr, content: &P2PContent) -> TransportType {
+        match content {
+            P2PContent::MtuProbe(..) | P2PContent::MtuProbeOk(..) | P2PContent::ObserveProbe => {
+                TransportType::UDP
             }
-
-            // check nat hole punching
-            let mut need_delete: Vec<PublicKey> = act
-                .holepunching
-                .iter()
-                .filter_map(|(pk, (ins, socket, group, _tasks))| {
-                    if Instant::now().duration_since(ins.clone()) > Duration::new(8, 0) {
-                        println!("Again hole punching {} : {:?}", pk, socket);
-                        act.send_session(act.new_p2p_message(
-                            group.clone(),
-                            pk.clone(),
-                            socket.clone(),
-                            P2PContent::HolePunching,
-                        ));
-                        Some(pk)
-                    } else {
-                        None
-                    }
-                })
+            _ => self
+                .sockets
+                .get(socket)
                 .cloned()
-                .collect();
-
-            while !need_delete.is_empty() {
-                let pk = need_delete.pop().unwrap();
-                act.holepunching.remove(&pk);
-            }
-
-            act.hb(ctx);
-        });
-    }
-}
-
-/// impl Actor for NetworkBridgeActor
-impl<A: P2PBridgeActor> Actor for P2PActor<A> {
-    type Context = Context<Self>;
-
-    fn started(&mut self, ctx: &mut Self::Context) {
-        let _ = try_resend_times(
-            self.session.clone(),
-            P2PAddrMessage(ctx.address()),
-            DEFAULT_TIMES,
-        )
-        .map_err(|_| println!("Send p2p addr to session fail"));
-
-        DHTTableStore::async_load(&self.pk, &self.storage, self, ctx);
-
-        self.hb(ctx);
+                .unwrap_or(self.config.transport),
+        }
     }
-}
 
-impl<A: P2PBridgeActor> Handler<P2PBridgeAddrMessage<A>> for P2PActor<A> {
-    type Result = ();
+    /// try send p2p message to peer by transport, if not have, use udp.
+    fn send_transport(&self, message: P2PMessage) {
+        let message = self.relay_wrap(message);
+        let transport_type = self.transport_type(&message.2, &message.1);
+        let transport = self
+            .transports
+            .get(&transport_type)
+            .or(self.transports.get(&TransportType::UDP));
 
-    fn handle(&mut self, msg: P2PBridgeAddrMessage<A>, _ctx: &mut Self::Context) -> Self::Result {
-        self.bridge = Some(msg.0);
-        // load saved tables
+        if let Some(transport) = transport {
+            let _ = transport
+                .send(message)
+                .map_err(|_| println!("Send Message to transport fail"));
+        }
     }
-}
 
-impl<A: P2PBridgeActor> P2PBridgeActor for P2PActor<A> {}
-
-impl<A: P2PBridgeActor> Handler<ReceiveEventMessage> for P2PActor<A> {
-    type Result = ();
-
-    fn handle(&mut self, msg: ReceiveEventMessage, _ctx: &mut Self::Context) -> Self::Result {
-        let (group, peer_addr, event) = (msg.0, msg.1, msg.2);
-        if peer_addr == self.pk {
-            return self.send_bridge(ReceiveEventMessage(group, peer_addr, event));
+    /// send content to peer in group, if peer is hole punching, wait it,
+    /// content need encrypted wait the secure session established.
+    fn send_to_peer(&mut self, group: GroupID, peer_addr: PublicKey, content: P2PContent) {
+        if content.need_seal() && !self.is_secure(&peer_addr) {
+            return self.wait_secure(group, peer_addr, None, content);
         }
 
         if let Some(table) = self.tables.get(&group) {
@@ -211,287 +282,2136 @@ impl<A: P2PBridgeActor> Handler<ReceiveEventMessage> for P2PActor<A> {
                     .unwrap()
                     .1
                     .clone();
-                let message = self.new_p2p_message(
-                    group,
-                    peer_addr.clone(),
-                    socket,
-                    P2PContent::Event(event),
-                );
+                let message = self.new_p2p_message(group, peer_addr.clone(), socket, content);
                 self.holepunching
                     .get_mut(&peer_addr)
                     .unwrap()
                     .3
                     .push(message);
+            } else if let Some(relay) = self.relay.relay_of(&peer_addr) {
+                let (head, content) = self.new_p2p_head(group, peer_addr, content);
+                self.send_relay(relay, head, content);
             } else if let Some(socket) = table.get_socket_addr(&peer_addr) {
-                self.send_session(self.new_p2p_message(
-                    group,
-                    peer_addr,
-                    socket,
-                    P2PContent::Event(event),
-                ));
+                self.send_message(group, peer_addr, socket, content);
+            } else if content.can_route() {
+                // not connected, route by other peers
+                let (head, content) = self.new_p2p_head(group, peer_addr, content);
+                self.route(head, content, MAX_HOPS, None);
             }
         }
     }
-}
 
-impl<A: P2PBridgeActor> Handler<ReceivePeerJoinMessage> for P2PActor<A> {
-    type Result = ();
+    /// send content to peer socket, content need encrypted wait the secure session established.
+    fn send_message(
+        &mut self,
+        group: GroupID,
+        peer_addr: PublicKey,
+        socket: SocketAddr,
+        content: P2PContent,
+    ) {
+        if content.need_seal() && !self.is_secure(&peer_addr) {
+            self.wait_secure(group, peer_addr, Some(socket), content);
+        } else {
+            self.send_transport(self.new_p2p_message(group, peer_addr, socket, content));
+        }
+    }
 
-    fn handle(&mut self, msg: ReceivePeerJoinMessage, _ctx: &mut Self::Context) -> Self::Result {
-        // join group to p2p
-        let (group, peer_addr, result, socket_addr) = (msg.0, msg.1, msg.2, msg.3);
-        if !self.tables.contains_key(&group) {
-            self.tables.insert(group.clone(), DHTTable::new(&self.pk));
+    fn is_secure(&self, pk: &PublicKey) -> bool {
+        self.secures
+            .get(pk)
+            .map(|secure| secure.is_established())
+            .unwrap_or(false)
+    }
+
+    /// save content until secure session established, start handshake if not started,
+    /// if socket is none, send by table.
+    fn wait_secure(
+        &mut self,
+        group: GroupID,
+        peer_addr: PublicKey,
+        socket: Option<SocketAddr>,
+        content: P2PContent,
+    ) {
+        let secure = self
+            .secures
+            .entry(peer_addr.clone())
+            .or_insert(SecureSession::new(group.clone()));
+        secure.push_pending(group.clone(), content, socket);
+        if secure.is_handshaking() {
+            return;
         }
 
-        if let Some(table) = self.tables.get_mut(&group) {
-            println!("DEBUG: start peer join: {}", peer_addr);
-            table.add_tmp_peer(&peer_addr, socket_addr);
-            if self.holepunching.contains_key(&peer_addr) {
-                let socket = self
-                    .holepunching
-                    .get(&peer_addr)
-                    .as_ref()
-                    .unwrap()
-                    .1
-                    .clone();
-                let message = self.new_p2p_message(
-                    group,
-                    peer_addr.clone(),
-                    socket,
-                    P2PContent::Join(result),
-                );
-                self.holepunching
-                    .get_mut(&peer_addr)
-                    .unwrap()
-                    .3
-                    .push(message);
-            } else if let Some(socket) = table.get_socket_addr(&peer_addr) {
-                self.send_session(self.new_p2p_message(
-                    group,
-                    peer_addr,
-                    socket,
-                    P2PContent::Join(result),
-                ));
+        let handshake = secure.start(group.clone(), socket);
+        match socket {
+            Some(socket) => self.send_message(group, peer_addr, socket, handshake),
+            None => self.send_to_peer(group, peer_addr, handshake),
+        }
+    }
+
+    /// send the contents waiting for secure session.
+    fn flush_pendings(&mut self, pk: &PublicKey) {
+        let pendings = self
+            .secures
+            .get_mut(pk)
+            .map(|secure| secure.take_pendings())
+            .unwrap_or(vec![]);
+
+        for (group, content, socket) in pendings {
+            match socket {
+                Some(socket) => self.send_message(group, pk.clone(), socket, content),
+                None => self.send_to_peer(group, pk.clone(), content),
             }
         }
     }
-}
 
-impl<A: P2PBridgeActor> Handler<ReceivePeerJoinResultMessage> for P2PActor<A> {
-    type Result = ();
+    /// broadcast message to group, if new, eager push to some random peers,
+    /// and lazy push the id to others.
+    fn broadcast(&mut self, group: GroupID, mut item: GossipItem, from: Option<PublicKey>) {
+        let id = item.id(&group);
+        if !self.gossip.insert(group.clone(), id.clone(), item.clone()) {
+            return;
+        }
 
-    fn handle(
-        &mut self,
-        msg: ReceivePeerJoinResultMessage,
-        _ctx: &mut Self::Context,
-    ) -> Self::Result {
-        let (group, peer_addr, result, helps) = (msg.0, msg.1, msg.2, msg.3);
-        println!("DEBUG: peer join: {}", result);
-        let need_store = if let Some(table) = self.tables.get_mut(&group) {
-            let mut need_store = false;
-            if let Some(socket) = table.get_socket_addr(&peer_addr) {
-                if result {
-                    if table.fixed_peer(&peer_addr) {
-                        need_store = true;
-                    }
+        if from.is_some() {
+            self.send_bridge(ReceiveBroadcastMessage(
+                group.clone(),
+                item.origin.clone(),
+                item.event.clone(),
+            ));
+        }
 
-                    let dht = helps
-                        .iter()
-                        .filter_map(|peer_addr| {
-                            if let Some(addr) = table.get_socket_addr(peer_addr) {
-                                Some((peer_addr.clone(), addr))
-                            } else {
-                                None
-                            }
-                        })
-                        .collect();
+        let peers = match self.tables.get(&group) {
+            Some(table) => table.peers().into_iter().map(|(pk, _)| pk).collect(),
+            None => return,
+        };
 
-                    self.send_session(self.new_p2p_message(
-                        group,
-                        peer_addr,
-                        socket,
-                        P2PContent::DHT(dht),
-                    ));
-                } else {
-                    self.send_session(self.new_p2p_message(
-                        group,
-                        peer_addr,
-                        socket,
-                        P2PContent::Leave,
-                    ));
-                }
+        let mut excepts = vec![&item.origin];
+        if let Some(ref from) = from {
+            excepts.push(from);
+        }
+        let (eager, mut lazy) = Gossip::select(peers, &excepts);
+
+        if item.can_relay() {
+            item.hops += 1;
+            for pk in eager {
+                self.send_to_peer(group.clone(), pk, P2PContent::Gossip(item.clone()));
             }
-            need_store
         } else {
-            false
-        };
+            lazy.extend(eager);
+        }
 
-        if need_store {
-            DHTTableStore::async_store(self.pk.clone(), self.tables.clone(), &self.storage);
+        for pk in lazy {
+            self.gossip.announce(group.clone(), pk, id.clone());
         }
     }
-}
 
-impl<A: P2PBridgeActor> Handler<ReceivePeerLeaveMessage> for P2PActor<A> {
-    type Result = ();
+    /// Timed task, gossip lazy push and pull
+    fn gossip_hb(&self, ctx: &mut Context<Self>) {
+        ctx.run_later(Duration::from_millis(500), |act, ctx| {
+            for (group, pk, ids) in act.gossip.take_announces() {
+                act.send_to_peer(group, pk, P2PContent::GossipIHave(ids));
+            }
 
-    fn handle(&mut self, msg: ReceivePeerLeaveMessage, _ctx: &mut Self::Context) -> Self::Result {
-        let (group, peer_addr, is_force) = (msg.0, msg.1, msg.2);
-        let socket = if let Some(table) = self.tables.get_mut(&group) {
-            if is_force {
-                let socket = table.get_socket_addr(&peer_addr);
-                table.remove_peer(&peer_addr);
-                socket
-            } else {
-                // do some other connect
-                None
+            for (group, pk, ids) in act.gossip.take_wants() {
+                act.send_to_peer(group, pk, P2PContent::GossipIWant(ids));
             }
-        } else {
-            None
-        };
 
-        if let Some(socket) = socket {
-            self.send_session(self.new_p2p_message(group, peer_addr, socket, P2PContent::Leave));
-        }
+            act.gossip.evict();
+            act.router.evict();
+            act.gossip_hb(ctx);
+        });
     }
-}
 
-/// handle receive P2PMessage from UDP
-impl<A: P2PBridgeActor> Handler<P2PMessage> for P2PActor<A> {
-    type Result = ();
+    /// start hole punching to new peer, the introducer is the first relay choice.
+    fn connect_peer(
+        &mut self,
+        group: GroupID,
+        pk: PublicKey,
+        socket_addr: SocketAddr,
+        introducer: PublicKey,
+    ) -> bool {
+        let not_contain = self.tables.get(&group).and_then(|t| Some(t.check_add(&pk)));
+        if let Some(true) = not_contain {
+            self.tables
+                .get_mut(&group)
+                .map(|t| t.add_tmp_peer(&pk, None));
 
-    fn handle(&mut self, msg: P2PMessage, _ctx: &mut Self::Context) -> Self::Result {
-        let (head, content, socket) = (msg.0, msg.1, msg.2);
-        let (group, from, to) = (head.gid, head.from, head.to);
+            println!("DEBUG: start hole punching {}, {}", pk, socket_addr);
+            self.relay.introduce(pk.clone(), introducer);
+            self.send_message(
+                group.clone(),
+                pk.clone(),
+                socket_addr,
+                P2PContent::HolePunching,
+            );
 
-        // check self group
-        if !self.tables.contains_key(&group) {
-            return;
+            self.holepunching
+                .entry(pk)
+                .and_modify(|time| {
+                    time.0 = Instant::now();
+                })
+                .or_insert((Instant::now(), socket_addr, group, vec![]));
+            true
+        } else {
+            false
         }
+    }
 
-        let table = self.tables.get_mut(&group).unwrap();
-
-        // check version include
-
-        // TODO check if send to is self node
-        // if true handle it, if not send to closest peer
-        if from == self.pk || to != self.pk {
-            return;
+    /// send join to peer, if group not in self, create the table.
+    fn join_peer(
+        &mut self,
+        group: GroupID,
+        peer_addr: PublicKey,
+        join: PeerInfoByte,
+        socket_addr: Option<SocketAddr>,
+    ) {
+        if !self.tables.contains_key(&group) {
+            self.tables.insert(group.clone(), DHTTable::new(&self.pk));
+        }
+        if let Some(table) = self.tables.get_mut(&group) {
+            println!("DEBUG: start peer join: {}", peer_addr);
+            table.add_tmp_peer(&peer_addr, socket_addr);
+            self.send_to_peer(group, peer_addr, P2PContent::Join(join));
         }
+    }
 
-        // remove from hole punching
-        if self.holepunching.contains_key(&from) {
-            if let Some((_, _, _, mut tasks)) = self.holepunching.remove(&from) {
-                loop {
-                    if let Some(message) = tasks.pop() {
-                        // use self.send_session will wrong ?
-                        let _ = try_resend_times(self.session.clone(), message, DEFAULT_TIMES)
-                            .map_err(|_| println!("Send Message to udp session fail"));
-                    } else {
-                        break;
+    /// Timed task, join the bootstrap peers of groups which peers too few,
+    /// and lookup self by the peers in table to fill it.
+    fn bootstrap_hb(&self, ctx: &mut Context<Self>) {
+        ctx.run_later(Duration::new(1, 0), |act, ctx| {
+            let tables = &act.tables;
+            let (joins, dones) = act
+                .bootstrap
+                .next(|group| tables.get(group).map(|t| t.peers().len()).unwrap_or(0));
+
+            for (group, join) in joins {
+                println!("DEBUG: bootstrap group: {}", group.to_string());
+                let seeds: Vec<(PublicKey, SocketAddr)> = act
+                    .bootstrap
+                    .seeds()
+                    .iter()
+                    .filter(|(pk, _)| pk != &act.pk && !act.scores.is_banned(pk))
+                    .cloned()
+                    .collect();
+                for (pk, socket) in seeds {
+                    let is_member = act
+                        .tables
+                        .get(&group)
+                        .map(|t| t.contains(&pk))
+                        .unwrap_or(false);
+                    if !is_member {
+                        act.join_peer(group.clone(), pk, join.clone(), Some(socket));
                     }
                 }
+
+                let target = node_id(&act.pk);
+                act.lookup(group, target, vec![], None, LookupKind::Node);
             }
-        }
 
-        match content {
-            P2PContent::HeartBeat => {
-                table.update_hb_peers(&from);
-                self.send_session(self.new_p2p_message(
-                    group,
-                    from,
-                    socket,
-                    P2PContent::HeartBeatOk,
-                ));
+            for group in dones {
+                println!("DEBUG: bootstrapped group: {}", group.to_string());
+                act.send_bridge(ReceiveBootstrappedMessage(group));
             }
-            P2PContent::HeartBeatOk => {
+
+            act.bootstrap_hb(ctx);
+        });
+    }
+
+    /// self record and the signed records of recent peers in group table for exchange,
+    /// peer not solve the identity puzzle is skipped.
+    fn pex_records(&self, group: &GroupID, except: &PublicKey) -> Vec<PexRecord> {
+        let difficulty = self.difficulty(group);
+        let peers = match self.tables.get(group) {
+            Some(table) => table.recent_peers(MAX_AGE),
+            None => return vec![],
+        };
+
+        let records = peers
+            .into_iter()
+            .filter(|(pk, _, _)| pk != except && puzzle::verify(pk, difficulty))
+            .filter_map(|(pk, _, _)| self.pex_records.get(&(group.clone(), pk)).cloned())
+            .collect();
+
+        let addrs = self.nat.addrs();
+        let own = if addrs.is_empty() {
+            None
+        } else {
+            Some(PexRecord::new(group, &self.psk, addrs))
+        };
+        pex::sample(own, records)
+    }
+
+    /// signed peers received by exchange, keep the newest record of peer,
+    /// connect the new peers by every address, the sender is introducer.
+    fn pex_receive(&mut self, group: GroupID, from: PublicKey, records: Vec<PexRecord>) {
+        let difficulty = self.difficulty(&group);
+        for record in pex::accept(&group, records) {
+            let pk = record.pk.clone();
+            if pk == self.pk || self.scores.is_banned(&pk) || !puzzle::verify(&pk, difficulty) {
+                continue;
+            }
+
+            let key = (group.clone(), pk.clone());
+            let is_newer = self
+                .pex_records
+                .get(&key)
+                .map(|r| r.last_seen < record.last_seen)
+                .unwrap_or(true);
+            if is_newer {
+                self.pex_records.insert(key, record.clone());
+            }
+
+            if self.holepunching.contains_key(&pk) {
+                continue;
+            }
+            let mut addrs = record.addrs.into_iter();
+            if let Some(first) = addrs.next() {
+                if self.connect_peer(group.clone(), pk.clone(), first, from.clone()) {
+                    // the other addresses, which answer first is used
+                    for socket in addrs {
+                        self.send_message(
+                            group.clone(),
+                            pk.clone(),
+                            socket,
+                            P2PContent::HolePunching,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Timed task, exchange peers with one random peer of every group
+    fn pex_hb(&self, ctx: &mut Context<Self>) {
+        ctx.run_later(Duration::new(PEX_TIME, 0), |act, ctx| {
+            let mut send_pex: Vec<(GroupID, PublicKey, SocketAddr)> = vec![];
+            for (group, table) in act.tables.iter() {
+                if let Some((pk, socket)) = table.peers().choose(&mut rand::thread_rng()) {
+                    send_pex.push((group.clone(), pk.clone(), *socket));
+                }
+            }
+
+            // only keep the recent records of the peers in table
+            let now = now_secs();
+            let tables = &act.tables;
+            act.pex_records.retain(|(group, pk), record| {
+                record.last_seen + MAX_AGE >= now
+                    && tables.get(group).map(|t| t.contains(pk)).unwrap_or(false)
+            });
+
+            for (group, pk, socket) in send_pex {
+                let records = act.pex_records(&group, &pk);
+                act.send_message(group, pk, socket, P2PContent::Pex(true, records));
+            }
+
+            act.pex_hb(ctx);
+        });
+    }
+
+    /// Timed task, announce self and groups to local network
+    fn lan_hb(&self, ctx: &mut Context<Self>) {
+        ctx.run_later(Duration::new(ANNOUNCE_TIME, 0), |act, ctx| {
+            if let Some(lan) = act.lan.as_ref() {
+                if !act.tables.is_empty() {
+                    let groups = act.tables.keys().cloned().collect();
+                    lan.do_send(LanAnnounceMessage(act.pk.clone(), groups));
+                }
+            }
+
+            act.lan_hb(ctx);
+        });
+    }
+
+    /// start iterative lookup of the k closest peers to key,
+    /// if not have seeds, use the closest peers in table.
+    fn lookup(
+        &mut self,
+        group: GroupID,
+        target: H256,
+        mut seeds: Vec<(PublicKey, SocketAddr)>,
+        via: Option<PublicKey>,
+        kind: LookupKind,
+    ) {
+        if seeds.is_empty() {
+            if let Some(table) = self.tables.get(&group) {
+                seeds = table.closest(&target.to_bytes(), K_BUCKET);
+            }
+        }
+
+        let mut id: u64 = rand::random();
+        while self.lookups.contains_key(&id) {
+            id = rand::random();
+        }
+        let difficulty = self.difficulty(&group);
+        self.lookups.insert(
+            id,
+            Lookup::new(group, target, &self.pk, difficulty, seeds, via, kind),
+        );
+        self.lookup_step(id);
+    }
+
+    /// merge the loaded tables to tables, the peers already in table are kept.
+    fn merge_tables(&mut self, tables: HashMap<GroupID, DHTTable>) {
+        for (group, loaded) in tables {
+            match self.tables.get_mut(&group) {
+                Some(table) => {
+                    for (pk, socket) in loaded.peers() {
+                        if !table.contains(&pk) {
+                            table.add_peer(&pk, socket);
+                        }
+                    }
+                }
+                None => {
+                    self.tables.insert(group, loaded);
+                }
+            }
+        }
+    }
+
+    /// store the changed peers of tables, every peer liveness is one key.
+    fn store_tables(&mut self) {
+        let mut groups_changed = false;
+        for (group, table) in self.tables.iter_mut() {
+            let (peers, metas, removed) = table.take_changes();
+            if let Some(peers) = peers {
+                groups_changed = true;
+                DHTPeersStore::async_store(
+                    DHTPeersStore(self.pk.clone(), group.clone(), peers),
+                    &self.storage,
+                );
+            }
+            for (peer, meta) in metas {
+                DHTPeerStore::async_store(
+                    DHTPeerStore(self.pk.clone(), group.clone(), peer, meta),
+                    &self.storage,
+                );
+            }
+            for peer in removed {
+                let _ = self
+                    .storage
+                    .do_send(EntityDelete::<DHTPeerStore>(DHTPeerStore::key_of(
+                        &self.pk, group, &peer,
+                    )));
+            }
+        }
+
+        if groups_changed {
+            DHTGroupsStore::async_store(
+                DHTGroupsStore(self.pk.clone(), self.tables.keys().cloned().collect()),
+                &self.storage,
+            );
+        }
+    }
+
+    /// store the changed records, every record is one key.
+    fn store_records(&mut self) {
+        let (keys, records, removed) = self.records.take_changes();
+        if let Some(keys) = keys {
+            DHTRecordKeysStore::async_store(
+                DHTRecordKeysStore(self.pk.clone(), keys),
+                &self.storage,
+            );
+        }
+        for (group, key, record) in records {
+            DHTRecordStore::async_store(
+                DHTRecordStore(self.pk.clone(), group, key, record),
+                &self.storage,
+            );
+        }
+        for (group, key) in removed {
+            let _ = self
+                .storage
+                .do_send(EntityDelete::<DHTRecordStore>(DHTRecordStore::key_of(
+                    &self.pk, &group, &key,
+                )));
+        }
+    }
+
+    /// identity puzzle difficulty of group.
+    fn difficulty(&self, group: &GroupID) -> u8 {
+        self.difficulties
+            .get(group)
+            .cloned()
+            .unwrap_or(self.config.puzzle_difficulty)
+    }
+
+    /// check identity puzzle of the joining peer and the peers in DHT,
+    /// invalid join and DHT reply are dropped, invalid peers are removed from DHT.
+    fn check_puzzle(
+        &mut self,
+        group: &GroupID,
+        from: &PublicKey,
+        content: P2PContent,
+    ) -> P2PContent {
+        let difficulty = self.difficulty(group);
+        match content {
+            P2PContent::Join(_) if !puzzle::verify(from, difficulty) => {
+                println!("DEBUG: drop join with invalid puzzle: {}", from);
+                self.misbehave(from, Behaviour::Invalid);
+                P2PContent::None
+            }
+            P2PContent::DHT(_) if !puzzle::verify(from, difficulty) => {
+                println!("DEBUG: drop DHT with invalid puzzle: {}", from);
+                self.misbehave(from, Behaviour::Invalid);
+                P2PContent::None
+            }
+            P2PContent::DHT(peers) => P2PContent::DHT(
+                peers
+                    .into_iter()
+                    .filter(|(pk, _)| puzzle::verify(pk, difficulty))
+                    .collect(),
+            ),
+            content => content,
+        }
+    }
+
+    /// the closest peers to key in table, with the address self observed.
+    fn closest_peers(
+        &self,
+        group: &GroupID,
+        key: &H256,
+        except: &PublicKey,
+    ) -> Vec<(PublicKey, SocketAddr)> {
+        let table = match self.tables.get(group) {
+            Some(table) => table,
+            None => return vec![],
+        };
+
+        table
+            .closest(&key.to_bytes(), K_BUCKET)
+            .into_iter()
+            .filter(|(pk, _)| pk != except)
+            .collect()
+    }
+
+    /// ask next peers of lookup, or finish it.
+    fn lookup_step(&mut self, id: u64) {
+        let (group, content, nexts) = match self.lookups.get_mut(&id) {
+            Some(lookup) if !lookup.is_done() => {
+                let target = lookup.target().clone();
+                let content = match lookup.kind() {
+                    LookupKind::Value(_) => P2PContent::FindValue(id, target),
+                    LookupKind::Providers(_) => P2PContent::GetProviders(id, target),
+                    _ => P2PContent::FindNode(id, target),
+                };
+                (lookup.group().clone(), content, lookup.next())
+            }
+            Some(_) => return self.lookup_finish(id),
+            None => return,
+        };
+
+        for (pk, socket) in nexts {
+            self.send_message(group.clone(), pk, socket, content.clone());
+        }
+    }
+
+    /// connect the new peers found, and tell bridge who help find them.
+    fn lookup_finish(&mut self, id: u64) {
+        let lookup = match self.lookups.remove(&id) {
+            Some(lookup) => lookup,
+            None => return,
+        };
+        let group = lookup.group().clone();
+
+        match lookup.kind() {
+            LookupKind::Node => {}
+            LookupKind::Store(record) => {
+                let key = lookup.target();
+                for (pk, socket, _) in lookup.result() {
+                    self.send_message(
+                        group.clone(),
+                        pk,
+                        socket,
+                        P2PContent::Store(key.clone(), record.clone()),
+                    );
+                }
+                return;
+            }
+            LookupKind::Value(key) => {
+                return self.send_bridge(ReceiveDHTValueMessage(group, key.clone(), None, 0));
+            }
+            LookupKind::Provide => {
+                for (pk, socket, _) in lookup.result() {
+                    self.send_message(
+                        group.clone(),
+                        pk,
+                        socket,
+                        P2PContent::AddProvider(lookup.target().clone()),
+                    );
+                }
+                return;
+            }
+            LookupKind::Providers(found) => {
+                // holders are unverified, bridge decide whether to connect them.
+                return self.send_bridge(ReceiveProviderMessage(
+                    group,
+                    lookup.target().clone(),
+                    false,
+                    found.clone(),
+                ));
+            }
+        }
+
+        let mut helps: HashMap<PublicKey, Vec<PublicKey>> = HashMap::new();
+        for (pk, socket, via) in lookup.result() {
+            if self.holepunching.contains_key(&pk) {
+                continue;
+            }
+            if let Some(via) = via {
+                if self.connect_peer(group.clone(), pk.clone(), socket, via.clone()) {
+                    helps.entry(via).or_insert(vec![]).push(pk);
+                }
+            }
+        }
+
+        for (via, pks) in helps {
+            self.send_bridge(ReceivePeerJoinResultMessage(group.clone(), via, true, pks));
+        }
+    }
+
+    /// Timed task, lookups request timeout and buckets refresh
+    fn lookup_hb(&self, ctx: &mut Context<Self>) {
+        ctx.run_later(Duration::new(1, 0), |act, ctx| {
+            let ids: Vec<u64> = act.lookups.keys().cloned().collect();
+            for id in ids {
+                act.lookup_step(id);
+            }
+
+            let mut refreshes: Vec<(GroupID, H256)> = vec![];
+            for (group, table) in act.tables.iter_mut() {
+                for key in table.refreshes() {
+                    refreshes.push((group.clone(), key));
+                }
+            }
+            for (group, key) in refreshes {
+                act.lookup(group, key, vec![], None, LookupKind::Node);
+            }
+
+            act.lookup_hb(ctx);
+        });
+    }
+
+    /// Timed task, reliable events retransmission and failure
+    fn reliable_hb(&self, ctx: &mut Context<Self>) {
+        ctx.run_later(Duration::from_millis(250), |act, ctx| {
+            let mut resends: Vec<(GroupID, PublicKey, P2PContent)> = vec![];
+            let mut failures: Vec<ReceiveEventFailMessage> = vec![];
+            for ((group, pk), channel) in act.reliables.iter_mut() {
+                let (resend, failure) = channel.timeouts();
+                for (id, seq, event) in resend {
+                    resends.push((
+                        group.clone(),
+                        pk.clone(),
+                        P2PContent::Reliable(id, seq, event),
+                    ));
+                }
+                for event in failure {
+                    failures.push(ReceiveEventFailMessage(group.clone(), pk.clone(), event));
+                }
+            }
+
+            let mut losts: Vec<(GroupID, PublicKey)> = vec![];
+            while !resends.is_empty() {
+                let (group, pk, content) = resends.remove(0);
+                if !losts.contains(&(group.clone(), pk.clone())) {
+                    losts.push((group.clone(), pk.clone()));
+                }
+                act.send_to_peer(group, pk, content);
+            }
+
+            for (group, pk) in losts {
+                act.send_congestion(&group, &pk, false);
+            }
+
+            while !failures.is_empty() {
+                let message = failures.remove(0);
+                act.send_bridge(message);
+            }
+
+            act.reliable_hb(ctx);
+        });
+    }
+
+    fn new_p2p_message(
+        &self,
+        group: GroupID,
+        to: PublicKey,
+        socket: SocketAddr,
+        content: P2PContent,
+    ) -> P2PMessage {
+        let (head, content) = self.new_p2p_head(group, to, content);
+        P2PMessage(head, content, socket)
+    }
+
+    /// build signed head and sealed content send to peer.
+    fn new_p2p_head(
+        &self,
+        group: GroupID,
+        to: PublicKey,
+        content: P2PContent,
+    ) -> (P2PHead, P2PContent) {
+        let content = match self.secures.get(&to) {
+            Some(secure) => secure.seal(content),
+            None => content,
+        };
+
+        let mut head = P2PHead::new(self.versions.version(&to), group, self.pk.clone(), to);
+        let body_bytes = bincode::serialize(&P2PBody(content.clone())).unwrap_or(vec![]);
+        head.update_signature(self.psk.sign_bytes(&head.sign_data(&body_bytes)));
+        (head, content)
+    }
+
+    /// forward message to the peer closer to the target than self by xor distance,
+    /// the message is wrapped with hops left.
+    fn route(&mut self, head: P2PHead, content: P2PContent, hops: u8, except: Option<SocketAddr>) {
+        let peers = match self.tables.get(&head.gid) {
+            Some(table) => table.closest(&node_id(&head.to).to_bytes(), K_BUCKET),
+            None => return,
+        };
+
+        let next = next_hop(peers, &head.to, &self.pk, |pk, socket| {
+            pk != &head.from && Some(*socket) != except
+        });
+
+        match next {
+            Some((pk, socket)) => {
+                let group = head.gid.clone();
+                let body_bytes = bincode::serialize(&P2PBody(content)).unwrap_or(vec![]);
+                let content = P2PContent::Route(hops, head.encode().to_vec(), body_bytes);
+                self.send_message(group, pk, socket, content);
+            }
+            None => println!("DEBUG: no route to: {}", head.to),
+        }
+    }
+
+    /// send message to peer by the relay peer.
+    fn send_relay(&mut self, relay: PublicKey, head: P2PHead, content: P2PContent) {
+        let socket = self
+            .tables
+            .get(&head.gid)
+            .and_then(|table| table.get_socket_addr(&relay));
+
+        if let Some(socket) = socket {
+            let group = head.gid.clone();
+            let body_bytes = bincode::serialize(&P2PBody(content)).unwrap_or(vec![]);
+            let content = P2PContent::Relay(head.encode().to_vec(), body_bytes);
+            self.send_message(group, relay, socket, content);
+        } else {
+            println!("DEBUG: relay not connected: {}", relay);
+        }
+    }
+
+    /// forward message for reserved peer, relayed bytes take from reservation.
+    fn relay_forward(&mut self, head: P2PHead, body_bytes: Vec<u8>) {
+        let bytes = HEAD_LENGTH + body_bytes.len();
+        if !self.relay.charge(&head.gid, &head.from, &head.to, bytes) {
+            println!("DEBUG: drop relay message over limit to: {}", head.to);
+            return;
+        }
+
+        let socket = self
+            .tables
+            .get(&head.gid)
+            .and_then(|table| table.get_socket_addr(&head.to));
+
+        if let Some(socket) = socket {
+            let (group, to) = (head.gid.clone(), head.to.clone());
+            let content = P2PContent::Relay(head.encode().to_vec(), body_bytes);
+            self.send_message(group, to, socket, content);
+        } else {
+            println!("DEBUG: drop relay message not connected: {}", head.to);
+        }
+    }
+
+    /// hole punching fail, send the waiting messages by relay,
+    /// first choice is the peer who introduce it, it connected both.
+    fn relay_fallback(
+        &mut self,
+        group: GroupID,
+        pk: PublicKey,
+        socket: SocketAddr,
+        tasks: Vec<P2PMessage>,
+    ) {
+        let introducer = self.relay.take_introducer(&pk);
+        let relay = match self.tables.get(&group) {
+            Some(table) => {
+                let peers = table.peers();
+                let versions = &self.versions;
+                introducer
+                    .filter(|r| peers.iter().any(|(p, _)| p == r))
+                    .or(closest(peers, &pk, |p, _| {
+                        p != &pk && versions.has_feature(p, FEATURE_RELAY)
+                    })
+                    .map(|(p, _)| p))
+            }
+            None => None,
+        };
+
+        let relay = match relay {
+            Some(relay) => relay,
+            None => {
+                println!("DEBUG: no relay for: {}", pk);
+                return self.connect_fail(group, pk);
+            }
+        };
+
+        println!("DEBUG: relay to {} by {}", pk, relay);
+        if self.relay.has_reservation(&group, &relay) {
+            self.relay
+                .add_relayed(pk, group, relay.clone(), Some(socket));
+            for message in tasks {
+                self.send_relay(relay.clone(), message.0, message.1);
+            }
+        } else if self
+            .relay
+            .wait(group.clone(), relay.clone(), (pk, Some(socket), tasks))
+        {
+            self.send_to_peer(group, relay, P2PContent::RelayReserve);
+        }
+    }
+
+    /// peer can not connect by hole punching and relay, tell bridge join fail.
+    fn connect_fail(&mut self, group: GroupID, pk: PublicKey) {
+        println!("DEBUG: can not connect to: {}", pk);
+        self.send_bridge(ReceivePeerJoinResultMessage(group, pk, false, vec![]));
+    }
+
+    /// tell udp session the peer socket is acked or lost, to change pacing rate.
+    fn send_congestion(&self, group: &GroupID, pk: &PublicKey, is_acked: bool) {
+        let socket = self
+            .tables
+            .get(group)
+            .and_then(|table| table.get_socket_addr(pk));
+
+        if let Some(socket) = socket {
+            if self.transport_type(&socket, &P2PContent::HeartBeat) == TransportType::UDP {
+                self.send_session(P2PCongestionMessage(socket, is_acked));
+            }
+        }
+    }
+
+    /// peer send too much, disconnect it.
+    fn disconnect_peer(&mut self, group: GroupID, pk: PublicKey, socket: SocketAddr) {
+        println!(
+            "DEBUG: disconnect peer {} : {:?}, over rate limit",
+            pk, socket
+        );
+        if let Some(table) = self.tables.get_mut(&group) {
+            table.remove_peer(&pk);
+        }
+        self.send_message(group.clone(), pk.clone(), socket, P2PContent::Leave);
+        self.secures.remove(&pk);
+        self.send_bridge(ReceivePeerLeaveMessage(group, pk, false));
+    }
+
+    /// peer behaviour change the score, ban it if too low.
+    fn misbehave(&mut self, pk: &PublicKey, behaviour: Behaviour) {
+        if let Some(ban) = self.scores.report(pk, behaviour) {
+            self.ban_peer(pk.clone(), ban);
+        }
+    }
+
+    /// socket send invalid message, the sender can not be known, so ban the ip of socket,
+    /// udp source address can be forged, only tcp socket proved by handshake is scored.
+    fn misbehave_socket(&mut self, socket: SocketAddr, behaviour: Behaviour) {
+        if self.sockets.get(&socket) != Some(&TransportType::TCP) {
+            return;
+        }
+        // tcp source port is ephemeral, score the ip of socket.
+        if let Some(ban) = self.ip_scores.report(&socket.ip(), behaviour) {
+            println!("DEBUG: ban ip {} until: {:?}", socket.ip(), ban.until);
+            self.send_bans();
+        }
+    }
+
+    /// banned peer is removed from all tables, and transports will ignore it.
+    fn ban_peer(&mut self, pk: PublicKey, ban: Ban) {
+        println!("DEBUG: ban peer {} until: {:?}", pk, ban.until);
+        let ban_time = ban
+            .until
+            .map(|until| until.saturating_sub(now_secs()) as u32)
+            .unwrap_or(0);
+        let mut groups = vec![];
+        for (group, table) in self.tables.iter_mut() {
+            if table.contains(&pk) {
+                table.remove_peer(&pk);
+                groups.push((group.clone(), true));
+            } else {
+                groups.push((group.clone(), false));
+            }
+        }
+        for (group, is_member) in groups {
+            if is_member {
+                self.send_bridge(ReceivePeerLeaveMessage(group.clone(), pk.clone(), false));
+            }
+            self.send_bridge(ReceivePeerMisbehaveMessage(
+                group,
+                pk.clone(),
+                ban_time,
+                true,
+            ));
+        }
+
+        self.secures.remove(&pk);
+        self.holepunching.remove(&pk);
+        self.reliables.retain(|(_, p), _| p != &pk);
+        self.send_bans();
+    }
+
+    /// tell transports the banned peers and ips.
+    fn send_bans(&self) {
+        let peers = self.scores.banned();
+        let ips = self.ip_scores.banned();
+        for transport in self.transports.values() {
+            let _ = transport
+                .ban(P2PBanMessage(peers.clone(), ips.clone()))
+                .map_err(|_| println!("Send bans to transport fail"));
+        }
+    }
+
+    /// peer version negotiated, tell bridge.
+    fn agree_version(&mut self, group: GroupID, pk: PublicKey, version: u16, features: u32) {
+        println!(
+            "DEBUG: peer {} version: {}, features: {}",
+            pk, version, features
+        );
+        self.versions.agree(pk.clone(), version, features);
+        self.send_bridge(ReceivePeerVersionMessage(group, pk, version, features));
+    }
+
+    /// peer version incompatible, remove it from table.
+    fn reject_version(&mut self, group: GroupID, pk: PublicKey, reason: String) {
+        println!("DEBUG: reject peer {} version: {}", pk, reason);
+        self.versions.reject(pk.clone());
+        if let Some(table) = self.tables.get_mut(&group) {
+            table.remove_peer(&pk);
+        }
+        self.send_bridge(ReceivePeerLeaveMessage(group, pk, false));
+    }
+
+    /// Timed task, SWIM probe one peer of every group, peer leave only when confirmed dead
+    fn swim_hb(&self, ctx: &mut Context<Self>) {
+        ctx.run_later(Duration::new(1, 0), |act, ctx| {
+            let mut send_dead: Vec<(GroupID, Vec<PublicKey>)> = vec![];
+            let mut send_probe: Vec<(GroupID, Probe, Vec<MemberUpdate>)> = vec![];
+            for (group, table) in act.tables.iter_mut() {
+                let (probes, dead) = table.next_probes();
+                for probe in probes {
+                    send_probe.push((group.clone(), probe, table.updates()));
+                }
+                send_dead.push((group.clone(), dead));
+            }
+
+            for (group, dead) in send_dead {
+                act.peers_dead(group, dead);
+            }
+            for (group, probe, updates) in send_probe {
+                act.send_probe(group, probe, updates);
+            }
+
+            act.swim_hb(ctx);
+        });
+    }
+
+    fn send_probe(&mut self, group: GroupID, probe: Probe, updates: Vec<MemberUpdate>) {
+        let (pk, socket, content) = match probe {
+            Probe::Ping(seq, pk, socket) => (pk, socket, P2PContent::Ping(seq, updates)),
+            Probe::PingReq(seq, pk, socket, target, target_socket) => (
+                pk,
+                socket,
+                P2PContent::PingReq(seq, target, target_socket, updates),
+            ),
+        };
+        self.send_message(group, pk, socket, content);
+    }
+
+    /// peers confirmed dead by failure detector, tell bridge they leave.
+    fn peers_dead(&self, group: GroupID, dead: Vec<PublicKey>) {
+        for pk in dead {
+            println!("DEBUG: peer confirmed dead: {}", pk);
+            self.send_bridge(ReceivePeerLeaveMessage(group.clone(), pk, false));
+        }
+    }
+
+    /// Timed task, include: NAT holepunching
+    fn hb(&self, ctx: &mut Context<Self>) {
+        ctx.run_later(Duration::new(5, 0), |act, ctx| {
+            // check nat hole punching
+            let mut need_delete: Vec<PublicKey> = act
+                .holepunching
+                .iter()
+                .filter_map(|(pk, (ins, socket, group, _tasks))| {
+                    if Instant::now().duration_since(ins.clone()) > Duration::new(8, 0) {
+                        println!("Again hole punching {} : {:?}", pk, socket);
+                        act.send_transport(act.new_p2p_message(
+                            group.clone(),
+                            pk.clone(),
+                            socket.clone(),
+                            P2PContent::HolePunching,
+                        ));
+                        Some(pk)
+                    } else {
+                        None
+                    }
+                })
+                .cloned()
+                .collect();
+
+            while !need_delete.is_empty() {
+                let pk = need_delete.pop().unwrap();
+                if let Some((_, socket, group, tasks)) = act.holepunching.remove(&pk) {
+                    act.relay_fallback(group, pk, socket, tasks);
+                }
+            }
+
+            // relayed peers hole punching again, and renew relay reservations
+            for (group, pk, socket) in act.relay.punches() {
+                act.send_message(group, pk, socket, P2PContent::HolePunching);
+            }
+            for (group, relay) in act.relay.renewals() {
+                act.send_to_peer(group, relay, P2PContent::RelayReserve);
+            }
+            act.relay.evict();
+
+            // observe external address by some udp peers
+            if act.nat.need_observe() {
+                let mut observers: HashMap<PublicKey, (GroupID, SocketAddr)> = HashMap::new();
+                for (group, table) in act.tables.iter() {
+                    for (pk, socket) in table.peers() {
+                        if act.transport_type(&socket, &P2PContent::HeartBeat) == TransportType::UDP
+                        {
+                            observers.insert(pk, (group.clone(), socket));
+                        }
+                    }
+                }
+
+                let mut observers: Vec<(PublicKey, (GroupID, SocketAddr))> =
+                    observers.into_iter().collect();
+                observers.shuffle(&mut rand::thread_rng());
+                let content = P2PContent::Observe;
+                for (pk, (group, socket)) in observers.into_iter().take(OBSERVE_PEERS) {
+                    act.nat.observe(pk.clone());
+                    act.send_message(group, pk, socket, content.clone());
+                }
+            }
+            act.nat.evict();
+
+            // path mtu probing
+            let mut send_probe: Vec<(GroupID, PublicKey, SocketAddr, P2PContent)> = vec![];
+            for (group, table) in act.tables.iter() {
+                for (pk, socket) in table.peers() {
+                    if act.transport_type(&socket, &P2PContent::HeartBeat) != TransportType::UDP
+                        || !act.versions.has_feature(&pk, FEATURE_MTU_PROBE)
+                    {
+                        continue;
+                    }
+                    let fragment_size = act.config.fragment_size;
+                    let mtu = act
+                        .mtus
+                        .entry(socket)
+                        .or_insert(PathMtu::new(fragment_size));
+                    if let Some(size) = mtu.next_probe() {
+                        let padding = vec![0u8; PathMtu::padding(size)];
+                        let content = P2PContent::MtuProbe(size as u32, padding);
+                        send_probe.push((group.clone(), pk, socket, content));
+                    }
+                }
+            }
+
+            while !send_probe.is_empty() {
+                let (group, pk, socket, content) = send_probe.pop().unwrap();
+                act.send_message(group, pk, socket, content);
+            }
+
+            // secure session rekey or handshake timeout
+            let mut send_handshake: Vec<(GroupID, PublicKey, Option<SocketAddr>, P2PContent)> =
+                vec![];
+            for (pk, secure) in act.secures.iter_mut() {
+                if secure.need_handshake() {
+                    let group = secure.group().clone();
+                    let content = secure.start(group.clone(), None);
+                    send_handshake.push((group, pk.clone(), secure.socket(), content));
+                }
+            }
+
+            while !send_handshake.is_empty() {
+                let (group, pk, socket, content) = send_handshake.pop().unwrap();
+                match socket {
+                    Some(socket) => act.send_message(group, pk, socket, content),
+                    None => act.send_to_peer(group, pk, content),
+                }
+            }
+
+            act.limiter.evict();
+            act.store_tables();
+
+            // scores decay, and the expired bans tell transports
+            let expired = act.scores.decay();
+            if act.ip_scores.decay() || expired {
+                act.send_bans();
+            }
+            if let Some(bans) = act.scores.take_changed() {
+                PeerBansStore::async_store(act.pk.clone(), bans, &act.storage);
+            }
+
+            // replicate records to the closest peers, and persist changed
+            for (group, key, record) in act.records.republishes() {
+                act.lookup(group, key, vec![], None, LookupKind::Store(record));
+            }
+            act.records.evict();
+            for (group, key) in act.providers.reprovides() {
+                act.lookup(group, key, vec![], None, LookupKind::Provide);
+            }
+            act.providers.evict();
+            act.store_records();
+
+            act.hb(ctx);
+        });
+    }
+}
+
+/// impl Actor for NetworkBridgeActor
+impl<A: P2PBridgeActor> Actor for P2PActor<A> {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        for transport in self.transports.values() {
+            let _ = transport
+                .register(P2PAddrMessage(ctx.address()))
+                .map_err(|_| println!("Send p2p addr to transport fail"));
+        }
+        if let Some(lan) = self.lan.as_ref() {
+            lan.do_send(P2PAddrMessage(ctx.address()));
+            self.lan_hb(ctx);
+        }
+
+        DHTGroupsStore::async_load(&self.pk, &self.storage, self, ctx);
+        DHTRecordKeysStore::async_load(&self.pk, &self.storage, self, ctx);
+        PeerBansStore::async_load(&self.pk, &self.storage, self, ctx);
+
+        self.hb(ctx);
+        self.swim_hb(ctx);
+        self.reliable_hb(ctx);
+        self.gossip_hb(ctx);
+        self.lookup_hb(ctx);
+        self.bootstrap_hb(ctx);
+        self.pex_hb(ctx);
+    }
+}
+
+impl<A: P2PBridgeActor> Handler<P2PBridgeAddrMessage<A>> for P2PActor<A> {
+    type Result = ();
+
+    fn handle(&mut self, msg: P2PBridgeAddrMessage<A>, _ctx: &mut Self::Context) -> Self::Result {
+        self.bridge = Some(msg.0);
+        // load saved tables
+    }
+}
+
+/// bridge broadcast event to all peers in group.
+impl<A: P2PBridgeActor> Handler<ReceiveBroadcastMessage> for P2PActor<A> {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReceiveBroadcastMessage, _ctx: &mut Self::Context) -> Self::Result {
+        let (group, event) = (msg.0, msg.2);
+        let item = GossipItem::new(&group, &self.psk, event);
+        self.broadcast(group, item, None);
+    }
+}
+
+/// udp send to socket fail many times, probe the peers of the socket first,
+/// failure detector decide if they leave.
+impl<A: P2PBridgeActor> Handler<P2PUnreachableMessage> for P2PActor<A> {
+    type Result = ();
+
+    fn handle(&mut self, msg: P2PUnreachableMessage, _ctx: &mut Self::Context) -> Self::Result {
+        let socket = msg.0;
+        for table in self.tables.values_mut() {
+            for (pk, peer_socket) in table.peers() {
+                if peer_socket == socket {
+                    println!("DEBUG: peer {} : {:?} maybe unreachable", pk, socket);
+                    table.probe_first(pk, socket);
+                }
+            }
+        }
+        self.mtus.remove(&socket);
+    }
+}
+
+/// connection open or close, send message to the socket by its transport.
+impl<A: P2PBridgeActor> Handler<TransportSocketMessage> for P2PActor<A> {
+    type Result = ();
+
+    fn handle(&mut self, msg: TransportSocketMessage, _ctx: &mut Self::Context) -> Self::Result {
+        let (socket, transport_type, is_open) = (msg.0, msg.1, msg.2);
+        if is_open {
+            self.sockets.insert(socket, transport_type);
+        } else if self.sockets.get(&socket) == Some(&transport_type) {
+            self.sockets.remove(&socket);
+        }
+    }
+}
+
+impl<A: P2PBridgeActor> P2PBridgeActor for P2PActor<A> {}
+
+/// bridge put value to the closest peers, or get value from them.
+impl<A: P2PBridgeActor> Handler<ReceiveDHTValueMessage> for P2PActor<A> {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReceiveDHTValueMessage, _ctx: &mut Self::Context) -> Self::Result {
+        let (group, key, value, ttl) = (msg.0, msg.1, msg.2, msg.3);
+        let target = H256::new(&key);
+        match value {
+            Some(value) => {
+                if value.len() > MAX_VALUE_SIZE {
+                    println!("DEBUG: DHT value too large: {}", value.len());
+                    return;
+                }
+                let record = DHTRecord::new(&group, &target, &self.psk, value, ttl);
+                self.records
+                    .put(group.clone(), target.clone(), record.clone());
+                self.lookup(group, target, vec![], None, LookupKind::Store(record));
+            }
+            None => match self.records.get(&group, &target) {
+                Some(record) => {
+                    let (value, ttl) = (record.value.clone(), record.ttl());
+                    self.send_bridge(ReceiveDHTValueMessage(group, key, Some(value), ttl));
+                }
+                None => self.lookup(group, target, vec![], None, LookupKind::Value(key)),
+            },
+        }
+    }
+}
+
+/// bridge announce self hold the content, or find the holders of content.
+impl<A: P2PBridgeActor> Handler<ReceiveProviderMessage> for P2PActor<A> {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReceiveProviderMessage, _ctx: &mut Self::Context) -> Self::Result {
+        let (group, key, is_provide) = (msg.0, msg.1, msg.2);
+        if is_provide {
+            self.providers.provide(group.clone(), key.clone());
+            self.lookup(group, key, vec![], None, LookupKind::Provide);
+        } else {
+            self.lookup(group, key, vec![], None, LookupKind::Providers(vec![]));
+        }
+    }
+}
+
+/// peer found in local network, add it to the same groups as tmp peer,
+/// if the group is bootstrapping, join it.
+impl<A: P2PBridgeActor> Handler<LanPeerMessage> for P2PActor<A> {
+    type Result = ();
+
+    fn handle(&mut self, msg: LanPeerMessage, _ctx: &mut Self::Context) -> Self::Result {
+        let (pk, socket, groups) = (msg.0, msg.1, msg.2);
+        if pk == self.pk || self.scores.is_banned(&pk) || self.ip_scores.is_banned(&socket.ip()) {
+            return;
+        }
+
+        for group in groups {
+            if !puzzle::verify(&pk, self.difficulty(&group)) {
+                println!("DEBUG: drop local peer with invalid puzzle: {}", pk);
+                continue;
+            }
+
+            let is_new = match self.tables.get_mut(&group) {
+                Some(table) if !table.contains(&pk) => {
+                    let is_new = table.get_socket_addr(&pk) != Some(socket);
+                    table.add_tmp_peer(&pk, Some(socket));
+                    is_new
+                }
+                _ => false,
+            };
+
+            if is_new {
+                println!("DEBUG: found peer {} in local network: {}", pk, socket);
+                if let Some(join) = self.bootstrap.join(&group) {
+                    self.join_peer(group, pk.clone(), join, Some(socket));
+                }
+            }
+        }
+    }
+}
+
+/// bridge start bootstrap group by the configured bootstrap peers.
+impl<A: P2PBridgeActor> Handler<ReceiveBootstrapMessage> for P2PActor<A> {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReceiveBootstrapMessage, _ctx: &mut Self::Context) -> Self::Result {
+        let (group, join) = (msg.0, msg.1);
+        if !self.tables.contains_key(&group) {
+            self.tables.insert(group.clone(), DHTTable::new(&self.pk));
+        }
+        if self.bootstrap.seeds().is_empty() {
+            println!("DEBUG: no bootstrap peers, wait peers join");
+        }
+        self.bootstrap.start(group, join);
+    }
+}
+
+/// bridge query the group bootstrapped, answer it if done.
+impl<A: P2PBridgeActor> Handler<ReceiveBootstrappedMessage> for P2PActor<A> {
+    type Result = ();
+
+    fn handle(
+        &mut self,
+        msg: ReceiveBootstrappedMessage,
+        _ctx: &mut Self::Context,
+    ) -> Self::Result {
+        if self.bootstrap.is_done(&msg.0) {
+            self.send_bridge(ReceiveBootstrappedMessage(msg.0));
+        }
+    }
+}
+
+/// bridge report peer misbehaviour, decrease the score, or ban it now.
+impl<A: P2PBridgeActor> Handler<ReceivePeerMisbehaveMessage> for P2PActor<A> {
+    type Result = ();
+
+    fn handle(
+        &mut self,
+        msg: ReceivePeerMisbehaveMessage,
+        _ctx: &mut Self::Context,
+    ) -> Self::Result {
+        let (pk, penalty, is_ban) = (msg.1, msg.2, msg.3);
+        if self.scores.is_banned(&pk) {
+            return;
+        }
+
+        if is_ban {
+            let ban = self.scores.ban(&pk, false);
+            self.ban_peer(pk, ban);
+        } else {
+            self.misbehave(&pk, Behaviour::Misbehave(penalty));
+        }
+    }
+}
+
+/// bridge query peer version, answer it, if not negotiated, negotiate first.
+impl<A: P2PBridgeActor> Handler<ReceivePeerVersionMessage> for P2PActor<A> {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReceivePeerVersionMessage, _ctx: &mut Self::Context) -> Self::Result {
+        let (group, peer_addr) = (msg.0, msg.1);
+        if let Some((version, features)) = self.versions.get(&peer_addr) {
+            self.send_bridge(ReceivePeerVersionMessage(
+                group, peer_addr, version, features,
+            ));
+        } else if let Some(nonce) = self.versions.need_negotiate(&peer_addr) {
+            let (min, max, features) = self.versions.local();
+            self.send_to_peer(
+                group,
+                peer_addr,
+                P2PContent::Version(min, max, features, nonce),
+            );
+        }
+    }
+}
+
+impl<A: P2PBridgeActor> Handler<ReceiveEventMessage> for P2PActor<A> {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReceiveEventMessage, _ctx: &mut Self::Context) -> Self::Result {
+        let (group, peer_addr, event) = (msg.0, msg.1, msg.2);
+        if peer_addr == self.pk {
+            return self.send_bridge(ReceiveEventMessage(group, peer_addr, event));
+        }
+
+        self.send_to_peer(group, peer_addr, P2PContent::Event(event));
+    }
+}
+
+impl<A: P2PBridgeActor> Handler<ReceiveReliableEventMessage> for P2PActor<A> {
+    type Result = ();
+
+    fn handle(
+        &mut self,
+        msg: ReceiveReliableEventMessage,
+        _ctx: &mut Self::Context,
+    ) -> Self::Result {
+        let (group, peer_addr, event) = (msg.0, msg.1, msg.2);
+        if peer_addr == self.pk {
+            return self.send_bridge(ReceiveEventMessage(group, peer_addr, event));
+        }
+
+        // peer not support reliable, send as normal event
+        if self.versions.lack_feature(&peer_addr, FEATURE_RELIABLE) {
+            return self.send_to_peer(group, peer_addr, P2PContent::Event(event));
+        }
+
+        let sent = self
+            .reliables
+            .entry((group.clone(), peer_addr.clone()))
+            .or_insert(ReliableChannel::new())
+            .send(event.clone());
+
+        match sent {
+            Some((id, seq)) => {
+                self.send_to_peer(group, peer_addr, P2PContent::Reliable(id, seq, event))
+            }
+            None => {
+                println!("DEBUG: too many reliable events waiting ack: {}", peer_addr);
+                self.send_bridge(ReceiveEventFailMessage(group, peer_addr, event));
+            }
+        }
+    }
+}
+
+/// event fail message is only sent to bridge.
+impl<A: P2PBridgeActor> Handler<ReceiveEventFailMessage> for P2PActor<A> {
+    type Result = ();
+
+    fn handle(&mut self, _msg: ReceiveEventFailMessage, _ctx: &mut Self::Context) -> Self::Result {}
+}
+
+/// bridge cancel the reliable events waiting to send to peer.
+impl<A: P2PBridgeActor> Handler<ReceiveEventCancelMessage> for P2PActor<A> {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReceiveEventCancelMessage, _ctx: &mut Self::Context) -> Self::Result {
+        if let Some(channel) = self.reliables.get_mut(&(msg.0, msg.1)) {
+            channel.reset();
+        }
+    }
+}
+
+impl<A: P2PBridgeActor> Handler<ReceivePeerJoinMessage> for P2PActor<A> {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReceivePeerJoinMessage, _ctx: &mut Self::Context) -> Self::Result {
+        // join group to p2p
+        let (group, peer_addr, result, socket_addr) = (msg.0, msg.1, msg.2, msg.3);
+        self.join_peer(group, peer_addr, result, socket_addr);
+    }
+}
+
+impl<A: P2PBridgeActor> Handler<ReceivePeerJoinResultMessage> for P2PActor<A> {
+    type Result = ();
+
+    fn handle(
+        &mut self,
+        msg: ReceivePeerJoinResultMessage,
+        _ctx: &mut Self::Context,
+    ) -> Self::Result {
+        let (group, peer_addr, result, helps) = (msg.0, msg.1, msg.2, msg.3);
+        println!("DEBUG: peer join: {}", result);
+        let need_store = if let Some(table) = self.tables.get_mut(&group) {
+            let mut need_store = false;
+            if let Some(socket) = table.get_socket_addr(&peer_addr) {
+                if result {
+                    if table.fixed_peer(&peer_addr) {
+                        need_store = true;
+                    }
+
+                    let dht: Vec<(PublicKey, SocketAddr)> = helps
+                        .iter()
+                        .filter_map(|peer_addr| {
+                            if let Some(addr) = table.get_socket_addr(peer_addr) {
+                                Some((peer_addr.clone(), addr))
+                            } else {
+                                None
+                            }
+                        })
+                        .collect();
+
+                    self.send_message(group, peer_addr, socket, P2PContent::DHT(dht));
+                } else {
+                    self.send_message(group, peer_addr, socket, P2PContent::Leave);
+                }
+            }
+            need_store
+        } else {
+            false
+        };
+
+        if need_store {
+            self.store_tables();
+        }
+    }
+}
+
+impl<A: P2PBridgeActor> Handler<ReceivePeerLeaveMessage> for P2PActor<A> {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReceivePeerLeaveMessage, _ctx: &mut Self::Context) -> Self::Result {
+        let (group, peer_addr, is_force) = (msg.0, msg.1, msg.2);
+        let socket = if let Some(table) = self.tables.get_mut(&group) {
+            if is_force {
+                let socket = table.get_socket_addr(&peer_addr);
+                table.remove_peer(&peer_addr);
+                socket
+            } else {
+                // do some other connect
+                None
+            }
+        } else {
+            None
+        };
+
+        if let Some(socket) = socket {
+            self.send_message(group, peer_addr, socket, P2PContent::Leave);
+        }
+    }
+}
+
+impl<A: P2PBridgeActor> P2PActor<A> {
+    /// handle received message, routed message is unwrapped from route content,
+    /// it can not change the peers table.
+    fn receive(&mut self, msg: P2PMessage, routed: bool) {
+        let (head, content, socket) = (msg.0, msg.1, msg.2);
+        if self.ip_scores.is_banned(&socket.ip()) || self.scores.is_banned(&head.from) {
+            return;
+        }
+
+        // check signature, drop the message if not signed by from
+        let body_bytes = bincode::serialize(&P2PBody(content.clone())).unwrap_or(vec![]);
+        if !head.verify(&body_bytes) {
+            self.invalid_messages += 1;
+            println!(
+                "DEBUG: drop invalid signature message from: {}, total: {}",
+                socket, self.invalid_messages
+            );
+            let behaviour = match content {
+                P2PContent::None => Behaviour::Undecodable,
+                _ => Behaviour::BadSignature,
+            };
+            if !routed {
+                self.misbehave_socket(socket, behaviour);
+            }
+            return;
+        }
+
+        let (group, from, to) = (head.gid.clone(), head.from.clone(), head.to.clone());
+
+        // check self group
+        if !self.tables.contains_key(&group) {
+            return;
+        }
+
+        if from == self.pk {
+            return;
+        }
+
+        // check inbound rate limit of peer, and group limit of members
+        let bytes = HEAD_LENGTH + body_bytes.len();
+        match self.limiter.check(&from, bytes) {
+            Limit::Pass => {}
+            Limit::Throttle => {
+                self.misbehave(&from, Behaviour::Throttled);
+                return;
+            }
+            Limit::Disconnect => {
+                self.disconnect_peer(group, from.clone(), socket);
+                self.misbehave(&from, Behaviour::Flood);
+                return;
+            }
+        }
+
+        let is_member = self
+            .tables
+            .get(&group)
+            .map(|table| table.contains(&from))
+            .unwrap_or(false);
+        if is_member && !self.limiter.check_group(&group, bytes) {
+            println!("DEBUG: drop message over group limit from: {}", from);
+            return;
+        }
+
+        // check version include, negotiation content can use any version
+        if !content.is_version() {
+            if self.versions.is_rejected(&from) {
+                println!("DEBUG: drop rejected peer message from: {}", from);
+                return;
+            }
+
+            if !self.versions.is_supported(head.ver) {
+                println!(
+                    "DEBUG: drop unsupported version {} from: {}",
+                    head.ver, from
+                );
+                return;
+            }
+        }
+
+        // not send to self, forward to the peer closest to it
+        if to != self.pk {
+            if self.relay.is_reserved(&group, &from) || self.relay.is_reserved(&group, &to) {
+                self.relay_forward(head, body_bytes);
+            } else if content.can_route() && !self.router.seen(&head.sign) {
+                // not wrapped by route, the sender is the first hop
+                self.route(head, content, MAX_HOPS - 1, Some(socket));
+            }
+            return;
+        }
+
+        // remove from hole punching
+        if self.holepunching.contains_key(&from) {
+            if let Some((_, _, _, mut tasks)) = self.holepunching.remove(&from) {
+                loop {
+                    if let Some(message) = tasks.pop() {
+                        self.send_transport(message);
+                    } else {
+                        break;
+                    }
+                }
+            }
+        }
+
+        // open encrypted content, only the content not need seal can be plain
+        let content = match content {
+            P2PContent::Sealed(id, ciphertext) => {
+                match self
+                    .secures
+                    .get_mut(&from)
+                    .and_then(|secure| secure.open(&id, &ciphertext))
+                {
+                    Some(content) if content.need_seal() => content,
+                    _ => {
+                        println!("DEBUG: drop can not open message from: {}", from);
+                        return;
+                    }
+                }
+            }
+            content if content.need_seal() => {
+                println!("DEBUG: drop not sealed message from: {}", from);
+                self.misbehave(&from, Behaviour::Invalid);
+                return;
+            }
+            content => content,
+        };
+
+        if routed && !content.can_route() {
+            println!("DEBUG: drop routed message can not route from: {}", from);
+            return;
+        }
+
+        // version negotiation, agree the highest common version
+        match content {
+            P2PContent::Version(min, max, features, nonce) => {
+                let local = self.versions.local();
+                match negotiate(local, (min, max, features)) {
+                    Ok((version, features)) => {
+                        self.send_message(
+                            group.clone(),
+                            from.clone(),
+                            socket,
+                            P2PContent::VersionOk(local.0, local.1, local.2),
+                        );
+                        self.agree_version(group, from, version, features);
+                    }
+                    Err(reason) => {
+                        self.send_message(
+                            group.clone(),
+                            from.clone(),
+                            socket,
+                            P2PContent::VersionReject(nonce, reason.clone()),
+                        );
+                        self.reject_version(group, from, reason);
+                    }
+                }
+                return;
+            }
+            P2PContent::VersionOk(min, max, features) => {
+                match negotiate(self.versions.local(), (min, max, features)) {
+                    Ok((version, features)) => self.agree_version(group, from, version, features),
+                    Err(reason) => self.reject_version(group, from, reason),
+                }
+                return;
+            }
+            P2PContent::VersionReject(nonce, reason) => {
+                // only the answer of our negotiation, replayed reject is dropped
+                if self.versions.is_negotiating(&from, nonce) {
+                    self.reject_version(group, from, reason);
+                } else {
+                    println!("DEBUG: drop unexpected version reject from: {}", from);
+                }
+                return;
+            }
+            _ => {
+                if let Some(nonce) = self.versions.need_negotiate(&from) {
+                    let (min, max, features) = self.versions.local();
+                    self.send_message(
+                        group.clone(),
+                        from.clone(),
+                        socket,
+                        P2PContent::Version(min, max, features, nonce),
+                    );
+                }
+            }
+        }
+
+        self.scores.report(&from, Behaviour::Good);
+        let content = self.check_puzzle(&group, &from, content);
+
+        let table = self.tables.get_mut(&group).unwrap();
+        match content {
+            P2PContent::HeartBeat => {
+                table.update_hb_peers(&from);
+                self.send_message(group, from, socket, P2PContent::HeartBeatOk);
+            }
+            P2PContent::HeartBeatOk => {
+                table.update_hb_peers(&from);
+            }
+            P2PContent::Ping(seq, updates) => {
+                if table.contains(&from) {
+                    table.update_hb_peers(&from);
+                    table.apply_updates(updates);
+                }
+                let updates = table.updates();
+                self.send_message(group, from, socket, P2PContent::Ack(seq, updates));
+            }
+            P2PContent::Ack(seq, updates) => {
+                if !table.contains(&from) {
+                    return;
+                }
                 table.update_hb_peers(&from);
+                table.apply_updates(updates);
+                if let Some(Acked::Forward(pk, pk_socket, seq)) = table.ack(&from, seq) {
+                    let updates = table.updates();
+                    self.send_message(group, pk, pk_socket, P2PContent::Ack(seq, updates));
+                }
+            }
+            P2PContent::PingReq(seq, target, target_socket, updates) => {
+                if !table.contains(&from) {
+                    return;
+                }
+                table.apply_updates(updates);
+                let probe = table.ping_req(from, socket, seq, target, target_socket);
+                let updates = table.updates();
+                self.send_probe(group, probe, updates);
+            }
+            P2PContent::Pex(need_answer, records) => {
+                if !table.contains(&from) {
+                    return;
+                }
+
+                if need_answer {
+                    let content = P2PContent::Pex(false, self.pex_records(&group, &from));
+                    self.send_message(group.clone(), from.clone(), socket, content);
+                }
+                self.pex_receive(group, from, records);
+            }
+            P2PContent::DHT(mut pk_sockets) => {
+                println!("DEBUG: receive DHT {}", from);
+                if table.fixed_peer(&from) {
+                    self.store_tables();
+                }
+
+                // lookup self by the joined peer and helpers, find the closest peers
+                let mut seeds = pk_sockets.clone();
+                seeds.push((from.clone(), socket));
+                let target = node_id(&self.pk);
+                self.lookup(
+                    group.clone(),
+                    target,
+                    seeds,
+                    Some(from.clone()),
+                    LookupKind::Node,
+                );
+
+                let pks = pk_sockets.iter().map(|(pk, _)| pk.clone()).collect();
+                self.send_bridge(ReceivePeerJoinResultMessage(
+                    group.clone(),
+                    from.clone(),
+                    true,
+                    pks,
+                ));
+                loop {
+                    if let Some((other_pk, socket_addr)) = pk_sockets.pop() {
+                        self.connect_peer(group.clone(), other_pk, socket_addr, from.clone());
+                    } else {
+                        break;
+                    }
+                }
+            }
+            P2PContent::Hole(pk, socket_addr) => {
+                if table.check_add(&from) {
+                    println!("DEBUG: need hole punching : {}", socket);
+                    self.send_message(group, pk, socket_addr, P2PContent::HolePunching);
+                }
+            }
+            P2PContent::HolePunching => {
+                println!("DEBUG: success hole punching : {}", socket);
+                table.fixed_tmp_peer(&from, socket);
+                if self.relay.upgrade(&from) {
+                    println!("DEBUG: upgrade relay to direct: {}", from);
+                }
+                self.send_message(group, from, socket, P2PContent::HolePunchingOk);
+            }
+
+            P2PContent::HolePunchingOk => {
+                println!("DEBUG: success hole punching : {}", socket);
+                table.fixed_tmp_peer(&from, socket);
+                if self.relay.upgrade(&from) {
+                    println!("DEBUG: upgrade relay to direct: {}", from);
+                }
+            }
+
+            P2PContent::Leave => {
+                println!("DEBUG: receive peer leave: {}", from);
+                table.remove_peer(&from);
+                self.send_bridge(ReceivePeerLeaveMessage(group, from, true));
+            }
+            P2PContent::Join(join_bytes) => {
+                println!("DEBUG: receive peer join: {}", from);
+                table.add_tmp_peer(&from, Some(socket));
+                self.send_bridge(ReceivePeerJoinMessage(
+                    group,
+                    from,
+                    join_bytes,
+                    Some(socket),
+                ));
+            }
+            P2PContent::Event(event_bytes) => {
+                if table.contains(&from) {
+                    self.send_bridge(ReceiveEventMessage(group, from, event_bytes));
+                }
+            }
+            P2PContent::Reliable(id, seq, event_bytes) => {
+                if !table.contains(&from) {
+                    return;
+                }
+
+                let (need_ack, events) = self
+                    .reliables
+                    .entry((group.clone(), from.clone()))
+                    .or_insert(ReliableChannel::new())
+                    .receive(id, seq, event_bytes);
+
+                if need_ack {
+                    self.send_message(
+                        group.clone(),
+                        from.clone(),
+                        socket,
+                        P2PContent::ReliableAck(id, seq),
+                    );
+                }
+
+                for event_bytes in events {
+                    self.send_bridge(ReceiveEventMessage(
+                        group.clone(),
+                        from.clone(),
+                        event_bytes,
+                    ));
+                }
+            }
+            P2PContent::ReliableAck(id, seq) => {
+                if let Some(channel) = self.reliables.get_mut(&(group.clone(), from.clone())) {
+                    channel.ack(id, seq);
+                }
+                self.send_congestion(&group, &from, true);
+            }
+            P2PContent::Gossip(item) => {
+                // only members can broadcast, the origin out of table is checked
+                // by the member forwarding it, and banned origin is dropped.
+                if !table.contains(&from) || self.scores.is_banned(&item.origin) {
+                    println!("DEBUG: drop gossip not from member: {}", from);
+                } else if item.verify(&group) {
+                    self.broadcast(group, item, Some(from));
+                } else {
+                    println!("DEBUG: drop invalid gossip from: {}", from);
+                    self.misbehave(&from, Behaviour::Invalid);
+                }
+            }
+            P2PContent::GossipIHave(ids) => {
+                if table.contains(&from) {
+                    self.gossip.have(group, from, ids);
+                }
+            }
+            P2PContent::GossipIWant(ids) => {
+                if !table.contains(&from) {
+                    return;
+                }
+                let items: Vec<GossipItem> = ids
+                    .iter()
+                    .filter_map(|id| self.gossip.get(&group, id).cloned())
+                    .collect();
+                for item in items {
+                    self.send_to_peer(group.clone(), from.clone(), P2PContent::Gossip(item));
+                }
+            }
+            P2PContent::Route(hops, head_bytes, body_bytes) => {
+                if head_bytes.len() < HEAD_LENGTH {
+                    return;
+                }
+                let inner_head = P2PHead::decode(&head_bytes);
+                if inner_head.to != self.pk && hops == 0 {
+                    return;
+                }
+                if self.router.seen(&inner_head.sign) {
+                    return;
+                }
+
+                // origin message decode only when it will be used.
+                let inner = match bincode::deserialize::<P2PBody>(&body_bytes) {
+                    Ok(body) => body.0,
+                    Err(_) => return,
+                };
+
+                if inner_head.to == self.pk {
+                    self.receive(P2PMessage(inner_head, inner, socket), true);
+                } else if inner.can_route() {
+                    self.route(inner_head, inner, hops - 1, Some(socket));
+                }
+            }
+            P2PContent::RelayReserve => {
+                // only members can reserve relay
+                let content = if !table.contains(&from) {
+                    P2PContent::RelayReserveReject("not group member".to_owned())
+                } else {
+                    match self.relay.reserve(group.clone(), from.clone()) {
+                        Ok(time) => P2PContent::RelayReserveOk(time),
+                        Err(reason) => P2PContent::RelayReserveReject(reason),
+                    }
+                };
+                self.send_message(group, from, socket, content);
+            }
+            P2PContent::RelayReserveOk(time) => {
+                for (pk, peer_socket, tasks) in
+                    self.relay.reserved(group.clone(), from.clone(), time)
+                {
+                    self.relay
+                        .add_relayed(pk, group.clone(), from.clone(), peer_socket);
+                    for message in tasks {
+                        self.send_relay(from.clone(), message.0, message.1);
+                    }
+                }
+            }
+            P2PContent::RelayReserveReject(reason) => {
+                let waitings = self.relay.rejected(group.clone(), from.clone());
+                println!(
+                    "DEBUG: relay {} reject: {}, drop {} peers",
+                    from,
+                    reason,
+                    waitings.len()
+                );
+                for (pk, _, _) in waitings {
+                    self.connect_fail(group.clone(), pk);
+                }
             }
-            P2PContent::DHT(mut pk_sockets) => {
-                println!("DEBUG: receive DHT {}", from);
-                if table.fixed_peer(&from) {
-                    DHTTableStore::async_store(self.pk.clone(), self.tables.clone(), &self.storage);
+            P2PContent::Relay(head_bytes, body_bytes) => {
+                // only members can relay
+                if head_bytes.len() < HEAD_LENGTH || !table.contains(&from) {
+                    return;
+                }
+                let inner_head = P2PHead::decode(&head_bytes);
+                if inner_head.gid != group {
+                    return;
                 }
 
-                let pks = pk_sockets.iter().map(|(pk, _)| pk.clone()).collect();
-                self.send_bridge(ReceivePeerJoinResultMessage(group.clone(), from, true, pks));
-                loop {
-                    if let Some((other_pk, socket_addr)) = pk_sockets.pop() {
-                        let not_contain = self
-                            .tables
-                            .get(&group)
-                            .and_then(|t| Some(t.check_add(&other_pk)));
-                        if let Some(true) = not_contain {
-                            self.tables
-                                .get_mut(&group)
-                                .map(|t| t.add_tmp_peer(&other_pk, None));
-
-                            println!("DEBUG: start hole punching {}, {}", other_pk, socket_addr);
-                            self.send_session(self.new_p2p_message(
-                                group.clone(),
-                                other_pk.clone(),
-                                socket_addr,
-                                P2PContent::HolePunching,
-                            ));
-
-                            self.holepunching
-                                .entry(other_pk)
-                                .and_modify(|time| {
-                                    time.0 = Instant::now();
-                                })
-                                .or_insert((Instant::now(), socket_addr, group.clone(), vec![]));
-                        }
-                    } else {
-                        break;
+                if inner_head.to != self.pk {
+                    return self.relay_forward(inner_head, body_bytes);
+                }
+
+                if !inner_head.verify(&body_bytes) {
+                    println!("DEBUG: drop invalid relayed message by: {}", from);
+                    return;
+                }
+                let inner = match bincode::deserialize::<P2PBody>(&body_bytes) {
+                    Ok(body) => body.0,
+                    Err(_) => return,
+                };
+
+                // the peer only reached by this relay, keep it in relayed peers,
+                // the reply to relay socket will wrap as relay message.
+                self.relay
+                    .add_relayed(inner_head.from.clone(), group, from, None);
+                self.receive(P2PMessage(inner_head, inner, socket), false);
+            }
+            P2PContent::Observe => {
+                // other peer send probe to it, check if NAT is restricted
+                let mut helpers = table.peers();
+                helpers.retain(|(pk, _)| pk != &from);
+                let helper = helpers.choose(&mut rand::thread_rng()).cloned();
+
+                if self.transport_type(&socket, &P2PContent::HeartBeat) != TransportType::UDP {
+                    return;
+                }
+
+                self.send_message(
+                    group.clone(),
+                    from.clone(),
+                    socket,
+                    P2PContent::Observed(socket),
+                );
+                if let Some((helper, helper_socket)) = helper {
+                    self.send_message(group, helper, helper_socket, P2PContent::ObserveHelp(from));
+                }
+            }
+            P2PContent::Observed(addr) => {
+                if self.transport_type(&socket, &P2PContent::HeartBeat) == TransportType::UDP
+                    && self.nat.observed(from, addr)
+                {
+                    println!(
+                        "DEBUG: observed external: {:?}, nat: {:?}",
+                        self.nat.external(),
+                        self.nat.nat_type()
+                    );
+                }
+            }
+            P2PContent::ObserveHelp(pk) => {
+                // only probe the peer in table by the address self observed
+                if table.contains(&from) && table.contains(&pk) {
+                    if let Some(addr) = table.get_socket_addr(&pk) {
+                        self.send_message(group, pk, addr, P2PContent::ObserveProbe);
                     }
                 }
             }
-            P2PContent::Hole(pk, socket_addr) => {
-                if table.check_add(&from) {
-                    println!("DEBUG: need hole punching : {}", socket);
-                    self.send_session(self.new_p2p_message(
-                        group,
-                        pk,
-                        socket_addr,
-                        P2PContent::HolePunching,
-                    ));
+            P2PContent::ObserveProbe => {
+                if table.get_socket_addr(&from).is_none() {
+                    self.nat.probed();
                 }
             }
-            P2PContent::HolePunching => {
-                println!("DEBUG: success hole punching : {}", socket);
-                table.fixed_tmp_peer(&from, socket);
-                self.send_session(self.new_p2p_message(
+            P2PContent::FindNode(id, target) => {
+                let peers = self.closest_peers(&group, &target, &from);
+                self.send_message(group, from, socket, P2PContent::FindNodeOk(id, peers));
+            }
+            P2PContent::FindNodeOk(id, peers) => {
+                let responded = self
+                    .lookups
+                    .get_mut(&id)
+                    .map(|lookup| lookup.group() == &group && lookup.response(&from, peers))
+                    .unwrap_or(false);
+                if responded {
+                    self.lookup_step(id);
+                }
+            }
+            P2PContent::Store(key, record) => {
+                if !record.verify(&group, &key) {
+                    println!("DEBUG: drop invalid record from: {}", from);
+                    self.misbehave(&from, Behaviour::Invalid);
+                } else if table.contains(&from) {
+                    self.records.put(group, key, record);
+                }
+            }
+            P2PContent::FindValue(id, key) => {
+                let content = match self.records.get(&group, &key) {
+                    Some(record) => P2PContent::FindValueOk(id, Some(record.clone()), vec![]),
+                    None => {
+                        P2PContent::FindValueOk(id, None, self.closest_peers(&group, &key, &from))
+                    }
+                };
+                self.send_message(group, from, socket, content);
+            }
+            P2PContent::AddProvider(key) => {
+                if table.contains(&from) {
+                    self.providers.add(group, key, from, socket);
+                }
+            }
+            P2PContent::GetProviders(id, key) => {
+                let mut holders = self.providers.get(&group, &key);
+                if self.providers.is_local(&group, &key) {
+                    holders.push((self.pk.clone(), self.nat.advertised()));
+                }
+                let peers = self.closest_peers(&group, &key, &from);
+                self.send_message(
                     group,
                     from,
                     socket,
-                    P2PContent::HolePunchingOk,
-                ));
+                    P2PContent::GetProvidersOk(id, holders, peers),
+                );
             }
+            P2PContent::GetProvidersOk(id, holders, peers) => {
+                let responded = self
+                    .lookups
+                    .get_mut(&id)
+                    .map(|lookup| lookup.group() == &group && lookup.response(&from, peers))
+                    .unwrap_or(false);
+                if !responded {
+                    return;
+                }
 
-            P2PContent::HolePunchingOk => {
-                println!("DEBUG: success hole punching : {}", socket);
-                table.fixed_tmp_peer(&from, socket);
+                let mut enough = false;
+                if let Some(lookup) = self.lookups.get_mut(&id) {
+                    if let LookupKind::Providers(found) = lookup.kind_mut() {
+                        for (pk, addr) in holders {
+                            if pk == self.pk || found.iter().any(|(p, _)| p == &pk) {
+                                continue;
+                            }
+                            found.push((pk, addr));
+                        }
+                        enough = found.len() >= K_BUCKET;
+                    }
+                }
+                if enough {
+                    self.lookup_finish(id);
+                } else {
+                    self.lookup_step(id);
+                }
             }
+            P2PContent::FindValueOk(id, record, peers) => {
+                let responded = self
+                    .lookups
+                    .get_mut(&id)
+                    .map(|lookup| lookup.group() == &group && lookup.response(&from, peers))
+                    .unwrap_or(false);
+                if !responded {
+                    return;
+                }
 
-            P2PContent::Leave => {
-                println!("DEBUG: receive peer leave: {}", from);
-                table.remove_peer(&from);
-                self.send_bridge(ReceivePeerLeaveMessage(group, from, true));
+                let lookups = &self.lookups;
+                let found = record.filter(|r| !r.is_expired()).and_then(|record| {
+                    match lookups.get(&id).map(|l| (l.target(), l.kind())) {
+                        Some((key, LookupKind::Value(origin))) if record.verify(&group, key) => {
+                            Some((origin.clone(), record))
+                        }
+                        _ => None,
+                    }
+                });
+                match found {
+                    Some((key, record)) => {
+                        self.lookups.remove(&id);
+                        let ttl = record.ttl();
+                        self.send_bridge(ReceiveDHTValueMessage(
+                            group,
+                            key,
+                            Some(record.value),
+                            ttl,
+                        ));
+                    }
+                    None => self.lookup_step(id),
+                }
             }
-            P2PContent::Join(join_bytes) => {
-                println!("DEBUG: receive peer join: {}", from);
-                table.add_tmp_peer(&from, Some(socket));
-                self.send_bridge(ReceivePeerJoinMessage(
-                    group,
-                    from,
-                    join_bytes,
-                    Some(socket),
-                ));
+            P2PContent::MtuProbe(size, _) => {
+                self.send_message(group, from, socket, P2PContent::MtuProbeOk(size));
             }
-            P2PContent::Event(event_bytes) => {
-                if table.contains(&from) {
-                    self.send_bridge(ReceiveEventMessage(group, from, event_bytes));
+            P2PContent::MtuProbeOk(size) => {
+                if let Some(mtu) = self.mtus.get_mut(&socket) {
+                    if mtu.confirm(size as usize) {
+                        let size = mtu.size();
+                        println!("DEBUG: path mtu {} : {}", socket, size);
+                        self.send_session(P2PMtuMessage(socket, size));
+                    }
+                }
+            }
+            P2PContent::Handshake(remote, nonce, timestamp) => {
+                // both start handshake at same time, bigger public key is initiator
+                let is_initiator = self.pk > from;
+                let secure = self
+                    .secures
+                    .entry(from.clone())
+                    .or_insert(SecureSession::new(group.clone()));
+                if secure.is_handshaking() && is_initiator {
+                    return;
+                }
+
+                match secure.respond(group.clone(), &remote, nonce, timestamp) {
+                    Some(content) => {
+                        self.send_message(group, from.clone(), socket, content);
+                        self.flush_pendings(&from);
+                    }
+                    None => println!("DEBUG: drop old or replayed handshake from: {}", from),
+                }
+            }
+            P2PContent::HandshakeOk(remote, remote_nonce, nonce) => {
+                let completed = self
+                    .secures
+                    .get_mut(&from)
+                    .map(|secure| secure.complete(&remote, remote_nonce, nonce))
+                    .unwrap_or(false);
+                if completed {
+                    self.flush_pendings(&from);
                 }
             }
             _ => {}
@@ -499,26 +2419,342 @@ impl<A: P2PBridgeActor> Handler<P2PMessage> for P2PActor<A> {
     }
 }
 
+/// handle receive P2PMessage from UDP
+impl<A: P2PBridgeActor> Handler<P2PMessage> for P2PActor<A> {
+    type Result = ();
+
+    fn handle(&mut self, msg: P2PMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.receive(msg, false);
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 struct DHTTableStore(PublicKey, HashMap<GroupID, DHTTable>);
 
 impl Entity for DHTTableStore {
     type Key = String;
 
+    fn key(&self) -> Self::Key {
+        format!("dht-{}", self.0)
+    }
+}
+
+/// tables stored by the old binary tree format, will migrate to k-buckets.
+#[derive(Serialize, Deserialize)]
+struct LegacyDHTTableStore(PublicKey, HashMap<GroupID, LegacyDHTTable>);
+
+impl Entity for LegacyDHTTableStore {
+    type Key = String;
+
     fn key(&self) -> Self::Key {
         format!("{}", self.0)
     }
 }
 
 impl DHTTableStore {
-    pub fn async_store(
-        pk: PublicKey,
-        table: HashMap<GroupID, DHTTable>,
+    /// load the tables stored in one key, store them as per peer keys.
+    pub fn async_load<A: P2PBridgeActor>(
+        pk: &PublicKey,
+        addr: &Addr<DiskStorageActor>,
+        p2p_actor: &P2PActor<A>,
+        ctx: &mut <P2PActor<A> as Actor>::Context,
+    ) {
+        let key = format!("dht-{}", pk);
+        let legacy_key = format!("{}", pk);
+        addr.send(EntityRead::<DHTTableStore>(key.clone()))
+            .into_actor(p2p_actor)
+            .then(move |res, act, ctx| {
+                match res {
+                    Ok(Ok(e)) => {
+                        println!("DEBUG: migrate {} tables to per peer keys", e.1.len());
+                        act.merge_tables(e.1);
+                        act.store_tables();
+                        let _ = act.storage.do_send(EntityDelete::<DHTTableStore>(key));
+                    }
+                    _ => Self::async_migrate(legacy_key, act, ctx),
+                }
+
+                actor_ok(())
+            })
+            .wait(ctx);
+    }
+
+    /// load the old format tables, store them as k-buckets format.
+    fn async_migrate<A: P2PBridgeActor>(
+        key: String,
+        p2p_actor: &P2PActor<A>,
+        ctx: &mut <P2PActor<A> as Actor>::Context,
+    ) {
+        p2p_actor
+            .storage
+            .send(EntityRead::<LegacyDHTTableStore>(key.clone()))
+            .into_actor(p2p_actor)
+            .then(move |res, act, _ctx| {
+                if let Ok(Ok(e)) = res {
+                    println!("DEBUG: migrate {} tables to k-buckets", e.1.len());
+                    act.merge_tables(
+                        e.1.into_iter()
+                            .map(|(group, table)| (group, table.migrate()))
+                            .collect(),
+                    );
+                    act.store_tables();
+                    let _ = act
+                        .storage
+                        .do_send(EntityDelete::<LegacyDHTTableStore>(key));
+                }
+
+                actor_ok(())
+            })
+            .wait(ctx);
+    }
+
+    pub fn _async_delete(pk: PublicKey, _addr: &Addr<DiskStorageActor>) {
+        println!("DEBUG: async delete tables: {}", pk);
+    }
+}
+
+/// groups of self tables.
+#[derive(Serialize, Deserialize, Clone)]
+struct DHTGroupsStore(PublicKey, Vec<GroupID>);
+
+impl Entity for DHTGroupsStore {
+    type Key = String;
+
+    fn key(&self) -> Self::Key {
+        format!("dht-groups-{}", self.0)
+    }
+}
+
+/// peers of one group table.
+#[derive(Serialize, Deserialize, Clone)]
+struct DHTPeersStore(PublicKey, GroupID, Vec<PublicKey>);
+
+impl Entity for DHTPeersStore {
+    type Key = String;
+
+    fn key(&self) -> Self::Key {
+        format!("dht-peers-{}-{}", self.0, self.1.to_string())
+    }
+}
+
+/// liveness of one peer in group table.
+#[derive(Serialize, Deserialize, Clone)]
+struct DHTPeerStore(PublicKey, GroupID, PublicKey, PeerMeta);
+
+impl Entity for DHTPeerStore {
+    type Key = String;
+
+    fn key(&self) -> Self::Key {
+        Self::key_of(&self.0, &self.1, &self.2)
+    }
+}
+
+impl DHTGroupsStore {
+    pub fn async_store(groups: DHTGroupsStore, addr: &Addr<DiskStorageActor>) {
+        let _ = try_resend_times(addr.clone(), EntityWrite(groups), DEFAULT_TIMES)
+            .map_err(|_| println!("Send to storage fail"));
+    }
+
+    /// load every group peers, if not have, load the old format tables.
+    pub fn async_load<A: P2PBridgeActor>(
+        pk: &PublicKey,
+        addr: &Addr<DiskStorageActor>,
+        p2p_actor: &P2PActor<A>,
+        ctx: &mut <P2PActor<A> as Actor>::Context,
+    ) {
+        let storage_addr = addr.clone();
+        let pk = pk.clone();
+        addr.send(EntityRead::<DHTGroupsStore>(format!("dht-groups-{}", pk)))
+            .into_actor(p2p_actor)
+            .then(move |res, act, ctx| {
+                match res {
+                    Ok(Ok(e)) => {
+                        for group in e.1 {
+                            DHTPeersStore::async_load(group, act, ctx);
+                        }
+                    }
+                    _ => DHTTableStore::async_load(&pk, &storage_addr, act, ctx),
+                }
+
+                actor_ok(())
+            })
+            .wait(ctx);
+    }
+}
+
+impl DHTPeersStore {
+    pub fn async_store(peers: DHTPeersStore, addr: &Addr<DiskStorageActor>) {
+        let _ = try_resend_times(addr.clone(), EntityWrite(peers), DEFAULT_TIMES)
+            .map_err(|_| println!("Send to storage fail"));
+    }
+
+    /// load the peers liveness of group, restore the table.
+    fn async_load<A: P2PBridgeActor>(
+        group: GroupID,
+        p2p_actor: &P2PActor<A>,
+        ctx: &mut <P2PActor<A> as Actor>::Context,
+    ) {
+        let storage_addr = p2p_actor.storage.clone();
+        let key = format!("dht-peers-{}-{}", p2p_actor.pk, group.to_string());
+        p2p_actor
+            .storage
+            .send(EntityRead::<DHTPeersStore>(key))
+            .into_actor(p2p_actor)
+            .then(move |res, act, ctx| {
+                if let Ok(Ok(e)) = res {
+                    let reads: Vec<_> =
+                        e.2.iter()
+                            .map(|peer| {
+                                storage_addr.send(EntityRead::<DHTPeerStore>(DHTPeerStore::key_of(
+                                    &e.0, &e.1, peer,
+                                )))
+                            })
+                            .collect();
+
+                    join_all(reads)
+                        .into_actor(act)
+                        .then(move |res, act, _ctx| {
+                            if let Ok(peers) = res {
+                                let peers: Vec<(PublicKey, PeerMeta)> = peers
+                                    .into_iter()
+                                    .filter_map(|peer| peer.ok())
+                                    .map(|peer| (peer.2, peer.3))
+                                    .collect();
+                                println!("DEBUG: restore {} peers", peers.len());
+                                let base = act.pk.clone();
+                                act.tables
+                                    .entry(group)
+                                    .or_insert(DHTTable::new(&base))
+                                    .restore(peers);
+                            }
+
+                            actor_ok(())
+                        })
+                        .wait(ctx);
+                }
+
+                actor_ok(())
+            })
+            .wait(ctx);
+    }
+}
+
+impl DHTPeerStore {
+    fn key_of(pk: &PublicKey, group: &GroupID, peer: &PublicKey) -> String {
+        format!("dht-peer-{}-{}-{}", pk, group.to_string(), peer)
+    }
+
+    pub fn async_store(peer: DHTPeerStore, addr: &Addr<DiskStorageActor>) {
+        let _ = try_resend_times(addr.clone(), EntityWrite(peer), DEFAULT_TIMES)
+            .map_err(|_| println!("Send to storage fail"));
+    }
+}
+
+/// keys of records stored in self, include published and replicated.
+#[derive(Serialize, Deserialize, Clone)]
+struct DHTRecordKeysStore(PublicKey, Vec<(GroupID, H256)>);
+
+impl Entity for DHTRecordKeysStore {
+    type Key = String;
+
+    fn key(&self) -> Self::Key {
+        format!("record-keys-{}", self.0)
+    }
+}
+
+/// one record stored in self.
+#[derive(Serialize, Deserialize, Clone)]
+struct DHTRecordStore(PublicKey, GroupID, H256, DHTRecord);
+
+impl Entity for DHTRecordStore {
+    type Key = String;
+
+    fn key(&self) -> Self::Key {
+        Self::key_of(&self.0, &self.1, &self.2)
+    }
+}
+
+impl DHTRecordKeysStore {
+    pub fn async_store(keys: DHTRecordKeysStore, addr: &Addr<DiskStorageActor>) {
+        let _ = try_resend_times(addr.clone(), EntityWrite(keys), DEFAULT_TIMES)
+            .map_err(|_| println!("Send to storage fail"));
+    }
+
+    /// load every record of the keys.
+    pub fn async_load<A: P2PBridgeActor>(
+        pk: &PublicKey,
         addr: &Addr<DiskStorageActor>,
+        p2p_actor: &P2PActor<A>,
+        ctx: &mut <P2PActor<A> as Actor>::Context,
     ) {
+        let storage_addr = addr.clone();
+        addr.send(EntityRead::<DHTRecordKeysStore>(format!(
+            "record-keys-{}",
+            pk
+        )))
+        .into_actor(p2p_actor)
+        .then(move |res, act, ctx| {
+            if let Ok(Ok(e)) = res {
+                let reads: Vec<_> =
+                    e.1.iter()
+                        .map(|(group, key)| {
+                            storage_addr.send(EntityRead::<DHTRecordStore>(DHTRecordStore::key_of(
+                                &e.0, group, key,
+                            )))
+                        })
+                        .collect();
+
+                join_all(reads)
+                    .into_actor(act)
+                    .then(|res, act, _ctx| {
+                        if let Ok(records) = res {
+                            let records = records
+                                .into_iter()
+                                .filter_map(|record| record.ok())
+                                .map(|record| (record.1, record.2, record.3))
+                                .collect();
+                            act.records.load(records);
+                        }
+
+                        actor_ok(())
+                    })
+                    .wait(ctx);
+            }
+
+            actor_ok(())
+        })
+        .wait(ctx);
+    }
+}
+
+impl DHTRecordStore {
+    fn key_of(pk: &PublicKey, group: &GroupID, key: &H256) -> String {
+        format!("record-{}-{}-{}", pk, group.to_string(), key)
+    }
+
+    pub fn async_store(record: DHTRecordStore, addr: &Addr<DiskStorageActor>) {
+        let _ = try_resend_times(addr.clone(), EntityWrite(record), DEFAULT_TIMES)
+            .map_err(|_| println!("Send to storage fail"));
+    }
+}
+
+/// peers banned by self, temporary and forever.
+#[derive(Serialize, Deserialize, Clone)]
+struct PeerBansStore(PublicKey, Vec<(PublicKey, Ban)>);
+
+impl Entity for PeerBansStore {
+    type Key = String;
+
+    fn key(&self) -> Self::Key {
+        format!("bans-{}", self.0)
+    }
+}
+
+impl PeerBansStore {
+    pub fn async_store(pk: PublicKey, bans: Vec<(PublicKey, Ban)>, addr: &Addr<DiskStorageActor>) {
         let _ = try_resend_times(
             addr.clone(),
-            EntityWrite(DHTTableStore(pk, table)),
+            EntityWrite(PeerBansStore(pk, bans)),
             DEFAULT_TIMES,
         )
         .map_err(|_| println!("Send to storage fail"));
@@ -530,22 +2766,16 @@ impl DHTTableStore {
         p2p_actor: &P2PActor<A>,
         ctx: &mut <P2PActor<A> as Actor>::Context,
     ) {
-        let storage_addr = addr.clone();
-        storage_addr
-            .send(EntityRead::<DHTTableStore>(format!("{}", pk)))
+        addr.send(EntityRead::<PeerBansStore>(format!("bans-{}", pk)))
             .into_actor(p2p_actor)
-            .then(move |res, act, _ctx| {
-                match res {
-                    Ok(Ok(e)) => act.tables = e.1,
-                    _ => {}
+            .then(|res, act, _ctx| {
+                if let Ok(Ok(e)) = res {
+                    act.scores.load(e.1);
+                    act.send_bans();
                 }
 
                 actor_ok(())
             })
             .wait(ctx);
     }
-
-    pub fn _async_delete(pk: PublicKey, _addr: &Addr<DiskStorageActor>) {
-        println!("DEBUG: async delete tables: {}", pk);
-    }
 }
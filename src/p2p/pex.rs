@@ -0,0 +1,138 @@
+use rand::seq::SliceRandom;
+use serde_derive::{Deserialize, Serialize};
+use std::net::SocketAddr;
+
+use crate::crypto::keypair::{PrivateKey, PublicKey, Signature};
+use crate::primitives::types::GroupID;
+
+use super::dht::now_secs;
+
+/// exchange peers with one random peer of every group in this time.
+pub const PEX_TIME: u64 = 60;
+
+/// only exchange the peers seen alive in this time.
+pub const MAX_AGE: u64 = 600;
+
+/// max records in one exchange.
+const PEX_PEERS: usize = 16;
+
+/// max addresses of one record.
+const MAX_ADDRS: usize = 4;
+
+/// last seen time can be later than self clock in this time.
+const MAX_SKEW: u64 = 60;
+
+/// peer record exchanged, signed by the peer it describes,
+/// others can only forward it, can not change it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PexRecord {
+    pub pk: PublicKey,
+    pub addrs: Vec<SocketAddr>,
+    pub last_seen: u64,
+    pub sign: Signature,
+}
+
+impl PexRecord {
+    fn sign_data(group: &GroupID, pk: &PublicKey, addrs: &[SocketAddr], last_seen: u64) -> Vec<u8> {
+        let mut bytes = group.to_bytes().to_vec();
+        bytes.extend_from_slice(&pk.to_bytes());
+        bytes.extend_from_slice(&bincode::serialize(addrs).unwrap_or(vec![]));
+        bytes.extend_from_slice(&last_seen.to_be_bytes());
+        bytes
+    }
+
+    /// self record with the addresses self advertised, seen now.
+    pub fn new(group: &GroupID, psk: &PrivateKey, addrs: Vec<SocketAddr>) -> Self {
+        let pk = psk.generate_public_key();
+        let last_seen = now_secs();
+        let sign = psk.sign_bytes(&Self::sign_data(group, &pk, &addrs, last_seen));
+
+        PexRecord {
+            pk,
+            addrs,
+            last_seen,
+            sign,
+        }
+    }
+
+    pub fn verify(&self, group: &GroupID) -> bool {
+        self.pk.verify_bytes(
+            &Self::sign_data(group, &self.pk, &self.addrs, self.last_seen),
+            &self.sign,
+        )
+    }
+}
+
+/// random sample of records to exchange, self record is the first if has.
+pub(crate) fn sample(own: Option<PexRecord>, mut records: Vec<PexRecord>) -> Vec<PexRecord> {
+    records.shuffle(&mut rand::thread_rng());
+    if let Some(own) = own {
+        records.insert(0, own);
+    }
+    records.truncate(PEX_PEERS);
+    records
+}
+
+/// keep the recent and valid signed records received, the most recent first.
+pub(crate) fn accept(group: &GroupID, mut records: Vec<PexRecord>) -> Vec<PexRecord> {
+    let now = now_secs();
+    records.truncate(PEX_PEERS);
+    records.retain(|r| {
+        !r.addrs.is_empty()
+            && r.addrs.len() <= MAX_ADDRS
+            && r.last_seen <= now + MAX_SKEW
+            && r.last_seen + MAX_AGE >= now
+            && r.verify(group)
+    });
+    records.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+    records.truncate(PEX_PEERS);
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pex_accept() {
+        let socket: SocketAddr = "127.0.0.1:7364".parse().unwrap();
+        let group: GroupID = Default::default();
+        let now = now_secs();
+        let record = |last_seen: u64, addrs: Vec<SocketAddr>| {
+            let psk = PrivateKey::generate();
+            let pk = psk.generate_public_key();
+            let sign = psk.sign_bytes(&PexRecord::sign_data(&group, &pk, &addrs, last_seen));
+            PexRecord {
+                pk,
+                addrs,
+                last_seen,
+                sign,
+            }
+        };
+
+        // forwarded record can not be changed
+        let mut forged = PexRecord::new(&group, &PrivateKey::generate(), vec![socket]);
+        assert!(forged.verify(&group));
+        forged.addrs = vec!["127.0.0.1:7365".parse().unwrap()];
+
+        let old = record(now - 10, vec![socket]);
+        let new = record(now, vec![socket]);
+        let records = vec![
+            old.clone(),
+            record(now - MAX_AGE - 10, vec![socket]),
+            record(now + MAX_SKEW + 10, vec![socket]),
+            record(now, vec![]),
+            record(now, vec![socket; MAX_ADDRS + 1]),
+            forged,
+            new.clone(),
+        ];
+        assert_eq!(accept(&group, records), vec![new.clone(), old]);
+
+        let many: Vec<PexRecord> = (0..PEX_PEERS * 2)
+            .map(|_| record(now, vec![socket]))
+            .collect();
+        assert_eq!(sample(None, many.clone()).len(), PEX_PEERS);
+        assert_eq!(sample(Some(new.clone()), many.clone())[0], new);
+        assert_eq!(accept(&group, many).len(), PEX_PEERS);
+    }
+}
@@ -0,0 +1,44 @@
+use crate::crypto::hash::H256;
+use crate::crypto::keypair::{PrivateKey, PublicKey};
+
+use super::dht::{node_id, Distance};
+
+/// max leading zero bits of identity puzzle, generating the keypair cost 2^difficulty,
+/// capped so it is done in seconds when start.
+pub const MAX_DIFFICULTY: u8 = 16;
+
+/// static puzzle, the hash of node id has difficulty leading zero bits.
+/// node id is the hash of public key, so every key tried for some id pay the cost.
+pub fn verify(pk: &PublicKey, difficulty: u8) -> bool {
+    if difficulty == 0 {
+        return true;
+    }
+
+    let puzzle = H256::new(&node_id(pk).to_bytes());
+    let zeros = Distance::distance(&puzzle.to_bytes(), &[]).leading_zeros();
+    zeros >= difficulty.min(MAX_DIFFICULTY) as usize
+}
+
+/// generate the keypair which solve the puzzle.
+pub fn solve(difficulty: u8) -> PrivateKey {
+    loop {
+        let psk = PrivateKey::generate();
+        if verify(&psk.generate_public_key(), difficulty) {
+            return psk;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_puzzle() {
+        let pk = solve(8).generate_public_key();
+        assert!(verify(&pk, 8));
+        assert!(verify(&pk, 4));
+        assert!(verify(&pk, 0));
+        assert_eq!(verify(&pk, MAX_DIFFICULTY + 8), verify(&pk, MAX_DIFFICULTY));
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use crate::crypto::keypair::PublicKey;
+use crate::primitives::types::GroupID;
+
+use super::limit::TokenBucket;
+use super::session::P2PMessage;
+
+/// reservation keep time (seconds), client need renew it before expired.
+pub const RESERVATION_TIME: u32 = 120;
+
+/// relayed peer try hole punching again in this time, if success, upgrade to direct.
+const PUNCH_INTERVAL: u64 = 30;
+
+/// relayed peer not used in this time will evict.
+const IDLE_TIME: u64 = 120;
+
+/// peer waiting for relay reservation.
+type Waiting = (PublicKey, Option<SocketAddr>, Vec<P2PMessage>);
+
+#[derive(Clone)]
+struct RelayedPeer {
+    group: GroupID,
+    relay: PublicKey,
+    socket: Option<SocketAddr>,
+    punched: Instant,
+    last: Instant,
+}
+
+/// relay of peers which can not hole punching (symmetric NAT),
+/// as server, forward messages for reserved peers with bandwidth cap,
+/// as client, send messages to peer by the relay peer.
+#[derive(Clone)]
+pub(crate) struct Relay {
+    enable: bool,
+    max_reservations: usize,
+    byte_rate: f64,
+    reservations: HashMap<(GroupID, PublicKey), (Instant, TokenBucket)>,
+    reserved: HashMap<(GroupID, PublicKey), Instant>,
+    waitings: HashMap<(GroupID, PublicKey), Vec<Waiting>>,
+    relayeds: HashMap<PublicKey, RelayedPeer>,
+    introducers: HashMap<PublicKey, PublicKey>,
+}
+
+impl Relay {
+    pub fn new(enable: bool, max_reservations: u32, byte_rate: u32) -> Self {
+        Relay {
+            enable,
+            max_reservations: max_reservations as usize,
+            byte_rate: byte_rate as f64,
+            reservations: HashMap::new(),
+            reserved: HashMap::new(),
+            waitings: HashMap::new(),
+            relayeds: HashMap::new(),
+            introducers: HashMap::new(),
+        }
+    }
+
+    /// peer reserve relay in self, return keep time.
+    pub fn reserve(&mut self, group: GroupID, pk: PublicKey) -> Result<u32, String> {
+        if !self.enable {
+            return Err("relay disabled".to_owned());
+        }
+
+        let key = (group, pk);
+        if !self.reservations.contains_key(&key) {
+            self.evict_reservations();
+            if self.reservations.len() >= self.max_reservations {
+                return Err("relay reservations full".to_owned());
+            }
+        }
+
+        let expires = Instant::now() + Duration::new(RESERVATION_TIME as u64, 0);
+        let rate = self.byte_rate;
+        self.reservations
+            .entry(key)
+            .and_modify(|(ins, _)| *ins = expires)
+            .or_insert((expires, TokenBucket::new(rate, rate)));
+        Ok(RESERVATION_TIME)
+    }
+
+    /// check if forward message between peers, one of them must reserved,
+    /// the bytes will take from the reservation.
+    pub fn charge(
+        &mut self,
+        group: &GroupID,
+        from: &PublicKey,
+        to: &PublicKey,
+        bytes: usize,
+    ) -> bool {
+        let now = Instant::now();
+        for pk in [from, to].iter() {
+            if let Some((expires, bucket)) =
+                self.reservations.get_mut(&(group.clone(), (*pk).clone()))
+            {
+                if *expires > now {
+                    return bucket.take(bytes as f64);
+                }
+            }
+        }
+        false
+    }
+
+    pub fn is_reserved(&self, group: &GroupID, pk: &PublicKey) -> bool {
+        self.reservations
+            .get(&(group.clone(), pk.clone()))
+            .map(|(expires, _)| *expires > Instant::now())
+            .unwrap_or(false)
+    }
+
+    /// remember the peer who help connect to peer, it is the first relay choice.
+    pub fn introduce(&mut self, pk: PublicKey, introducer: PublicKey) {
+        self.introducers.insert(pk, introducer);
+    }
+
+    pub fn take_introducer(&mut self, pk: &PublicKey) -> Option<PublicKey> {
+        self.introducers.remove(pk)
+    }
+
+    /// self has reservation in the relay peer.
+    pub fn has_reservation(&self, group: &GroupID, relay: &PublicKey) -> bool {
+        self.reserved
+            .get(&(group.clone(), relay.clone()))
+            .map(|expires| *expires > Instant::now())
+            .unwrap_or(false)
+    }
+
+    /// wait reservation of the relay, return true if need send reserve.
+    pub fn wait(&mut self, group: GroupID, relay: PublicKey, waiting: Waiting) -> bool {
+        let waitings = self.waitings.entry((group, relay)).or_insert(vec![]);
+        waitings.push(waiting);
+        waitings.len() == 1
+    }
+
+    /// relay accept reservation, return the waiting peers.
+    pub fn reserved(&mut self, group: GroupID, relay: PublicKey, time: u32) -> Vec<Waiting> {
+        let expires = Instant::now() + Duration::new(time as u64, 0);
+        let key = (group, relay);
+        self.reserved.insert(key.clone(), expires);
+        self.waitings.remove(&key).unwrap_or(vec![])
+    }
+
+    /// relay reject reservation, return the waiting peers.
+    pub fn rejected(&mut self, group: GroupID, relay: PublicKey) -> Vec<Waiting> {
+        let key = (group, relay);
+        self.reserved.remove(&key);
+        self.waitings.remove(&key).unwrap_or(vec![])
+    }
+
+    /// send to peer by the relay.
+    pub fn add_relayed(
+        &mut self,
+        pk: PublicKey,
+        group: GroupID,
+        relay: PublicKey,
+        socket: Option<SocketAddr>,
+    ) {
+        let now = Instant::now();
+        let relayed = self.relayeds.entry(pk).or_insert(RelayedPeer {
+            group: group.clone(),
+            relay: relay.clone(),
+            socket,
+            punched: now,
+            last: now,
+        });
+        relayed.group = group;
+        relayed.relay = relay;
+        relayed.last = now;
+        if socket.is_some() {
+            relayed.socket = socket;
+        }
+    }
+
+    /// relay peer of the peer, and update used time.
+    pub fn relay_of(&mut self, pk: &PublicKey) -> Option<PublicKey> {
+        self.relayeds.get_mut(pk).map(|relayed| {
+            relayed.last = Instant::now();
+            relayed.relay.clone()
+        })
+    }
+
+    /// relay of the peer and its group, not update used time.
+    pub fn relay_by(&self, pk: &PublicKey) -> Option<(GroupID, PublicKey)> {
+        self.relayeds
+            .get(pk)
+            .map(|relayed| (relayed.group.clone(), relayed.relay.clone()))
+    }
+
+    /// peer hole punching success, not use relay, return true if it was relayed.
+    pub fn upgrade(&mut self, pk: &PublicKey) -> bool {
+        self.introducers.remove(pk);
+        self.relayeds.remove(pk).is_some()
+    }
+
+    /// relayed peers need hole punching again.
+    pub fn punches(&mut self) -> Vec<(GroupID, PublicKey, SocketAddr)> {
+        let now = Instant::now();
+        let mut punches = vec![];
+        for (pk, relayed) in self.relayeds.iter_mut() {
+            if let Some(socket) = relayed.socket {
+                if now.duration_since(relayed.punched) > Duration::new(PUNCH_INTERVAL, 0) {
+                    relayed.punched = now;
+                    punches.push((relayed.group.clone(), pk.clone(), socket));
+                }
+            }
+        }
+        punches
+    }
+
+    /// the reservations in use and will expire, need renew.
+    pub fn renewals(&self) -> Vec<(GroupID, PublicKey)> {
+        let deadline = Instant::now() + Duration::new(RESERVATION_TIME as u64 / 2, 0);
+        self.reserved
+            .iter()
+            .filter(|(key, expires)| {
+                **expires < deadline
+                    && !self.waitings.contains_key(key)
+                    && self
+                        .relayeds
+                        .values()
+                        .any(|r| r.group == key.0 && r.relay == key.1)
+            })
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    fn evict_reservations(&mut self) {
+        let now = Instant::now();
+        self.reservations.retain(|_, (expires, _)| *expires > now);
+    }
+
+    /// remove the expired reservations and idle relayed peers.
+    pub fn evict(&mut self) {
+        let now = Instant::now();
+        self.evict_reservations();
+        self.reserved.retain(|_, expires| *expires > now);
+        self.relayeds
+            .retain(|_, relayed| now.duration_since(relayed.last) < Duration::new(IDLE_TIME, 0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::keypair::PrivateKey;
+
+    #[test]
+    fn test_relay_reserve_and_charge() {
+        let group: GroupID = Default::default();
+        let a = PrivateKey::generate().generate_public_key();
+        let b = PrivateKey::generate().generate_public_key();
+        let c = PrivateKey::generate().generate_public_key();
+        let mut relay = Relay::new(true, 1, 100);
+
+        assert!(!relay.charge(&group, &a, &b, 10));
+        assert_eq!(
+            relay.reserve(group.clone(), a.clone()),
+            Ok(RESERVATION_TIME)
+        );
+        assert!(relay.reserve(group.clone(), c.clone()).is_err());
+        assert!(relay.charge(&group, &b, &a, 60));
+        assert!(!relay.charge(&group, &a, &b, 60));
+        assert!(!relay.charge(&group, &b, &c, 10));
+
+        // relayed peer keep the relay, not its socket
+        relay.add_relayed(b.clone(), group.clone(), a.clone(), None);
+        assert_eq!(relay.relay_by(&b), Some((group.clone(), a.clone())));
+        assert!(relay.punches().is_empty());
+        assert!(relay.upgrade(&b));
+        assert_eq!(relay.relay_by(&b), None);
+    }
+}
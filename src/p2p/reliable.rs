@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::primitives::types::EventByte;
+
+/// first retransmission timeout (millis), double after every retry.
+const INITIAL_RTO: u64 = 500;
+
+/// max retransmission timeout (millis).
+const MAX_RTO: u64 = 8000;
+
+/// retry times before give up.
+const MAX_RETRIES: u32 = 6;
+
+/// max events can receive out of order, more than it will drop.
+const RECEIVE_WINDOW: u64 = 1024;
+
+/// max events waiting ack, the receiver can not keep more out of order.
+const MAX_SENDINGS: usize = RECEIVE_WINDOW as usize;
+
+#[derive(Clone)]
+struct Sending {
+    event: EventByte,
+    last: Instant,
+    rto: u64,
+    retries: u32,
+}
+
+/// new channel id, it is unix millis when created, so the id of new channel
+/// is bigger than the old one, even the peer restart.
+fn new_channel_id(old: u64) -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    now.max(old + 1)
+}
+
+/// reliable and ordered event channel with one peer in one group.
+/// when retries run out, the channel will reset by a new bigger channel id,
+/// so the receiver will not wait for the lost events forever.
+#[derive(Clone)]
+pub(crate) struct ReliableChannel {
+    send_id: u64,
+    next_send: u64,
+    sendings: BTreeMap<u64, Sending>,
+    recv_id: u64,
+    next_recv: u64,
+    receivings: BTreeMap<u64, EventByte>,
+}
+
+impl ReliableChannel {
+    pub fn new() -> Self {
+        ReliableChannel {
+            send_id: new_channel_id(0),
+            next_send: 0,
+            sendings: BTreeMap::new(),
+            recv_id: 0,
+            next_recv: 0,
+            receivings: BTreeMap::new(),
+        }
+    }
+
+    /// save the event, return the channel id and sequence number need send,
+    /// if too many events waiting ack, return none and the event is not sent.
+    pub fn send(&mut self, event: EventByte) -> Option<(u64, u64)> {
+        if self.sendings.len() >= MAX_SENDINGS {
+            return None;
+        }
+
+        let seq = self.next_send;
+        self.next_send += 1;
+        self.sendings.insert(
+            seq,
+            Sending {
+                event,
+                last: Instant::now(),
+                rto: INITIAL_RTO,
+                retries: 0,
+            },
+        );
+        Some((self.send_id, seq))
+    }
+
+    /// receive ack from peer, remove the event.
+    pub fn ack(&mut self, id: u64, seq: u64) {
+        if id == self.send_id {
+            self.sendings.remove(&seq);
+        }
+    }
+
+    /// receive event from peer, return if need ack and the events can deliver in order.
+    pub fn receive(&mut self, id: u64, seq: u64, event: EventByte) -> (bool, Vec<EventByte>) {
+        if id < self.recv_id {
+            // late packet of the old channel
+            return (false, vec![]);
+        }
+
+        if id > self.recv_id {
+            // peer restart or reset the channel
+            self.recv_id = id;
+            self.next_recv = 0;
+            self.receivings.clear();
+        }
+
+        if seq < self.next_recv {
+            // duplicate, the ack maybe lost
+            return (true, vec![]);
+        }
+
+        if seq >= self.next_recv + RECEIVE_WINDOW {
+            return (false, vec![]);
+        }
+
+        self.receivings.insert(seq, event);
+
+        let mut events = vec![];
+        while let Some(event) = self.receivings.remove(&self.next_recv) {
+            events.push(event);
+            self.next_recv += 1;
+        }
+
+        (true, events)
+    }
+
+    /// check timeout events, return the events need resend and the failure events.
+    /// when some event failure, all waiting events fail, and channel reset.
+    pub fn timeouts(&mut self) -> (Vec<(u64, u64, EventByte)>, Vec<EventByte>) {
+        let now = Instant::now();
+        let mut resends = vec![];
+        let mut failure = false;
+
+        for (seq, sending) in self.sendings.iter_mut() {
+            if now.duration_since(sending.last) < Duration::from_millis(sending.rto) {
+                continue;
+            }
+
+            if sending.retries >= MAX_RETRIES {
+                failure = true;
+                break;
+            }
+
+            sending.retries += 1;
+            sending.last = now;
+            sending.rto = std::cmp::min(sending.rto * 2, MAX_RTO);
+            resends.push((self.send_id, *seq, sending.event.clone()));
+        }
+
+        if failure {
+            (vec![], self.reset())
+        } else {
+            (resends, vec![])
+        }
+    }
+
+    /// reset send side with new channel id, return the waiting events.
+    pub fn reset(&mut self) -> Vec<EventByte> {
+        self.send_id = new_channel_id(self.send_id);
+        self.next_send = 0;
+        let sendings = std::mem::replace(&mut self.sendings, BTreeMap::new());
+        sendings.into_iter().map(|(_, s)| s.event).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_receive_out_of_order() {
+        let mut sender = ReliableChannel::new();
+        let mut receiver = ReliableChannel::new();
+
+        let (id, seq0) = sender.send(vec![0]).unwrap();
+        let (_, seq1) = sender.send(vec![1]).unwrap();
+        let (_, seq2) = sender.send(vec![2]).unwrap();
+
+        assert_eq!(receiver.receive(id, seq0, vec![0]).1, vec![vec![0]]);
+        assert!(receiver.receive(id, seq2, vec![2]).1.is_empty());
+        assert_eq!(
+            receiver.receive(id, seq1, vec![1]).1,
+            vec![vec![1], vec![2]]
+        );
+        assert_eq!(receiver.receive(id, seq1, vec![1]), (true, vec![]));
+
+        sender.ack(id, seq0);
+        sender.ack(id, seq1);
+        sender.ack(id, seq2);
+        assert!(sender.reset().is_empty());
+
+        // late packet of old channel not reset the new channel
+        let (new_id, seq0) = sender.send(vec![3]).unwrap();
+        assert!(new_id > id);
+        assert!(receiver.receive(new_id, seq0 + 1, vec![4]).1.is_empty());
+        assert_eq!(receiver.receive(id, seq2, vec![2]), (false, vec![]));
+        assert_eq!(
+            receiver.receive(new_id, seq0, vec![3]).1,
+            vec![vec![3], vec![4]]
+        );
+    }
+
+    #[test]
+    fn test_sendings_cap() {
+        let mut sender = ReliableChannel::new();
+        for i in 0..MAX_SENDINGS {
+            assert!(sender.send(vec![i as u8]).is_some());
+        }
+        assert!(sender.send(vec![0]).is_none());
+
+        let id = sender.send_id;
+        sender.ack(id, 0);
+        assert!(sender.send(vec![0]).is_some());
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use crate::crypto::hash::H256;
+use crate::crypto::keypair::{PublicKey, Signature};
+
+use super::dht::{node_id, Distance};
+
+/// max hops of routed message.
+pub const MAX_HOPS: u8 = 16;
+
+/// routed message id kept in this time, for loop protection.
+const SEEN_TIME: u64 = 60;
+
+/// max routed message ids kept.
+const MAX_SEENS: usize = 100000;
+
+/// the peer closest to target by xor distance.
+pub fn closest<F: Fn(&PublicKey, &SocketAddr) -> bool>(
+    peers: Vec<(PublicKey, SocketAddr)>,
+    target: &PublicKey,
+    filter: F,
+) -> Option<(PublicKey, SocketAddr)> {
+    let target = node_id(target).to_bytes();
+    peers
+        .into_iter()
+        .filter(|(pk, socket)| filter(pk, socket))
+        .min_by_key(|(pk, _)| Distance::distance(&node_id(pk).to_bytes(), &target))
+}
+
+/// next hop of greedy routing, only the peer strictly closer to target than base,
+/// so every hop is closer and the message never go back.
+pub fn next_hop<F: Fn(&PublicKey, &SocketAddr) -> bool>(
+    peers: Vec<(PublicKey, SocketAddr)>,
+    target: &PublicKey,
+    base: &PublicKey,
+    filter: F,
+) -> Option<(PublicKey, SocketAddr)> {
+    let target_bytes = node_id(target).to_bytes();
+    let base_distance = Distance::distance(&node_id(base).to_bytes(), &target_bytes);
+    closest(peers, target, |pk, socket| {
+        filter(pk, socket)
+            && Distance::distance(&node_id(pk).to_bytes(), &target_bytes) < base_distance
+    })
+}
+
+/// routed messages seen recently, the same message will not forward again.
+#[derive(Default, Clone)]
+pub(crate) struct Router {
+    seens: HashMap<H256, Instant>,
+}
+
+impl Router {
+    /// check message is seen, if not, remember it.
+    pub fn seen(&mut self, sign: &Signature) -> bool {
+        let id = H256::new(&sign.to_bytes());
+        if self.seens.contains_key(&id) {
+            return true;
+        }
+
+        if self.seens.len() >= MAX_SEENS {
+            self.evict();
+        }
+        self.seens.insert(id, Instant::now());
+        false
+    }
+
+    pub fn evict(&mut self) {
+        let now = Instant::now();
+        self.seens
+            .retain(|_, ins| now.duration_since(*ins) < Duration::new(SEEN_TIME, 0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::keypair::PrivateKey;
+
+    #[test]
+    fn test_closest_and_seen() {
+        let target = PrivateKey::generate().generate_public_key();
+        let socket: SocketAddr = "127.0.0.1:7364".parse().unwrap();
+        let peers: Vec<(PublicKey, SocketAddr)> = (0..8)
+            .map(|_| (PrivateKey::generate().generate_public_key(), socket))
+            .collect();
+
+        let (best, _) = closest(peers.clone(), &target, |_, _| true).unwrap();
+        let target_id = node_id(&target).to_bytes();
+        let distance = |pk: &PublicKey| Distance::distance(&node_id(pk).to_bytes(), &target_id);
+        assert!(peers.iter().all(|(pk, _)| distance(&best) <= distance(pk)));
+        assert!(closest(peers.clone(), &target, |_, _| false).is_none());
+
+        // only forward to peer closer than self
+        assert_eq!(next_hop(peers.clone(), &target, &best, |_, _| true), None);
+        let farthest = peers
+            .iter()
+            .max_by_key(|(pk, _)| distance(pk))
+            .map(|(pk, _)| pk.clone())
+            .unwrap();
+        assert_eq!(
+            next_hop(peers, &target, &farthest, |_, _| true).map(|(pk, _)| pk),
+            Some(best)
+        );
+
+        let psk = PrivateKey::generate();
+        let sign = psk.sign_bytes(&vec![1, 2, 3]);
+        let mut router = Router::default();
+        assert!(!router.seen(&sign));
+        assert!(router.seen(&sign));
+    }
+}
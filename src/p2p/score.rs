@@ -0,0 +1,229 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use super::dht::now_secs;
+
+/// max score of good behaviour, good peer can not save too much to misbehave.
+const MAX_SCORE: i32 = 20;
+
+/// score lower than this, peer will be banned.
+const BAN_SCORE: i32 = -100;
+
+/// first ban time, every ban again will double it.
+const BAN_TIME: u64 = 600;
+
+/// banned times reach this, ban forever.
+const MAX_BANS: u32 = 3;
+
+/// expired ban kept in this time, ban again in it will be longer.
+const FORGET_TIME: u64 = 86400;
+
+/// max bans kept, when full, the expired bans are forgot.
+const MAX_BANS_KEPT: usize = 10000;
+
+/// behaviour of peer, change the score of it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Behaviour {
+    /// valid message.
+    Good,
+    /// content can not decode.
+    Undecodable,
+    /// signature not match the content.
+    BadSignature,
+    /// over the rate limit.
+    Throttled,
+    /// over the rate limit too many times.
+    Flood,
+    /// invalid puzzle, record, gossip or not sealed event.
+    Invalid,
+    /// misbehaviour reported by bridge, params is penalty.
+    Misbehave(u32),
+}
+
+impl Behaviour {
+    fn score(&self) -> i32 {
+        match self {
+            Behaviour::Good => 1,
+            Behaviour::Undecodable => -10,
+            Behaviour::BadSignature => -20,
+            Behaviour::Throttled => -5,
+            Behaviour::Flood => -50,
+            Behaviour::Invalid => -20,
+            Behaviour::Misbehave(penalty) => -((*penalty).min(i32::max_value() as u32) as i32),
+        }
+    }
+}
+
+/// ban of peer, until is unix seconds, none is forever.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Ban {
+    pub until: Option<u64>,
+    pub times: u32,
+}
+
+impl Ban {
+    pub fn is_active(&self) -> bool {
+        self.until.map(|until| until > now_secs()).unwrap_or(true)
+    }
+}
+
+/// score of peers, rise and fall with behaviour, ban the peer when too low,
+/// and decay to zero, so the old behaviour will be forgot.
+#[derive(Clone)]
+pub(crate) struct Scores<K: Hash + Eq + Clone> {
+    scores: HashMap<K, i32>,
+    bans: HashMap<K, Ban>,
+    active: usize,
+    changed: bool,
+}
+
+impl<K: Hash + Eq + Clone> Default for Scores<K> {
+    fn default() -> Self {
+        Scores {
+            scores: HashMap::new(),
+            bans: HashMap::new(),
+            active: 0,
+            changed: false,
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone> Scores<K> {
+    /// load the persisted bans.
+    pub fn load(&mut self, bans: Vec<(K, Ban)>) {
+        for (key, ban) in bans {
+            self.bans.insert(key, ban);
+        }
+        self.active = self.banned().len();
+    }
+
+    /// change score by behaviour, return the ban if peer is banned now.
+    pub fn report(&mut self, key: &K, behaviour: Behaviour) -> Option<Ban> {
+        if self.is_banned(key) {
+            return None;
+        }
+
+        let score = self.scores.entry(key.clone()).or_insert(0);
+        *score = score.saturating_add(behaviour.score()).min(MAX_SCORE);
+        if *score > BAN_SCORE {
+            return None;
+        }
+
+        self.scores.remove(key);
+        Some(self.ban(key, false))
+    }
+
+    /// ban the peer, every ban again is longer, until ban forever.
+    pub fn ban(&mut self, key: &K, forever: bool) -> Ban {
+        let times = self.bans.get(key).map(|ban| ban.times).unwrap_or(0) + 1;
+        let until = if forever || times >= MAX_BANS {
+            None
+        } else {
+            Some(now_secs() + BAN_TIME * 2u64.pow(times - 1))
+        };
+
+        let ban = Ban { until, times };
+        if self.bans.len() >= MAX_BANS_KEPT {
+            self.bans.retain(|_, ban| ban.is_active());
+        }
+        self.bans.insert(key.clone(), ban.clone());
+        self.active = self.banned().len();
+        self.changed = true;
+        ban
+    }
+
+    pub fn is_banned(&self, key: &K) -> bool {
+        self.bans
+            .get(key)
+            .map(|ban| ban.is_active())
+            .unwrap_or(false)
+    }
+
+    /// all peers banned now.
+    pub fn banned(&self) -> Vec<K> {
+        self.bans
+            .iter()
+            .filter(|(_, ban)| ban.is_active())
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// scores decay to zero, and the bans expired long ago are forgot,
+    /// return true if some ban is expired.
+    pub fn decay(&mut self) -> bool {
+        for score in self.scores.values_mut() {
+            *score = *score * 9 / 10;
+        }
+        self.scores.retain(|_, score| *score != 0);
+
+        let now = now_secs();
+        let len = self.bans.len();
+        self.bans.retain(|_, ban| {
+            ban.until
+                .map(|until| until + FORGET_TIME > now)
+                .unwrap_or(true)
+        });
+        if self.bans.len() != len {
+            self.changed = true;
+        }
+
+        let active = self.banned().len();
+        let expired = active != self.active;
+        self.active = active;
+        expired
+    }
+
+    /// all bans for persist, none if not changed since last time.
+    pub fn take_changed(&mut self) -> Option<Vec<(K, Ban)>> {
+        if !self.changed {
+            return None;
+        }
+        self.changed = false;
+
+        Some(
+            self.bans
+                .iter()
+                .map(|(key, ban)| (key.clone(), ban.clone()))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scores_ban() {
+        let mut scores: Scores<u32> = Default::default();
+        for _ in 0..100 {
+            assert!(scores.report(&1, Behaviour::Good).is_none());
+        }
+        assert!(scores.report(&1, Behaviour::Flood).is_none());
+        assert!(scores.report(&1, Behaviour::Flood).is_none());
+
+        let ban = scores.report(&1, Behaviour::Flood).unwrap();
+        assert_eq!(ban.times, 1);
+        assert!(ban.until.is_some());
+        assert!(scores.is_banned(&1));
+        assert!(scores.report(&1, Behaviour::Flood).is_none());
+        assert_eq!(scores.banned(), vec![1]);
+        assert_eq!(scores.take_changed().unwrap().len(), 1);
+        assert!(scores.take_changed().is_none());
+
+        assert!(scores.ban(&2, false).until.is_some());
+        assert!(scores.ban(&2, false).until.is_some());
+        assert_eq!(scores.ban(&2, false).until, None);
+        assert_eq!(scores.ban(&3, true).until, None);
+
+        assert!(scores.report(&4, Behaviour::Misbehave(1000)).is_some());
+        assert!(!scores.is_banned(&5));
+
+        // expired long ago is forgot
+        scores.bans.get_mut(&1).unwrap().until = Some(now_secs() - FORGET_TIME - 1);
+        assert!(scores.decay());
+        assert!(!scores.bans.contains_key(&1));
+        assert!(scores.bans.contains_key(&2));
+    }
+}
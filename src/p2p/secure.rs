@@ -0,0 +1,377 @@
+use std::cell::Cell;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use crate::crypto::cipher::{EphemeralKey, ExchangeKey, SymmetricKey};
+use crate::crypto::hash::H256;
+use crate::primitives::types::GroupID;
+
+use super::content::P2PContent;
+use super::dht::now_secs;
+
+/// session keys will rekey after this time.
+const REKEY_TIME: u64 = 600;
+
+/// handshake not finished after this time, will start again.
+const HANDSHAKE_TIMEOUT: u64 = 10;
+
+/// max contents waiting for handshake.
+const MAX_PENDINGS: usize = 128;
+
+/// handshake timestamp differ from now more than this (seconds) is rejected,
+/// the nonces in this time are kept to reject replayed handshake.
+const HANDSHAKE_WINDOW: u64 = 60;
+
+/// received counters in this range below the max are kept, older is rejected.
+const REPLAY_WINDOW: u64 = 64;
+
+#[derive(Clone)]
+struct SessionKeys {
+    id: [u8; 8],
+    send: SymmetricKey,
+    recv: SymmetricKey,
+    created: Instant,
+    sent: Cell<u64>,
+    received: ReplayWindow,
+}
+
+/// sliding window of received counters, bit n of bitmap is counter max - n.
+#[derive(Clone, Default)]
+struct ReplayWindow {
+    max: u64,
+    bitmap: u64,
+}
+
+impl ReplayWindow {
+    /// accept the counter if not received and not too old.
+    fn accept(&mut self, counter: u64) -> bool {
+        if counter > self.max {
+            let shift = counter - self.max;
+            self.bitmap = if shift >= REPLAY_WINDOW {
+                0
+            } else {
+                self.bitmap << shift
+            };
+            self.bitmap |= 1;
+            self.max = counter;
+            return true;
+        }
+
+        let offset = self.max - counter;
+        if offset >= REPLAY_WINDOW || self.bitmap & (1 << offset) != 0 {
+            return false;
+        }
+        self.bitmap |= 1 << offset;
+        true
+    }
+}
+
+impl SessionKeys {
+    /// derive session keys from the shared secret, exchange keys and nonces of both,
+    /// initiator's send key is responder's receive key.
+    fn derive(
+        shared: &H256,
+        initiator: (&ExchangeKey, u64),
+        responder: (&ExchangeKey, u64),
+        is_initiator: bool,
+    ) -> Self {
+        let mut data = shared.to_vec();
+        data.extend_from_slice(&initiator.0.to_bytes());
+        data.extend_from_slice(&responder.0.to_bytes());
+        data.extend_from_slice(&initiator.1.to_le_bytes());
+        data.extend_from_slice(&responder.1.to_le_bytes());
+        let master = H256::new(&data);
+
+        let derive = |label: u8| {
+            let mut bytes = master.to_vec();
+            bytes.push(label);
+            H256::new(&bytes)
+        };
+
+        let mut id = [0u8; 8];
+        id.copy_from_slice(&derive(0).to_vec()[0..8]);
+        let i_to_r = SymmetricKey::from_hash(&derive(1));
+        let r_to_i = SymmetricKey::from_hash(&derive(2));
+
+        let (send, recv) = if is_initiator {
+            (i_to_r, r_to_i)
+        } else {
+            (r_to_i, i_to_r)
+        };
+
+        SessionKeys {
+            id,
+            send,
+            recv,
+            created: Instant::now(),
+            sent: Cell::new(0),
+            received: Default::default(),
+        }
+    }
+}
+
+/// encrypted session with one peer. keys agreed by ephemeral x25519,
+/// and authenticated by the signature of the handshake message.
+#[derive(Clone)]
+pub(crate) struct SecureSession {
+    group: GroupID,
+    current: Option<SessionKeys>,
+    previous: Option<SessionKeys>,
+    handshaking: Option<(EphemeralKey, u64, Instant)>,
+    seen: Vec<(u64, u64)>,
+    socket: Option<SocketAddr>,
+    pendings: Vec<(GroupID, P2PContent, Option<SocketAddr>)>,
+}
+
+impl SecureSession {
+    pub fn new(group: GroupID) -> Self {
+        SecureSession {
+            group,
+            current: None,
+            previous: None,
+            handshaking: None,
+            seen: vec![],
+            socket: None,
+            pendings: vec![],
+        }
+    }
+
+    pub fn group(&self) -> &GroupID {
+        &self.group
+    }
+
+    /// socket of peer when handshake by socket, not by table.
+    pub fn socket(&self) -> Option<SocketAddr> {
+        self.socket
+    }
+
+    pub fn is_established(&self) -> bool {
+        self.current.is_some()
+    }
+
+    pub fn is_handshaking(&self) -> bool {
+        self.handshaking.is_some()
+    }
+
+    /// need start a new handshake, when keys are old or handshake timeout.
+    pub fn need_handshake(&self) -> bool {
+        if let Some((_, _, ins)) = &self.handshaking {
+            return Instant::now().duration_since(*ins) > Duration::new(HANDSHAKE_TIMEOUT, 0);
+        }
+
+        if let Some(keys) = &self.current {
+            Instant::now().duration_since(keys.created) > Duration::new(REKEY_TIME, 0)
+        } else {
+            !self.pendings.is_empty()
+        }
+    }
+
+    /// start handshake as initiator, return the handshake content send to peer.
+    pub fn start(&mut self, group: GroupID, socket: Option<SocketAddr>) -> P2PContent {
+        self.group = group;
+        if socket.is_some() {
+            self.socket = socket;
+        }
+        let key = EphemeralKey::generate();
+        let nonce: u64 = rand::random();
+        let public = key.public_key().clone();
+        self.handshaking = Some((key, nonce, Instant::now()));
+        P2PContent::Handshake(public, nonce, now_secs())
+    }
+
+    /// receive initiator's handshake, return the reply content to peer,
+    /// the old or replayed handshake is rejected.
+    pub fn respond(
+        &mut self,
+        group: GroupID,
+        remote: &ExchangeKey,
+        remote_nonce: u64,
+        timestamp: u64,
+    ) -> Option<P2PContent> {
+        let now = now_secs();
+        if timestamp + HANDSHAKE_WINDOW < now || timestamp > now + HANDSHAKE_WINDOW {
+            return None;
+        }
+        self.seen.retain(|(_, t)| t + HANDSHAKE_WINDOW >= now);
+        if self.seen.iter().any(|(n, _)| n == &remote_nonce) {
+            return None;
+        }
+        self.seen.push((remote_nonce, timestamp));
+
+        self.group = group;
+        self.handshaking = None;
+        let key = EphemeralKey::generate();
+        let nonce: u64 = rand::random();
+        let shared = key.exchange(remote);
+        self.update_keys(SessionKeys::derive(
+            &shared,
+            (remote, remote_nonce),
+            (key.public_key(), nonce),
+            false,
+        ));
+        Some(P2PContent::HandshakeOk(
+            key.public_key().clone(),
+            nonce,
+            remote_nonce,
+        ))
+    }
+
+    /// receive responder's reply, return false if not handshaking,
+    /// or the reply is not for the handshake.
+    pub fn complete(&mut self, remote: &ExchangeKey, remote_nonce: u64, nonce: u64) -> bool {
+        match self.handshaking.take() {
+            Some((key, self_nonce, _)) if self_nonce == nonce => {
+                let shared = key.exchange(remote);
+                self.update_keys(SessionKeys::derive(
+                    &shared,
+                    (key.public_key(), self_nonce),
+                    (remote, remote_nonce),
+                    true,
+                ));
+                true
+            }
+            handshaking => {
+                self.handshaking = handshaking;
+                false
+            }
+        }
+    }
+
+    fn update_keys(&mut self, keys: SessionKeys) {
+        // keep previous keys to open messages in flight when rekey.
+        self.previous = self.current.take();
+        self.current = Some(keys);
+    }
+
+    /// save content until session established, socket is none if send by table.
+    pub fn push_pending(
+        &mut self,
+        group: GroupID,
+        content: P2PContent,
+        socket: Option<SocketAddr>,
+    ) {
+        if self.pendings.len() < MAX_PENDINGS {
+            self.pendings.push((group, content, socket));
+        }
+    }
+
+    pub fn take_pendings(&mut self) -> Vec<(GroupID, P2PContent, Option<SocketAddr>)> {
+        self.pendings.drain(..).collect()
+    }
+
+    /// encrypt content with the next send counter, if not established or not need seal,
+    /// return itself.
+    pub fn seal(&self, content: P2PContent) -> P2PContent {
+        if !content.need_seal() {
+            return content;
+        }
+
+        if let Some(keys) = &self.current {
+            let counter = keys.sent.get() + 1;
+            keys.sent.set(counter);
+            let mut bytes = counter.to_le_bytes().to_vec();
+            bytes.append(&mut bincode::serialize(&content).unwrap_or(vec![]));
+            if let Ok(ciphertext) = keys.send.encrypt(&bytes) {
+                return P2PContent::Sealed(keys.id, ciphertext);
+            }
+        }
+
+        content
+    }
+
+    /// decrypt sealed content by current or previous keys,
+    /// the replayed or too old counter is rejected.
+    pub fn open(&mut self, id: &[u8; 8], ciphertext: &[u8]) -> Option<P2PContent> {
+        let keys = match (&mut self.current, &mut self.previous) {
+            (Some(keys), _) if &keys.id == id => keys,
+            (_, Some(keys)) if &keys.id == id => keys,
+            _ => return None,
+        };
+
+        let bytes = keys.recv.decrypt(ciphertext).ok()?;
+        if bytes.len() < 8 {
+            return None;
+        }
+        let mut counter = [0u8; 8];
+        counter.copy_from_slice(&bytes[..8]);
+        if !keys.received.accept(u64::from_le_bytes(counter)) {
+            return None;
+        }
+
+        bincode::deserialize(&bytes[8..]).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handshake_seal_open() {
+        let group: GroupID = Default::default();
+        let mut initiator = SecureSession::new(group.clone());
+        let mut responder = SecureSession::new(group.clone());
+
+        let (key, nonce, timestamp) = match initiator.start(group.clone(), None) {
+            P2PContent::Handshake(key, nonce, timestamp) => (key, nonce, timestamp),
+            _ => panic!("not handshake"),
+        };
+        let reply = responder.respond(group.clone(), &key, nonce, timestamp);
+        // replayed or old handshake is rejected
+        assert!(responder
+            .respond(group.clone(), &key, nonce, timestamp)
+            .is_none());
+        assert!(responder
+            .respond(
+                group.clone(),
+                &key,
+                nonce + 1,
+                timestamp - HANDSHAKE_WINDOW - 1
+            )
+            .is_none());
+
+        match reply {
+            Some(P2PContent::HandshakeOk(reply, reply_nonce, echo)) => {
+                assert!(!initiator.complete(&reply, reply_nonce, echo + 1));
+                assert!(initiator.complete(&reply, reply_nonce, echo));
+            }
+            _ => panic!("handshake not answered"),
+        }
+
+        let sealed = initiator.seal(P2PContent::Event(vec![1, 2, 3]));
+        match sealed {
+            P2PContent::Sealed(id, ciphertext) => match responder.open(&id, &ciphertext) {
+                Some(P2PContent::Event(bytes)) => assert_eq!(bytes, vec![1, 2, 3]),
+                _ => panic!("open sealed event fail"),
+            },
+            _ => panic!("event not sealed"),
+        }
+
+        // replayed ciphertext is rejected, out of order in window is accepted
+        let sealeds: Vec<P2PContent> = (0..3)
+            .map(|i| initiator.seal(P2PContent::Event(vec![i])))
+            .collect();
+        let mut open = |sealed: &P2PContent| match sealed {
+            P2PContent::Sealed(id, ciphertext) => responder.open(id, ciphertext).is_some(),
+            _ => panic!("event not sealed"),
+        };
+        assert!(open(&sealeds[2]));
+        assert!(open(&sealeds[0]));
+        assert!(!open(&sealeds[0]));
+        assert!(!open(&sealeds[2]));
+        assert!(open(&sealeds[1]));
+    }
+
+    #[test]
+    fn test_replay_window() {
+        let mut window = ReplayWindow::default();
+        assert!(window.accept(1));
+        assert!(!window.accept(1));
+        assert!(window.accept(REPLAY_WINDOW + 1));
+        assert!(!window.accept(1));
+        assert!(window.accept(2));
+        assert!(!window.accept(2));
+        assert!(window.accept(REPLAY_WINDOW * 3));
+        assert!(!window.accept(REPLAY_WINDOW + 1));
+    }
+}
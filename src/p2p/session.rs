@@ -1,18 +1,29 @@
 use bytes::{BufMut, BytesMut};
 use futures::stream::SplitSink;
 use futures::Sink;
-use std::collections::HashMap;
-use std::net::SocketAddr;
+use futures::Stream;
+use std::collections::{HashMap, HashSet};
+use std::io::{Error, ErrorKind};
+use std::net::{IpAddr, SocketAddr, UdpSocket as StdUdpSocket};
+use std::time::Duration;
 use tokio::codec::BytesCodec;
-use tokio::net::UdpFramed;
+use tokio::net::{UdpFramed, UdpSocket};
+use tokio::reactor::Handle;
 
 use crate::actor::prelude::*;
+use crate::crypto::keypair::PublicKey;
 use crate::primitives::functions::{try_resend_times, DEFAULT_TIMES};
 use crate::traits::actor::P2PBridgeActor;
 
 use super::codec::{P2PBody, P2PHead, HEAD_LENGTH};
 use super::content::P2PContent;
+use super::fragment::{message_id, split, Reassembler};
+use super::limit::Pacer;
 use super::p2p::P2PActor;
+use super::transport::{P2PTransport, TransportType};
+
+/// udp send to one socket fail this times continuously, tell p2p actor.
+const MAX_SEND_FAILURES: u32 = 3;
 
 /// message between session and p2p actor.
 #[derive(Clone)]
@@ -30,6 +41,39 @@ impl Message for CodecMessage {
     type Result = ();
 }
 
+/// path mtu of peer socket, session will split bytes by it.
+#[derive(Clone)]
+pub(crate) struct P2PMtuMessage(pub SocketAddr, pub usize);
+
+impl Message for P2PMtuMessage {
+    type Result = ();
+}
+
+/// congestion signal of peer socket, true is acked, false is lost,
+/// session will change the pacing rate of the socket.
+#[derive(Clone)]
+pub(crate) struct P2PCongestionMessage(pub SocketAddr, pub bool);
+
+impl Message for P2PCongestionMessage {
+    type Result = ();
+}
+
+/// udp send to peer socket fail many times, peer maybe unreachable.
+#[derive(Clone)]
+pub(crate) struct P2PUnreachableMessage(pub SocketAddr);
+
+impl Message for P2PUnreachableMessage {
+    type Result = ();
+}
+
+/// banned peers and ips of tcp sockets, transport will ignore the message from them.
+#[derive(Clone)]
+pub(crate) struct P2PBanMessage(pub Vec<PublicKey>, pub Vec<IpAddr>);
+
+impl Message for P2PBanMessage {
+    type Result = ();
+}
+
 /// p2p addr message, need register to p2p session
 #[derive(Clone)]
 pub(crate) struct P2PAddrMessage<A: P2PBridgeActor>(pub Addr<P2PActor<A>>);
@@ -38,63 +82,302 @@ impl<A: P2PBridgeActor> Message for P2PAddrMessage<A> {
     type Result = ();
 }
 
+/// bind udp socket, return it and a dup of it, the dup is used to make new sink.
+pub(crate) fn bind_udp(socket: &SocketAddr) -> std::io::Result<(UdpSocket, StdUdpSocket)> {
+    let sock = StdUdpSocket::bind(socket)?;
+    let dup = sock.try_clone()?;
+    Ok((UdpSocket::from_std(sock, &Handle::default())?, dup))
+}
+
+/// make udp socket again from the dup, the dup still hold the address, not bind again.
+pub(crate) fn reopen_udp(dup: &StdUdpSocket) -> std::io::Result<UdpSocket> {
+    UdpSocket::from_std(dup.try_clone()?, &Handle::default())
+}
+
+/// kind of udp send error.
+#[derive(Debug, PartialEq)]
+enum SendError {
+    /// socket is busy, send again later.
+    Retry,
+    /// the destination can not reach, drop it.
+    Unreachable,
+    /// local socket is broken.
+    Fatal,
+}
+
+impl SendError {
+    fn classify(e: &Error) -> Self {
+        match e.kind() {
+            ErrorKind::WouldBlock | ErrorKind::Interrupted | ErrorKind::TimedOut => {
+                SendError::Retry
+            }
+            ErrorKind::ConnectionRefused
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::AddrNotAvailable
+            | ErrorKind::PermissionDenied
+            | ErrorKind::InvalidInput => SendError::Unreachable,
+            _ => SendError::Fatal,
+        }
+    }
+}
+
 pub struct P2PSessionActor<A: P2PBridgeActor> {
+    pub p2p_socket: SocketAddr,
+    pub stream: Option<SpawnHandle>,
     pub sinks: Vec<SplitSink<UdpFramed<BytesCodec>>>,
+    pub(crate) socket: StdUdpSocket,
     pub p2p_addr: Option<Addr<P2PActor<A>>>,
     pub waitings: Vec<(P2PHead, P2PBody, SocketAddr)>,
-    pub receivings: HashMap<[u8; 8], Vec<u8>>,
+    pub receivings: Reassembler,
+    pub fragment_size: usize,
+    pub mtus: HashMap<SocketAddr, usize>,
+    pub(crate) pacers: HashMap<SocketAddr, Pacer>,
+    pub(crate) failures: HashMap<SocketAddr, u32>,
+    pub banned: HashSet<PublicKey>,
 }
 
 impl<A: P2PBridgeActor> P2PSessionActor<A> {
+    /// send fragments to socket one by one.
     fn send_udp(
         &mut self,
-        mut bytes: Vec<u8>,
-        mut prev_sign: [u8; 8],
-        self_sign: [u8; 8],
+        mut fragments: Vec<Vec<u8>>,
         socket: SocketAddr,
         ctx: &mut Context<Self>,
     ) {
-        let mut send_bytes = vec![];
-        let (mut now, next, next_sign) = if bytes.len() > 65400 {
-            let now = bytes.drain(0..65400).as_slice().into();
-            (now, bytes, rand::random())
-        } else {
-            (bytes, vec![], self_sign.clone())
-        };
+        if fragments.is_empty() {
+            return;
+        }
 
-        send_bytes.extend_from_slice(&mut prev_sign);
-        send_bytes.extend_from_slice(&mut self_sign.clone());
-        send_bytes.extend_from_slice(&mut next_sign.clone());
-        send_bytes.append(&mut now);
+        let sink = match self.sinks.pop() {
+            Some(sink) => sink,
+            None => {
+                self.requeue(socket, fragments);
+                return;
+            }
+        };
+        let now = fragments.remove(0);
 
         let mut dst = BytesMut::new();
-        dst.reserve(send_bytes.len());
-        dst.put(send_bytes);
-        self.sinks.pop().and_then(|sink| {
-            let _ = sink
-                .send((dst.into(), socket.clone()))
-                .into_actor(self)
-                .then(move |res, act, ctx| {
-                    match res {
-                        Ok(sink) => {
-                            act.sinks.push(sink);
-                            if !next.is_empty() {
-                                act.send_udp(next, self_sign, next_sign, socket, ctx);
-                            }
-                        }
-                        Err(_) => panic!("DEBUG: NETWORK HAVE ERROR"),
+        dst.reserve(now.len());
+        dst.put(&now[..]);
+        let _ = sink
+            .send((dst.into(), socket.clone()))
+            .into_actor(self)
+            .then(move |res, act, ctx| {
+                match res {
+                    Ok(sink) => {
+                        act.sinks.push(sink);
+                        act.failures.remove(&socket);
+                        act.send_udp(fragments, socket, ctx);
                     }
+                    Err(e) => act.send_error(e, now, fragments, socket, ctx),
+                }
 
-                    actor_ok(())
-                })
-                .wait(ctx);
-            Some(())
+                actor_ok(())
+            })
+            .wait(ctx);
+    }
+
+    /// udp send fail, the failed send consumed the sink, so make a new sink,
+    /// only bind socket again when the socket is broken.
+    /// busy fragment will send again, unreachable or fatal fragment is dropped,
+    /// and the other fragments of unreachable message are dropped too,
+    /// unreachable many times, drop the queue and tell p2p actor.
+    fn send_error(
+        &mut self,
+        e: Error,
+        fragment: Vec<u8>,
+        mut fragments: Vec<Vec<u8>>,
+        socket: SocketAddr,
+        ctx: &mut Context<Self>,
+    ) {
+        match SendError::classify(&e) {
+            SendError::Retry => fragments.insert(0, fragment),
+            SendError::Unreachable => {
+                println!("DEBUG: udp send to {} fail: {}, unreachable", socket, e);
+                // the message can not reassembly without the fragment, drop others of it.
+                let id = message_id(&fragment);
+                fragments.retain(|f| message_id(f) != id);
+                if let Some(pacer) = self.pacers.get_mut(&socket) {
+                    pacer.drop_fragments(|f| message_id(f) == id);
+                }
+
+                let failures = self.failures.entry(socket).or_insert(0);
+                *failures += 1;
+                if *failures >= MAX_SEND_FAILURES {
+                    self.failures.remove(&socket);
+                    self.pacers.remove(&socket);
+                    fragments.clear();
+                    self.send_p2p(P2PUnreachableMessage(socket));
+                }
+            }
+            SendError::Fatal => {
+                println!("DEBUG: udp socket {} fail: {}", self.p2p_socket, e);
+                self.requeue(socket, fragments);
+                return self.rebind(ctx);
+            }
+        }
+
+        self.requeue(socket, fragments);
+        match self.new_sink() {
+            Ok(sink) => self.sinks.push(sink),
+            Err(e) => {
+                println!("DEBUG: udp new sink fail: {}", e);
+                self.rebind(ctx);
+            }
+        }
+    }
+
+    /// new sink of the same udp socket.
+    fn new_sink(&self) -> std::io::Result<SplitSink<UdpFramed<BytesCodec>>> {
+        let sock = UdpSocket::from_std(self.socket.try_clone()?, &Handle::default())?;
+        let (sink, _) = UdpFramed::new(sock, BytesCodec::new()).split();
+        Ok(sink)
+    }
+
+    /// put back the fragments to pacing queue.
+    fn requeue(&mut self, socket: SocketAddr, fragments: Vec<Vec<u8>>) {
+        if !fragments.is_empty() {
+            self.pacers
+                .entry(socket)
+                .or_insert(Pacer::new())
+                .requeue(fragments);
+        }
+    }
+
+    /// close the old udp stream and sinks, and make new ones from the kept socket.
+    fn rebind(&mut self, ctx: &mut Context<Self>) {
+        if let Some(handle) = self.stream.take() {
+            ctx.cancel_future(handle);
+        }
+
+        // wait the old stream closed
+        ctx.run_later(Duration::from_millis(100), |act, ctx| {
+            if !act.sinks.is_empty() {
+                return;
+            }
+
+            match reopen_udp(&act.socket) {
+                Ok(sock) => {
+                    let (sink, stream) = UdpFramed::new(sock, BytesCodec::new()).split();
+                    let handle =
+                        ctx.add_stream(stream.map(|(data, sender)| CodecMessage(data, sender)));
+                    act.stream = Some(handle);
+                    act.sinks.push(sink);
+                    println!("DEBUG: P2P rebind: {}", act.p2p_socket);
+                    act.flush_waitings(ctx);
+                }
+                Err(e) => {
+                    println!("DEBUG: P2P rebind: {} fail: {}", act.p2p_socket, e);
+                    act.rebind(ctx);
+                }
+            }
+        });
+    }
+
+    fn send_p2p<M: 'static>(&self, message: M)
+    where
+        P2PActor<A>: Handler<M>,
+        M: Message + Send + Clone,
+        <M as Message>::Result: Send,
+        <P2PActor<A> as Actor>::Context: ToEnvelope<P2PActor<A>, M>,
+    {
+        if let Some(addr) = self.p2p_addr.clone() {
+            let _ = try_resend_times(addr, message, DEFAULT_TIMES)
+                .map_err(|_| println!("Send Message to p2p fail"));
+        }
+    }
+
+    /// split waiting messages to fragments, and send by pacing.
+    fn flush_waitings(&mut self, ctx: &mut Context<Self>) {
+        while !self.waitings.is_empty() {
+            let w = self.waitings.remove(0);
+            if self.sinks.is_empty() {
+                self.waitings.push(w);
+                break;
+            }
+            let (mut head, body, socket) = (w.0, w.1, w.2);
+
+            let mut body_bytes: Vec<u8> = bincode::serialize(&body).unwrap_or(vec![]);
+            head.update_len(body_bytes.len() as u32);
+            let mut head_bytes = head.encode().to_vec();
+            let mut bytes = vec![];
+            bytes.append(&mut head_bytes);
+            bytes.append(&mut body_bytes);
+
+            // mtu probe must be one datagram
+            let fragment_size = match body.0 {
+                P2PContent::MtuProbe(_, _) => usize::max_value(),
+                _ => *self.mtus.get(&socket).unwrap_or(&self.fragment_size),
+            };
+
+            let pacer = self.pacers.entry(socket).or_insert(Pacer::new());
+            if !pacer.push(split(&bytes, fragment_size)) {
+                println!("DEBUG: drop message to {}, pacing queue is full", socket);
+                continue;
+            }
+            self.send_paced(socket, ctx);
+        }
+    }
+
+    /// send the fragments of socket which pacing allowed.
+    fn send_paced(&mut self, socket: SocketAddr, ctx: &mut Context<Self>) {
+        if self.sinks.is_empty() {
+            return;
+        }
+
+        let fragments = match self.pacers.get_mut(&socket) {
+            Some(pacer) => pacer.ready(),
+            None => return,
+        };
+
+        self.send_udp(fragments, socket, ctx);
+    }
+
+    /// Timed task, send the waiting fragments by pacing
+    fn pacing_hb(&self, ctx: &mut Context<Self>) {
+        ctx.run_later(Duration::from_millis(10), |act, ctx| {
+            let sockets: Vec<SocketAddr> = act
+                .pacers
+                .iter()
+                .filter(|(_, pacer)| !pacer.is_empty())
+                .map(|(socket, _)| *socket)
+                .collect();
+
+            for socket in sockets {
+                act.send_paced(socket, ctx);
+            }
+
+            act.pacing_hb(ctx);
+        });
+    }
+
+    /// Timed task, evict the timeout partial messages and idle pacers
+    fn evict_hb(&self, ctx: &mut Context<Self>) {
+        ctx.run_later(Duration::new(1, 0), |act, ctx| {
+            act.receivings.evict();
+            act.pacers.retain(|_, pacer| !pacer.is_idle());
+            let pacers = &act.pacers;
+            act.failures.retain(|socket, _| pacers.contains_key(socket));
+            act.evict_hb(ctx);
         });
     }
 }
 
 impl<A: P2PBridgeActor> Actor for P2PSessionActor<A> {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.evict_hb(ctx);
+        self.pacing_hb(ctx);
+    }
+}
+
+impl<A: P2PBridgeActor> P2PTransport<A> for P2PSessionActor<A> {
+    fn transport_type() -> TransportType {
+        TransportType::UDP
+    }
 }
 
 /// when receive P2PMessage, send it to that socket.
@@ -106,46 +389,55 @@ impl<A: P2PBridgeActor> Handler<P2PAddrMessage<A>> for P2PSessionActor<A> {
     }
 }
 
-/// when receive from upd stream, send to p2p actor to handle.
-impl<A: P2PBridgeActor> StreamHandler<CodecMessage, std::io::Error> for P2PSessionActor<A> {
-    fn handle(&mut self, msg: CodecMessage, _ctx: &mut Context<Self>) {
-        let (mut src, socket) = (msg.0, msg.1);
-        if src.len() < 16 {
-            return;
-        }
-        let (head_sign, new_data) = src.split_at_mut(24);
-
-        let (prev, me_next) = head_sign.split_at_mut(8);
-        let (me, next) = me_next.split_at_mut(8);
+/// when path mtu probed, update the fragment size of the socket.
+impl<A: P2PBridgeActor> Handler<P2PMtuMessage> for P2PSessionActor<A> {
+    type Result = ();
 
-        let mut prev_sign = [0u8; 8];
-        prev_sign.copy_from_slice(prev);
-        let mut sign = [0u8; 8];
-        sign.copy_from_slice(me);
-        let mut next_sign = [0u8; 8];
-        next_sign.copy_from_slice(next);
+    fn handle(&mut self, msg: P2PMtuMessage, _ctx: &mut Context<Self>) {
+        self.mtus.insert(msg.0, msg.1);
+    }
+}
 
-        let mut data = vec![];
+/// update the banned peers, udp socket is not banned, the source address can be forged.
+impl<A: P2PBridgeActor> Handler<P2PBanMessage> for P2PSessionActor<A> {
+    type Result = ();
 
-        if let Some(mut prev_data) = self.receivings.remove(&prev_sign) {
-            prev_sign = sign;
-            data.append(&mut prev_data);
-        }
+    fn handle(&mut self, msg: P2PBanMessage, _ctx: &mut Context<Self>) {
+        self.banned = msg.0.into_iter().collect();
+    }
+}
 
-        data.extend_from_slice(new_data);
+/// change the pacing rate of the socket, additive increase, multiplicative decrease.
+impl<A: P2PBridgeActor> Handler<P2PCongestionMessage> for P2PSessionActor<A> {
+    type Result = ();
 
-        if let Some(mut next_data) = self.receivings.remove(&next_sign) {
-            data.append(&mut next_data);
+    fn handle(&mut self, msg: P2PCongestionMessage, _ctx: &mut Context<Self>) {
+        if let Some(pacer) = self.pacers.get_mut(&msg.0) {
+            if msg.1 {
+                pacer.increase();
+            } else {
+                pacer.decrease();
+            }
         }
+    }
+}
 
-        let head = {
-            if data.len() < HEAD_LENGTH || prev_sign != sign {
-                self.receivings.insert(sign, data);
-                return;
-            }
-            P2PHead::decode(data.as_ref())
+/// when receive from upd stream, send to p2p actor to handle.
+impl<A: P2PBridgeActor> StreamHandler<CodecMessage, std::io::Error> for P2PSessionActor<A> {
+    fn handle(&mut self, msg: CodecMessage, _ctx: &mut Context<Self>) {
+        let (src, socket) = (msg.0, msg.1);
+        let mut data = match self.receivings.receive(socket, &src) {
+            Some(data) => data,
+            None => return,
         };
 
+        if data.len() < HEAD_LENGTH {
+            return;
+        }
+        let head = P2PHead::decode(data.as_ref());
+        if self.banned.contains(&head.from) {
+            return;
+        }
         let size = head.len as usize;
 
         if data.len() >= size + HEAD_LENGTH {
@@ -163,10 +455,14 @@ impl<A: P2PBridgeActor> StreamHandler<CodecMessage, std::io::Error> for P2PSessi
                     println!("Send Message to p2p fail");
                 });
             }
-        } else {
-            self.receivings.insert(sign, data);
         }
     }
+
+    /// udp receive error, maybe icmp error of sent datagram, keep receiving.
+    fn error(&mut self, err: std::io::Error, _ctx: &mut Self::Context) -> Running {
+        println!("DEBUG: udp receive error: {}", err);
+        Running::Continue
+    }
 }
 
 /// when receive P2PMessage, send it to that socket.
@@ -179,24 +475,55 @@ impl<A: P2PBridgeActor> Handler<P2PMessage> for P2PSessionActor<A> {
             return;
         }
 
-        while !self.waitings.is_empty() {
-            let w = self.waitings.remove(0);
-            if self.sinks.is_empty() {
-                self.waitings.push(w);
-                break;
-            }
-            let (mut head, body, socket) = (w.0, w.1, w.2);
-
-            let mut body_bytes: Vec<u8> = bincode::serialize(&body).unwrap_or(vec![]);
-            head.update_len(body_bytes.len() as u32);
-            let mut head_bytes = head.encode().to_vec();
-            let mut bytes = vec![];
-            bytes.append(&mut head_bytes);
-            bytes.append(&mut body_bytes);
+        self.flush_waitings(ctx);
+    }
+}
 
-            let sign: [u8; 8] = rand::random();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_send_error_classify() {
+        let classify = |kind| SendError::classify(&Error::new(kind, "test"));
+        assert_eq!(classify(ErrorKind::WouldBlock), SendError::Retry);
+        assert_eq!(classify(ErrorKind::Interrupted), SendError::Retry);
+        assert_eq!(
+            classify(ErrorKind::ConnectionRefused),
+            SendError::Unreachable
+        );
+        assert_eq!(
+            classify(ErrorKind::AddrNotAvailable),
+            SendError::Unreachable
+        );
+        assert_eq!(
+            classify(ErrorKind::PermissionDenied),
+            SendError::Unreachable
+        );
+        assert_eq!(classify(ErrorKind::BrokenPipe), SendError::Fatal);
+        assert_eq!(classify(ErrorKind::Other), SendError::Fatal);
+    }
 
-            self.send_udp(bytes, sign.clone(), sign, socket, ctx);
-        }
+    #[test]
+    fn test_rebind_receive() {
+        let (sock, dup) = bind_udp(&"127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = dup.local_addr().unwrap();
+
+        // fatal error close the stream and sinks, the dup still hold the address
+        drop(sock);
+        assert!(bind_udp(&addr).is_err());
+
+        let sock = reopen_udp(&dup).unwrap();
+        let (_, stream) = UdpFramed::new(sock, BytesCodec::new()).split();
+        let sender = StdUdpSocket::bind("127.0.0.1:0").unwrap();
+        sender.send_to(b"ping", addr).unwrap();
+
+        let received = tokio::runtime::current_thread::block_on_all(stream.into_future())
+            .map_err(|(e, _)| e)
+            .unwrap()
+            .0
+            .unwrap();
+        assert_eq!(&received.0[..], b"ping");
+        assert_eq!(received.1, sender.local_addr().unwrap());
     }
 }
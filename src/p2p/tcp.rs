@@ -0,0 +1,272 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Error;
+use std::net::{IpAddr, SocketAddr};
+use tokio::codec::FramedRead;
+use tokio::io::{AsyncRead, WriteHalf};
+use tokio::net::TcpStream;
+
+use crate::actor::prelude::*;
+use crate::crypto::keypair::PublicKey;
+use crate::primitives::consts::{HIGH_WATERMARK, LOW_WATERMARK};
+use crate::primitives::functions::{try_resend_times, DEFAULT_TIMES};
+use crate::traits::actor::P2PBridgeActor;
+
+use super::codec::{P2PBody, P2PCodec, P2PHead};
+use super::content::P2PContent;
+use super::p2p::P2PActor;
+use super::session::{P2PAddrMessage, P2PBanMessage, P2PMessage};
+use super::transport::{P2PTransport, TransportSocketMessage, TransportType};
+
+/// max messages waiting for connecting.
+const MAX_WAITINGS: usize = 256;
+
+/// tcp stream connected, from listener or connect to peer.
+pub(crate) struct TcpConnectMessage(pub TcpStream, pub SocketAddr);
+
+impl Message for TcpConnectMessage {
+    type Result = ();
+}
+
+/// connect to peer fail.
+#[derive(Clone)]
+pub(crate) struct TcpConnectFailMessage(pub SocketAddr);
+
+impl Message for TcpConnectFailMessage {
+    type Result = ();
+}
+
+/// tcp session open or close, send to transport actor.
+#[derive(Clone)]
+pub(crate) struct TcpSessionMessage<A: P2PBridgeActor>(
+    pub SocketAddr,
+    pub Option<Addr<TcpSessionActor<A>>>,
+);
+
+impl<A: P2PBridgeActor> Message for TcpSessionMessage<A> {
+    type Result = ();
+}
+
+/// tcp transport, it listen tcp socket, and keep one session with every peer socket.
+/// when send to the socket which not connected, it will connect first.
+pub struct TcpTransportActor<A: P2PBridgeActor> {
+    p2p_addr: Option<Addr<P2PActor<A>>>,
+    sessions: HashMap<SocketAddr, Addr<TcpSessionActor<A>>>,
+    waitings: HashMap<SocketAddr, Vec<P2PMessage>>,
+    banned: HashSet<PublicKey>,
+    banned_ips: HashSet<IpAddr>,
+}
+
+impl<A: P2PBridgeActor> TcpTransportActor<A> {
+    pub fn new() -> Self {
+        TcpTransportActor {
+            p2p_addr: None,
+            sessions: HashMap::new(),
+            waitings: HashMap::new(),
+            banned: HashSet::new(),
+            banned_ips: HashSet::new(),
+        }
+    }
+
+    fn send_p2p<M: 'static>(&self, message: M)
+    where
+        P2PActor<A>: Handler<M>,
+        M: Message + Send + Clone,
+        <M as Message>::Result: Send,
+        <P2PActor<A> as Actor>::Context: ToEnvelope<P2PActor<A>, M>,
+    {
+        if let Some(addr) = self.p2p_addr.clone() {
+            let _ = try_resend_times(addr, message, DEFAULT_TIMES)
+                .map_err(|_| println!("Send Message to p2p fail"));
+        }
+    }
+
+    fn connect(&self, socket: SocketAddr, ctx: &mut Context<Self>) {
+        let addr = ctx.address();
+        Arbiter::spawn(TcpStream::connect(&socket).then(move |res| {
+            match res {
+                Ok(stream) => addr.do_send(TcpConnectMessage(stream, socket)),
+                Err(e) => {
+                    println!("DEBUG: tcp connect {} fail: {}", socket, e);
+                    addr.do_send(TcpConnectFailMessage(socket));
+                }
+            }
+            futures::future::ok(())
+        }));
+    }
+}
+
+impl<A: P2PBridgeActor> Actor for TcpTransportActor<A> {
+    type Context = Context<Self>;
+}
+
+impl<A: P2PBridgeActor> P2PTransport<A> for TcpTransportActor<A> {
+    fn transport_type() -> TransportType {
+        TransportType::TCP
+    }
+}
+
+impl<A: P2PBridgeActor> Handler<P2PAddrMessage<A>> for TcpTransportActor<A> {
+    type Result = ();
+
+    fn handle(&mut self, msg: P2PAddrMessage<A>, _ctx: &mut Context<Self>) {
+        self.p2p_addr = Some(msg.0);
+    }
+}
+
+/// send message to the socket, if not connected, connect it.
+impl<A: P2PBridgeActor> Handler<P2PMessage> for TcpTransportActor<A> {
+    type Result = ();
+
+    fn handle(&mut self, msg: P2PMessage, ctx: &mut Context<Self>) {
+        let socket = msg.2;
+        if let Some(session) = self.sessions.get(&socket) {
+            session.do_send(msg);
+            return;
+        }
+
+        let need_connect = !self.waitings.contains_key(&socket);
+        let waitings = self.waitings.entry(socket).or_insert(vec![]);
+        if waitings.len() < MAX_WAITINGS {
+            waitings.push(msg);
+        }
+
+        if need_connect {
+            self.connect(socket, ctx);
+        }
+    }
+}
+
+impl<A: P2PBridgeActor> Handler<TcpConnectMessage> for TcpTransportActor<A> {
+    type Result = ();
+
+    fn handle(&mut self, msg: TcpConnectMessage, ctx: &mut Context<Self>) {
+        let (stream, socket) = (msg.0, msg.1);
+        if self.banned_ips.contains(&socket.ip()) {
+            println!("DEBUG: drop tcp connection from banned: {}", socket);
+            return;
+        }
+        let transport = ctx.address();
+        let p2p_addr = self.p2p_addr.clone();
+        let banned = self.banned.clone();
+
+        TcpSessionActor::create(move |ctx| {
+            let (r, w) = stream.split();
+            TcpSessionActor::add_stream(FramedRead::new(r, P2PCodec), ctx);
+            let mut write_frame = FramedWrite::new(w, P2PCodec, ctx);
+            write_frame.set_buffer_capacity(LOW_WATERMARK, HIGH_WATERMARK);
+            TcpSessionActor {
+                socket,
+                transport,
+                p2p_addr,
+                banned,
+                framed: write_frame,
+            }
+        });
+    }
+}
+
+/// update the banned peers and ips, and tell every session.
+impl<A: P2PBridgeActor> Handler<P2PBanMessage> for TcpTransportActor<A> {
+    type Result = ();
+
+    fn handle(&mut self, msg: P2PBanMessage, _ctx: &mut Context<Self>) {
+        self.banned = msg.0.iter().cloned().collect();
+        self.banned_ips = msg.1.iter().cloned().collect();
+        let banned_ips = &self.banned_ips;
+        self.waitings
+            .retain(|socket, _| !banned_ips.contains(&socket.ip()));
+        for session in self.sessions.values() {
+            session.do_send(msg.clone());
+        }
+    }
+}
+
+impl<A: P2PBridgeActor> Handler<TcpConnectFailMessage> for TcpTransportActor<A> {
+    type Result = ();
+
+    fn handle(&mut self, msg: TcpConnectFailMessage, _ctx: &mut Context<Self>) {
+        self.waitings.remove(&msg.0);
+    }
+}
+
+impl<A: P2PBridgeActor> Handler<TcpSessionMessage<A>> for TcpTransportActor<A> {
+    type Result = ();
+
+    fn handle(&mut self, msg: TcpSessionMessage<A>, _ctx: &mut Context<Self>) {
+        let (socket, session) = (msg.0, msg.1);
+        if let Some(session) = session {
+            if let Some(waitings) = self.waitings.remove(&socket) {
+                for message in waitings {
+                    session.do_send(message);
+                }
+            }
+            self.sessions.insert(socket, session);
+            self.send_p2p(TransportSocketMessage(socket, TransportType::TCP, true));
+        } else {
+            self.sessions.remove(&socket);
+            self.send_p2p(TransportSocketMessage(socket, TransportType::TCP, false));
+        }
+    }
+}
+
+/// one tcp connection with peer socket.
+pub(crate) struct TcpSessionActor<A: P2PBridgeActor> {
+    socket: SocketAddr,
+    transport: Addr<TcpTransportActor<A>>,
+    p2p_addr: Option<Addr<P2PActor<A>>>,
+    banned: HashSet<PublicKey>,
+    framed: FramedWrite<WriteHalf<TcpStream>, P2PCodec>,
+}
+
+impl<A: P2PBridgeActor> Actor for TcpSessionActor<A> {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.transport
+            .do_send(TcpSessionMessage(self.socket, Some(ctx.address())));
+    }
+
+    fn stopping(&mut self, _: &mut Self::Context) -> Running {
+        self.transport.do_send(TcpSessionMessage(self.socket, None));
+        Running::Stop
+    }
+}
+
+impl<A: P2PBridgeActor> WriteHandler<Error> for TcpSessionActor<A> {}
+
+/// when receive from tcp stream, send to p2p actor to handle.
+impl<A: P2PBridgeActor> StreamHandler<(P2PHead, P2PContent), Error> for TcpSessionActor<A> {
+    fn handle(&mut self, msg: (P2PHead, P2PContent), _ctx: &mut Self::Context) {
+        if self.banned.contains(&msg.0.from) {
+            return;
+        }
+
+        if let Some(addr) = self.p2p_addr.clone() {
+            let _ = try_resend_times(addr, P2PMessage(msg.0, msg.1, self.socket), DEFAULT_TIMES)
+                .map_err(|_| println!("Send Message to p2p fail"));
+        }
+    }
+}
+
+/// send message to the tcp stream.
+impl<A: P2PBridgeActor> Handler<P2PMessage> for TcpSessionActor<A> {
+    type Result = ();
+
+    fn handle(&mut self, msg: P2PMessage, _ctx: &mut Self::Context) {
+        self.framed.write((msg.0, P2PBody(msg.1)));
+    }
+}
+
+/// update the banned peers, if the ip of socket is banned, close the connection.
+impl<A: P2PBridgeActor> Handler<P2PBanMessage> for TcpSessionActor<A> {
+    type Result = ();
+
+    fn handle(&mut self, msg: P2PBanMessage, ctx: &mut Self::Context) {
+        if msg.1.contains(&self.socket.ip()) {
+            println!("DEBUG: close tcp connection of banned: {}", self.socket);
+            ctx.stop();
+            return;
+        }
+        self.banned = msg.0.into_iter().collect();
+    }
+}
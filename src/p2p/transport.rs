@@ -0,0 +1,69 @@
+use serde_derive::{Deserialize, Serialize};
+use std::net::SocketAddr;
+
+use crate::actor::prelude::*;
+use crate::traits::actor::P2PBridgeActor;
+
+use super::session::{P2PAddrMessage, P2PBanMessage, P2PMessage};
+
+/// transport type of p2p message.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportType {
+    UDP,
+    TCP,
+}
+
+/// p2p transport actor, it send P2PMessage to the socket, and send received
+/// P2PMessage to p2p actor which registered by P2PAddrMessage.
+pub(crate) trait P2PTransport<A: P2PBridgeActor>
+where
+    Self: Actor<Context = Context<Self>>
+        + Handler<P2PMessage>
+        + Handler<P2PAddrMessage<A>>
+        + Handler<P2PBanMessage>,
+{
+    fn transport_type() -> TransportType;
+}
+
+/// transport handle in p2p actor.
+#[derive(Clone)]
+pub(crate) struct Transport<A: P2PBridgeActor> {
+    sender: Recipient<P2PMessage>,
+    register: Recipient<P2PAddrMessage<A>>,
+    ban: Recipient<P2PBanMessage>,
+}
+
+impl<A: P2PBridgeActor> Transport<A> {
+    pub fn new<T: P2PTransport<A>>(addr: Addr<T>) -> (TransportType, Self) {
+        (
+            T::transport_type(),
+            Transport {
+                sender: addr.clone().recipient::<P2PMessage>(),
+                register: addr.clone().recipient::<P2PAddrMessage<A>>(),
+                ban: addr.recipient::<P2PBanMessage>(),
+            },
+        )
+    }
+
+    pub fn send(&self, message: P2PMessage) -> Result<(), ()> {
+        self.sender.do_send(message).map_err(|_| ())
+    }
+
+    pub fn register(&self, message: P2PAddrMessage<A>) -> Result<(), ()> {
+        self.register.do_send(message).map_err(|_| ())
+    }
+
+    pub fn ban(&self, message: P2PBanMessage) -> Result<(), ()> {
+        self.ban.do_send(message).map_err(|_| ())
+    }
+}
+
+/// connection transport open or close for socket, send to p2p actor,
+/// p2p actor will send message to the socket by this transport.
+#[derive(Clone)]
+pub(crate) struct TransportSocketMessage(pub SocketAddr, pub TransportType, pub bool);
+
+impl Message for TransportSocketMessage {
+    type Result = ();
+}
@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::crypto::keypair::PublicKey;
+
+/// lowest protocol version this node can speak.
+pub const MIN_VERSION: u16 = 1;
+
+/// highest protocol version this node can speak.
+pub const MAX_VERSION: u16 = 1;
+
+/// feature flags, peers only use the features both have.
+/// secure session and fragment are required by all versions, so not features.
+pub const FEATURE_RELIABLE: u32 = 1 << 1;
+pub const FEATURE_MTU_PROBE: u32 = 1 << 3;
+pub const FEATURE_TCP: u32 = 1 << 4;
+pub const FEATURE_RELAY: u32 = 1 << 5;
+
+/// negotiation not answered after this time, will send again.
+const NEGOTIATE_TIMEOUT: u64 = 10;
+
+/// rejected peer can negotiate again after this time, maybe it upgraded.
+const REJECT_TIME: u64 = 600;
+
+/// negotiate with remote version range, return highest common version
+/// and common features, or the reason why incompatible.
+pub fn negotiate(local: (u16, u16, u32), remote: (u16, u16, u32)) -> Result<(u16, u32), String> {
+    let (local_min, local_max, local_features) = local;
+    let (remote_min, remote_max, remote_features) = remote;
+
+    if remote_min > remote_max {
+        return Err(format!(
+            "invalid version range: {} - {}",
+            remote_min, remote_max
+        ));
+    }
+
+    if remote_max < local_min {
+        return Err(format!(
+            "version too old: {} - {}, need at least {}",
+            remote_min, remote_max, local_min
+        ));
+    }
+
+    if remote_min > local_max {
+        return Err(format!(
+            "version too new: {} - {}, support at most {}",
+            remote_min, remote_max, local_max
+        ));
+    }
+
+    Ok((
+        std::cmp::min(local_max, remote_max),
+        local_features & remote_features,
+    ))
+}
+
+/// negotiated versions with peers.
+#[derive(Clone)]
+pub(crate) struct PeerVersions {
+    features: u32,
+    agreed: HashMap<PublicKey, (u16, u32)>,
+    negotiatings: HashMap<PublicKey, (Instant, u64)>,
+    rejects: HashMap<PublicKey, Instant>,
+}
+
+impl PeerVersions {
+    pub fn new(features: u32) -> Self {
+        PeerVersions {
+            features,
+            agreed: HashMap::new(),
+            negotiatings: HashMap::new(),
+            rejects: HashMap::new(),
+        }
+    }
+
+    /// local version range and features.
+    pub fn local(&self) -> (u16, u16, u32) {
+        (MIN_VERSION, MAX_VERSION, self.features)
+    }
+
+    /// check head version can handle.
+    pub fn is_supported(&self, version: u16) -> bool {
+        version >= MIN_VERSION && version <= MAX_VERSION
+    }
+
+    /// negotiated version and features with peer.
+    pub fn get(&self, pk: &PublicKey) -> Option<(u16, u32)> {
+        self.agreed.get(pk).cloned()
+    }
+
+    /// version used in head when send to peer.
+    pub fn version(&self, pk: &PublicKey) -> u16 {
+        self.agreed
+            .get(pk)
+            .map(|(version, _)| *version)
+            .unwrap_or(MAX_VERSION)
+    }
+
+    /// the peer and self both have the feature.
+    pub fn has_feature(&self, pk: &PublicKey, feature: u32) -> bool {
+        self.agreed
+            .get(pk)
+            .map(|(_, features)| features & feature == feature)
+            .unwrap_or(false)
+    }
+
+    /// the peer negotiated, but not have the feature.
+    pub fn lack_feature(&self, pk: &PublicKey, feature: u32) -> bool {
+        self.agreed
+            .get(pk)
+            .map(|(_, features)| features & feature != feature)
+            .unwrap_or(false)
+    }
+
+    /// check if need send negotiation to peer, and mark it negotiating,
+    /// return the nonce of negotiation, reject must carry it.
+    pub fn need_negotiate(&mut self, pk: &PublicKey) -> Option<u64> {
+        if self.agreed.contains_key(pk) {
+            return None;
+        }
+
+        let now = Instant::now();
+        if let Some((ins, _)) = self.negotiatings.get(pk) {
+            if now.duration_since(*ins) < Duration::new(NEGOTIATE_TIMEOUT, 0) {
+                return None;
+            }
+        }
+
+        let nonce = rand::random();
+        self.negotiatings.insert(pk.clone(), (now, nonce));
+        Some(nonce)
+    }
+
+    /// check the reject is answer of our negotiation, not a replayed one.
+    pub fn is_negotiating(&self, pk: &PublicKey, nonce: u64) -> bool {
+        self.negotiatings
+            .get(pk)
+            .map(|(_, n)| *n == nonce)
+            .unwrap_or(false)
+    }
+
+    pub fn agree(&mut self, pk: PublicKey, version: u16, features: u32) {
+        self.negotiatings.remove(&pk);
+        self.rejects.remove(&pk);
+        self.agreed.insert(pk, (version, features));
+    }
+
+    pub fn reject(&mut self, pk: PublicKey) {
+        self.negotiatings.remove(&pk);
+        self.agreed.remove(&pk);
+        self.rejects.insert(pk, Instant::now());
+    }
+
+    /// check if peer is rejected recently.
+    pub fn is_rejected(&mut self, pk: &PublicKey) -> bool {
+        let expired = match self.rejects.get(pk) {
+            Some(ins) => Instant::now().duration_since(*ins) > Duration::new(REJECT_TIME, 0),
+            None => return false,
+        };
+
+        if expired {
+            self.rejects.remove(pk);
+        }
+        !expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::keypair::PrivateKey;
+
+    #[test]
+    fn test_negotiate() {
+        let all = FEATURE_RELIABLE | FEATURE_MTU_PROBE | FEATURE_TCP;
+        assert_eq!(
+            negotiate((1, 3, all), (2, 5, FEATURE_RELIABLE)),
+            Ok((3, FEATURE_RELIABLE))
+        );
+        assert_eq!(negotiate((1, 3, all), (1, 1, all)), Ok((1, all)));
+        assert!(negotiate((2, 3, all), (1, 1, all)).is_err());
+        assert!(negotiate((1, 3, all), (4, 5, all)).is_err());
+        assert!(negotiate((1, 3, all), (3, 2, all)).is_err());
+    }
+
+    #[test]
+    fn test_reject_nonce() {
+        let pk = PrivateKey::generate().generate_public_key();
+        let mut versions = PeerVersions::new(FEATURE_RELIABLE);
+        assert!(!versions.is_negotiating(&pk, 1));
+
+        let nonce = versions.need_negotiate(&pk).unwrap();
+        assert_eq!(versions.need_negotiate(&pk), None);
+        assert!(versions.is_negotiating(&pk, nonce));
+        assert!(!versions.is_negotiating(&pk, nonce.wrapping_add(1)));
+
+        versions.agree(pk.clone(), 1, 0);
+        assert!(!versions.is_negotiating(&pk, nonce));
+        assert!(versions.lack_feature(&pk, FEATURE_RELIABLE));
+    }
+}
@@ -3,5 +3,7 @@ pub const LOW_WATERMARK: usize = 200;
 pub const HIGH_WATERMARK: usize = 1 * 1024 * 1024 + 200; // 1MB + HEADER
 pub const DEFAULT_STORAGE_DIR_NAME: &'static str = ".tea";
 pub const P2P_CACHE_DIR_NAME: &'static str = "p2p_cache";
+pub const P2P_DEFAULT_FRAGMENT_SIZE: usize = 1200;
 pub const P2P_DEFAULT_SOCKET: &'static str = "0.0.0.0:7364";
+pub const P2P_DEFAULT_LAN_MULTICAST: &'static str = "239.255.70.77:7365";
 pub const RPC_DEFAULT_SOCKET: &'static str = "0.0.0.0:3030";
@@ -5,9 +5,16 @@ pub trait BridgeActor<R = Context<Self>>
 where
     Self: Actor<Context = R>
         + Handler<EventMessage>
+        + Handler<EventFailMessage>
+        + Handler<BroadcastMessage>
         + Handler<PeerJoinMessage>
         + Handler<PeerJoinResultMessage>
         + Handler<PeerLeaveMessage>
+        + Handler<PeerVersionMessage>
+        + Handler<DHTValueMessage>
+        + Handler<ProviderMessage>
+        + Handler<PeerMisbehaveMessage>
+        + Handler<BootstrappedMessage>
         + Handler<LocalMessage>
         + Handler<UpperMessage>
         + Handler<LowerMessage>
@@ -19,9 +26,16 @@ where
 
     R: ActorContext
         + ToEnvelope<Self, EventMessage>
+        + ToEnvelope<Self, EventFailMessage>
+        + ToEnvelope<Self, BroadcastMessage>
         + ToEnvelope<Self, PeerJoinMessage>
         + ToEnvelope<Self, PeerJoinResultMessage>
         + ToEnvelope<Self, PeerLeaveMessage>
+        + ToEnvelope<Self, PeerVersionMessage>
+        + ToEnvelope<Self, DHTValueMessage>
+        + ToEnvelope<Self, ProviderMessage>
+        + ToEnvelope<Self, PeerMisbehaveMessage>
+        + ToEnvelope<Self, BootstrappedMessage>
         + ToEnvelope<Self, LocalMessage>
         + ToEnvelope<Self, UpperMessage>
         + ToEnvelope<Self, LowerMessage>
@@ -6,13 +6,29 @@ where
     Self: Clone
         + Actor<Context = R>
         + Handler<ReceiveEventMessage>
+        + Handler<ReceiveReliableEventMessage>
+        + Handler<ReceiveEventFailMessage>
+        + Handler<ReceiveBroadcastMessage>
         + Handler<ReceivePeerJoinMessage>
         + Handler<ReceivePeerLeaveMessage>
-        + Handler<ReceivePeerJoinResultMessage>,
+        + Handler<ReceivePeerJoinResultMessage>
+        + Handler<ReceivePeerVersionMessage>
+        + Handler<ReceiveDHTValueMessage>
+        + Handler<ReceiveProviderMessage>
+        + Handler<ReceivePeerMisbehaveMessage>
+        + Handler<ReceiveBootstrappedMessage>,
     R: ActorContext
         + ToEnvelope<Self, ReceiveEventMessage>
+        + ToEnvelope<Self, ReceiveReliableEventMessage>
+        + ToEnvelope<Self, ReceiveEventFailMessage>
+        + ToEnvelope<Self, ReceiveBroadcastMessage>
         + ToEnvelope<Self, ReceivePeerJoinMessage>
         + ToEnvelope<Self, ReceivePeerLeaveMessage>
-        + ToEnvelope<Self, ReceivePeerJoinResultMessage>,
+        + ToEnvelope<Self, ReceivePeerJoinResultMessage>
+        + ToEnvelope<Self, ReceivePeerVersionMessage>
+        + ToEnvelope<Self, ReceiveDHTValueMessage>
+        + ToEnvelope<Self, ReceiveProviderMessage>
+        + ToEnvelope<Self, ReceivePeerMisbehaveMessage>
+        + ToEnvelope<Self, ReceiveBootstrappedMessage>,
 {
 }
@@ -1,6 +1,7 @@
 use std::net::SocketAddr;
 
 use crate::actor::prelude::{Addr, Message};
+use crate::crypto::hash::H256;
 use crate::primitives::types::{
     BlockByte, EventByte, EventID, GroupID, LevelPermissionByte, PeerAddr, PeerInfoByte, RPCParams,
 };
@@ -16,6 +17,111 @@ impl Message for EventMessage {
     type Result = ();
 }
 
+/// reliable event send to p2p network self group.
+/// Params is PeerAddr (p2p Node), Event Byte.
+#[derive(Clone)]
+pub struct ReliableEventMessage(pub GroupID, pub PeerAddr, pub EventByte);
+
+impl Message for ReliableEventMessage {
+    type Result = ();
+}
+
+/// reliable event send to p2p network fail.
+/// Params is PeerAddr (p2p Node), Event Byte.
+#[derive(Clone)]
+pub struct EventFailMessage(pub GroupID, pub PeerAddr, pub EventByte);
+
+impl Message for EventFailMessage {
+    type Result = ();
+}
+
+/// cancel the reliable events waiting to send to p2p network.
+/// Params is PeerAddr (p2p Node).
+#[derive(Clone)]
+pub struct EventCancelMessage(pub GroupID, pub PeerAddr);
+
+impl Message for EventCancelMessage {
+    type Result = ();
+}
+
+/// broadcast event to all peers in p2p network self group.
+/// Params is PeerAddr (origin p2p Node, ignored when send), Event Byte.
+#[derive(Clone)]
+pub struct BroadcastMessage(pub GroupID, pub PeerAddr, pub EventByte);
+
+impl Message for BroadcastMessage {
+    type Result = ();
+}
+
+/// peer negotiated protocol version from p2p network.
+/// send it to network to query the peer version.
+/// Params is PeerAddr (p2p Node), version, feature flags.
+#[derive(Clone)]
+pub struct PeerVersionMessage(pub GroupID, pub PeerAddr, pub u16, pub u32);
+
+impl Message for PeerVersionMessage {
+    type Result = ();
+}
+
+/// DHT key/value in p2p network.
+/// send it with value to put, without value to get, p2p network will answer the value.
+/// Params is key, value, ttl seconds.
+#[derive(Clone)]
+pub struct DHTValueMessage(pub GroupID, pub Vec<u8>, pub Option<Vec<u8>>, pub u32);
+
+impl Message for DHTValueMessage {
+    type Result = ();
+}
+
+/// content providers in p2p network.
+/// send it with true to announce self hold the content, with false to find the holders,
+/// p2p network will answer the holders, they are not verified,
+/// send PeerJoinMessage with the socket to connect.
+/// Params is content hash, bool (provide or find), holders PeerAddr and socket.
+#[derive(Clone)]
+pub struct ProviderMessage(
+    pub GroupID,
+    pub H256,
+    pub bool,
+    pub Vec<(PeerAddr, SocketAddr)>,
+);
+
+impl Message for ProviderMessage {
+    type Result = ();
+}
+
+/// peer misbehaviour in p2p network.
+/// send it to report the peer misbehave, penalty decrease the peer score,
+/// too low will be banned, if bool is true, ban it now.
+/// p2p network will send it when the peer is banned.
+/// Params is PeerAddr (p2p Node), penalty, bool (is banned).
+#[derive(Clone)]
+pub struct PeerMisbehaveMessage(pub GroupID, pub PeerAddr, pub u32, pub bool);
+
+impl Message for PeerMisbehaveMessage {
+    type Result = ();
+}
+
+/// bootstrap group in p2p network by the configured bootstrap peers.
+/// the configured groups are bootstrapped when start, send it with the join info,
+/// p2p network will send BootstrappedMessage when the group is bootstrapped.
+/// Params is Peer Join Info Byte.
+#[derive(Clone)]
+pub struct BootstrapMessage(pub GroupID, pub PeerInfoByte);
+
+impl Message for BootstrapMessage {
+    type Result = ();
+}
+
+/// group bootstrapped in p2p network.
+/// send it to query, p2p network will answer if the group is bootstrapped.
+#[derive(Clone)]
+pub struct BootstrappedMessage(pub GroupID);
+
+impl Message for BootstrappedMessage {
+    type Result = ();
+}
+
 /// peer join from p2p network.
 /// Params is PeerAddr (p2p Node), Peer Join Info Byte.
 #[derive(Clone)]
@@ -1,6 +1,7 @@
 use std::net::SocketAddr;
 
 use crate::actor::prelude::{Addr, Message};
+use crate::crypto::hash::H256;
 use crate::primitives::types::{EventByte, GroupID, PeerAddr, PeerInfoByte};
 
 use crate::traits::actor::P2PBridgeActor;
@@ -14,6 +15,114 @@ impl Message for ReceiveEventMessage {
     type Result = ();
 }
 
+/// reliable event message between p2p & bridge, it will retransmission
+/// until peer receive it, and peer will receive in order.
+/// Params peerAddr, Event Byte.
+#[derive(Clone)]
+pub struct ReceiveReliableEventMessage(pub GroupID, pub PeerAddr, pub EventByte);
+
+impl Message for ReceiveReliableEventMessage {
+    type Result = ();
+}
+
+/// reliable event send fail when retries run out, p2p send it to bridge.
+/// Params peerAddr, Event Byte.
+#[derive(Clone)]
+pub struct ReceiveEventFailMessage(pub GroupID, pub PeerAddr, pub EventByte);
+
+impl Message for ReceiveEventFailMessage {
+    type Result = ();
+}
+
+/// cancel the reliable events waiting to send to peer, bridge send it to p2p.
+/// Params peerAddr.
+#[derive(Clone)]
+pub struct ReceiveEventCancelMessage(pub GroupID, pub PeerAddr);
+
+impl Message for ReceiveEventCancelMessage {
+    type Result = ();
+}
+
+/// broadcast event to all peers in group between p2p & bridge.
+/// Params is PeerAddr (origin p2p Node, ignored when send), Event Byte.
+#[derive(Clone)]
+pub struct ReceiveBroadcastMessage(pub GroupID, pub PeerAddr, pub EventByte);
+
+impl Message for ReceiveBroadcastMessage {
+    type Result = ();
+}
+
+/// peer negotiated protocol version between p2p & bridge.
+/// when bridge send it to p2p, p2p will answer the peer version,
+/// if not negotiated, will negotiate first.
+/// Params is PeerAddr (p2p Node), version, feature flags.
+#[derive(Clone)]
+pub struct ReceivePeerVersionMessage(pub GroupID, pub PeerAddr, pub u16, pub u32);
+
+impl Message for ReceivePeerVersionMessage {
+    type Result = ();
+}
+
+/// DHT key/value between p2p & bridge.
+/// when bridge send it to p2p, value is some to put, none to get,
+/// p2p will answer the value found, none if not found.
+/// Params is key, value, ttl seconds.
+#[derive(Clone)]
+pub struct ReceiveDHTValueMessage(pub GroupID, pub Vec<u8>, pub Option<Vec<u8>>, pub u32);
+
+impl Message for ReceiveDHTValueMessage {
+    type Result = ();
+}
+
+/// content providers between p2p & bridge.
+/// when bridge send it to p2p, bool is true to announce self hold the content,
+/// false to find the holders, p2p will answer the holders found.
+/// Params is content hash, bool (provide or find), holders PeerAddr and socket.
+#[derive(Clone)]
+pub struct ReceiveProviderMessage(
+    pub GroupID,
+    pub H256,
+    pub bool,
+    pub Vec<(PeerAddr, SocketAddr)>,
+);
+
+impl Message for ReceiveProviderMessage {
+    type Result = ();
+}
+
+/// peer misbehaviour between p2p & bridge.
+/// when bridge send it to p2p, the peer score decrease by penalty, too low will be banned,
+/// if bool is true, ban it now. p2p send it to bridge when the peer is banned.
+/// Params is PeerAddr (p2p Node), penalty, bool (is banned).
+#[derive(Clone)]
+pub struct ReceivePeerMisbehaveMessage(pub GroupID, pub PeerAddr, pub u32, pub bool);
+
+impl Message for ReceivePeerMisbehaveMessage {
+    type Result = ();
+}
+
+/// bootstrap group from bridge to p2p.
+/// p2p will join the bootstrap peers with the join info,
+/// retry until the table has enough peers, and bootstrap again when peers too few.
+/// the configured groups are bootstrapped when start, send it to change the join info.
+/// Params is Peer Join Info Byte.
+#[derive(Clone)]
+pub struct ReceiveBootstrapMessage(pub GroupID, pub PeerInfoByte);
+
+impl Message for ReceiveBootstrapMessage {
+    type Result = ();
+}
+
+/// group bootstrapped between p2p & bridge.
+/// when bridge send it to p2p, p2p will answer if the group is bootstrapped.
+/// p2p send it to bridge when the group table has enough peers.
+#[derive(Clone)]
+pub struct ReceiveBootstrappedMessage(pub GroupID);
+
+impl Message for ReceiveBootstrappedMessage {
+    type Result = ();
+}
+
 /// receive peer join between p2p & bridge.
 /// Params is PeerAddr (p2p Node), Peer Join Info Byte.
 #[derive(Clone)]